@@ -0,0 +1,237 @@
+use std::borrow::Cow;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Direction {
+    LeftToRight,
+    RightToLeft,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum CharClass {
+    Strong(Direction),
+    Number,
+    Neutral,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_numeric() {
+        return CharClass::Number;
+    }
+    match c as u32 {
+        0x0590..=0x08FF
+        | 0xFB1D..=0xFDFF
+        | 0xFE70..=0xFEFF
+        | 0x10800..=0x10FFF
+        | 0x1E800..=0x1EFFF => CharClass::Strong(Direction::RightToLeft),
+        _ if c.is_alphabetic() => CharClass::Strong(Direction::LeftToRight),
+        _ => CharClass::Neutral,
+    }
+}
+
+fn mirrored(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '«' => '»',
+        '»' => '«',
+        _ => c,
+    }
+}
+
+/// Direction of the first strong character, left-to-right when there is none.
+pub fn paragraph_direction(text: &str) -> Direction {
+    text.chars()
+        .find_map(|c| match char_class(c) {
+            CharClass::Strong(direction) => Some(direction),
+            _ => None,
+        })
+        .unwrap_or(Direction::LeftToRight)
+}
+
+/// Characters of `line` in the order they are displayed from left to right.
+/// Right-to-left runs are reversed and their brackets mirrored. Lines without
+/// right-to-left characters are returned as they are.
+///
+/// This is a subset of the Unicode Bidirectional Algorithm covering Hebrew or
+/// Arabic mixed with left-to-right text and numbers. Explicit embeddings,
+/// isolates and Arabic number rules aren't supported.
+pub fn visual_order(line: &str) -> Cow<'_, str> {
+    let chars: Vec<char> = line.chars().collect();
+    let has_rtl = chars
+        .iter()
+        .any(|&c| char_class(c) == CharClass::Strong(Direction::RightToLeft));
+    if !has_rtl {
+        return Cow::Borrowed(line);
+    }
+
+    let levels = resolve_levels(&chars, paragraph_direction(line));
+    let mut order: Vec<usize> = (0..chars.len()).collect();
+    let max_level = levels.iter().cloned().max().unwrap_or(0);
+    let min_odd_level = levels
+        .iter()
+        .cloned()
+        .filter(|level| level % 2 == 1)
+        .min()
+        .unwrap_or(1);
+
+    // Reverse every run at the level or higher, from the highest level down.
+    for level in (min_odd_level..=max_level).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+        }
+    }
+
+    Cow::Owned(
+        order
+            .into_iter()
+            .map(|i| {
+                if levels[i] % 2 == 1 {
+                    mirrored(chars[i])
+                } else {
+                    chars[i]
+                }
+            })
+            .collect(),
+    )
+}
+
+// Embedding level of every character. Even levels are left-to-right.
+fn resolve_levels(chars: &[char], paragraph: Direction) -> Vec<u8> {
+    let base = match paragraph {
+        Direction::LeftToRight => 0,
+        Direction::RightToLeft => 1,
+    };
+    let level_of = |direction| match (paragraph, direction) {
+        (Direction::LeftToRight, Direction::LeftToRight) => 0,
+        (Direction::RightToLeft, Direction::LeftToRight) => 2,
+        (_, Direction::RightToLeft) => 1,
+    };
+
+    // Numbers follow preceding left-to-right text, otherwise they are
+    // displayed left-to-right inside the surrounding right-to-left run.
+    let mut last_strong = paragraph;
+    let mut directions: Vec<Option<Direction>> = Vec::with_capacity(chars.len());
+    let mut levels: Vec<u8> = Vec::with_capacity(chars.len());
+    for &c in chars {
+        match char_class(c) {
+            CharClass::Strong(direction) => {
+                last_strong = direction;
+                directions.push(Some(direction));
+                levels.push(level_of(direction));
+            }
+            CharClass::Number => {
+                directions.push(Some(last_strong));
+                levels.push(match last_strong {
+                    Direction::LeftToRight => level_of(Direction::LeftToRight),
+                    Direction::RightToLeft => 2,
+                });
+            }
+            CharClass::Neutral => {
+                directions.push(None);
+                levels.push(base);
+            }
+        }
+    }
+
+    // Neutrals between characters of the same direction take it, others
+    // take the paragraph direction. Trailing neutrals stay at the base level.
+    let mut i = 0;
+    while i < chars.len() {
+        if directions[i].is_some() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && directions[i].is_none() {
+            i += 1;
+        }
+        if i == chars.len() {
+            break;
+        }
+        let before = if start == 0 {
+            paragraph
+        } else {
+            directions[start - 1].unwrap_or(paragraph)
+        };
+        let after = directions[i].unwrap_or(paragraph);
+        let direction = if before == after { before } else { paragraph };
+        for level in &mut levels[start..i] {
+            *level = level_of(direction);
+        }
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(text: &str) -> Vec<u8> {
+        let chars: Vec<char> = text.chars().collect();
+        resolve_levels(&chars, paragraph_direction(text))
+    }
+
+    #[test]
+    fn pure_ltr_is_borrowed() {
+        assert_eq!(
+            paragraph_direction("hello, world 42"),
+            Direction::LeftToRight
+        );
+        assert!(matches!(visual_order("hello, world 42"), Cow::Borrowed(_)));
+        assert_eq!(levels("ab 1"), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn pure_rtl_is_reversed() {
+        assert_eq!(paragraph_direction("שלום עולם"), Direction::RightToLeft);
+        assert_eq!(levels("אב ג"), vec![1, 1, 1, 1]);
+        assert_eq!(visual_order("שלום עולם"), "םלוע םולש");
+    }
+
+    #[test]
+    fn neutrals_between_runs_take_paragraph_direction() {
+        assert_eq!(levels("ab אב cd"), vec![0, 0, 0, 1, 1, 0, 0, 0]);
+        assert_eq!(visual_order("abc אבג def"), "abc גבא def");
+        // Trailing neutrals stay at the base level.
+        assert_eq!(visual_order("abc אבג!"), "abc גבא!");
+    }
+
+    #[test]
+    fn digits_keep_left_to_right_order() {
+        assert_eq!(levels("א 12 ב"), vec![1, 1, 2, 2, 1, 1]);
+        assert_eq!(visual_order("אב 123 גד"), "דג 123 בא");
+        // Digits after left-to-right text join its run.
+        assert_eq!(levels("א ab 1"), vec![1, 1, 2, 2, 2, 2]);
+        assert_eq!(visual_order("א abc 12"), "abc 12 א");
+    }
+
+    #[test]
+    fn runs_are_reordered_visually() {
+        // Left-to-right runs inside a right-to-left paragraph keep their
+        // order, while the runs themselves are displayed right to left.
+        assert_eq!(visual_order("אב one שתיים two"), "two םייתש one בא");
+        assert_eq!(visual_order("one אב two גד three"), "one בא two דג three");
+    }
+
+    #[test]
+    fn brackets_are_mirrored_in_rtl_runs() {
+        assert_eq!(visual_order("א(ב)"), "(ב)א");
+        assert_eq!(visual_order("a (אב) b"), "a (בא) b");
+    }
+}
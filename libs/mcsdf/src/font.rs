@@ -1,17 +1,31 @@
-use super::geometry::{Curve, Line, Rect};
-use super::shape::{AllocatedShape, Segment, Shape};
+use super::bidi::visual_order;
+use super::geometry::{Curve, Line, Rect, Transform2D};
+use super::renderer::{DebugMode, MsdfParams};
+use super::shape::{AllocateShapeError, AllocatedShape, Segment, Shape};
+use super::shaper::{DefaultShaper, ShapedGlyph, Shaper};
 use super::texture::{Texture, TextureViewAllocator};
-use cgmath::Point2;
-use rusttype::{Contour, Scale, Segment as FontSegment};
+use cgmath::{EuclideanSpace, InnerSpace, Point2, Vector2};
+use rusttype::{point, Contour, GlyphId, Scale, Segment as FontSegment};
 use rusttype::{Error as RustTypeError, Font as RustTypeFont};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
 use std::iter::{once, FromIterator};
-use std::mem::replace;
+use std::mem::{replace, take};
+use std::ops::Range;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+// Smaller glyphs would have no pixels to render.
+const MIN_FONT_SIZE: f32 = 1.0;
+const ATLAS_CACHE_MAGIC: &[u8; 8] = b"MCSDFATL";
+const ATLAS_CACHE_VERSION: u32 = 3;
+
 #[derive(Debug)]
 pub enum FontError {
     CannotLoadFont,
+    InvalidAtlasCache,
+    Io(io::Error),
 }
 
 impl From<RustTypeError> for FontError {
@@ -20,6 +34,12 @@ impl From<RustTypeError> for FontError {
     }
 }
 
+impl From<io::Error> for FontError {
+    fn from(error: io::Error) -> Self {
+        FontError::Io(error)
+    }
+}
+
 struct GlyphInfo {
     texture_id: u32,
     texture_view: Rect<f32>,
@@ -29,6 +49,84 @@ pub struct GlyphLayout {
     pub texture_id: u32,
     pub screen_coord: Rect<f32>,
     pub texture_coord: Rect<f32>,
+    /// Applied to the corners of `screen_coord`, in the same units.
+    pub transform: Option<Transform2D>,
+}
+
+impl GlyphLayout {
+    /// Corners of the transformed quad: top left, top right, bottom left and
+    /// bottom right, matching corners of `texture_coord`.
+    pub fn screen_corners(&self) -> [Point2<f32>; 4] {
+        let scr = self.screen_coord;
+        let corners = [
+            Point2::new(scr.min.x, scr.max.y),
+            Point2::new(scr.max.x, scr.max.y),
+            Point2::new(scr.min.x, scr.min.y),
+            Point2::new(scr.max.x, scr.min.y),
+        ];
+        match self.transform {
+            Some(transform) => [
+                transform.transform_point(corners[0]),
+                transform.transform_point(corners[1]),
+                transform.transform_point(corners[2]),
+                transform.transform_point(corners[3]),
+            ],
+            None => corners,
+        }
+    }
+
+    /// Quad of the glyph as two triangles. `vertex` builds a vertex from a
+    /// screen corner and its texture coordinate. Vertices are ordered top
+    /// left, top right, bottom right and bottom left, indices start at
+    /// `first_index`.
+    pub fn quad<V, F: Fn(Point2<f32>, Point2<f32>) -> V>(
+        &self,
+        first_index: u16,
+        vertex: F,
+    ) -> ([V; 4], [u16; 6]) {
+        let [tl, tr, bl, br] = self.screen_corners();
+        let tex = self.texture_coord;
+        let vertices = [
+            vertex(tl, Point2::new(tex.min.x, tex.max.y)),
+            vertex(tr, Point2::new(tex.max.x, tex.max.y)),
+            vertex(br, Point2::new(tex.max.x, tex.min.y)),
+            vertex(bl, Point2::new(tex.min.x, tex.min.y)),
+        ];
+        let i = first_index;
+        (vertices, [i, i + 1, i + 2, i + 2, i + 3, i])
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GlyphPlacement {
+    pub texture_id: u32,
+    pub pixel_rect: Rect<u32>,
+    pub uv_rect: Rect<f32>,
+}
+
+pub struct LineInfo {
+    pub baseline_y: f32,
+    pub glyph_range: Range<usize>,
+    pub width: f32,
+    /// Pen advance of every glyph of the line, including whitespace and other
+    /// glyphs without an outline which `glyph_layouts` skips.
+    pub advances: Vec<f32>,
+}
+
+impl LineInfo {
+    /// Insertion index nearest to `x`, from 0 before the first glyph up to the
+    /// glyph count after the last one. Positions left of a glyph's midpoint
+    /// go before it, the others after it.
+    pub fn index_at_x(&self, x: f32) -> usize {
+        let mut pen = 0.0;
+        for (index, advance) in self.advances.iter().enumerate() {
+            if x < pen + advance / 2.0 {
+                return index;
+            }
+            pen += advance;
+        }
+        self.advances.len()
+    }
 }
 
 pub struct TextBlockLayout {
@@ -36,12 +134,27 @@ pub struct TextBlockLayout {
     pub shadow_size: u8,
     pub bounding_box: Rect<f32>,
     pub glyph_layouts: Vec<GlyphLayout>,
+    pub lines: Vec<LineInfo>,
+    /// Baseline of the last line. The first line's baseline is at zero.
+    pub baseline_y: f32,
+    /// Pen position after the last glyph, where a following run continues.
+    pub advance: f32,
+}
+
+impl TextBlockLayout {
+    /// Insertion index nearest to `x` on the first line. See
+    /// `LineInfo::index_at_x`, which also works for the other lines.
+    pub fn index_at_x(&self, x: f32) -> usize {
+        self.lines.first().map_or(0, |line| line.index_at_x(x))
+    }
 }
 
 pub struct TextureRenderBatch {
     pub texture_id: u32,
     pub texture: Arc<Mutex<Texture>>,
     pub allocated_shapes: Vec<AllocatedShape>,
+    /// When set, shapes should be rendered with `render_shape_debug`.
+    pub debug_mode: Option<DebugMode>,
 }
 
 struct TextureMetadata {
@@ -55,10 +168,18 @@ pub struct Font {
     free_texture_index: u32,
     texture_width: u32,
     texture_height: u32,
-    font_size: u8,
+    font_size: f32,
     shadow_size: u8,
     font: RustTypeFont<'static>,
-    glyphs: HashMap<char, Option<GlyphInfo>>,
+    font_hash: u64,
+    shaper: Box<dyn Shaper>,
+    kerning_enabled: bool,
+    variations: Vec<([u8; 4], f32)>,
+    simplify_tolerance: Option<f32>,
+    msdf_params: MsdfParams,
+    debug_mode: Option<DebugMode>,
+    fixed_advance: Option<f32>,
+    glyphs: HashMap<GlyphId, Option<GlyphInfo>>,
 }
 
 impl Font {
@@ -69,6 +190,7 @@ impl Font {
         shadow_size: u8,
         font_data: Vec<u8>,
     ) -> Result<Self, FontError> {
+        let font_hash = fnv1a(FNV_OFFSET_BASIS, &font_data);
         let font = RustTypeFont::from_bytes(font_data)?;
         let (texture, allocator) = Texture::new(texture_width, texture_height);
         let texture_metadatas = vec![TextureMetadata {
@@ -82,9 +204,17 @@ impl Font {
             free_texture_index: 0,
             texture_width,
             texture_height,
-            font_size,
+            font_size: f32::from(font_size),
             shadow_size,
             font,
+            font_hash,
+            shaper: Box::new(DefaultShaper::default()),
+            kerning_enabled: true,
+            variations: Vec::new(),
+            simplify_tolerance: None,
+            msdf_params: MsdfParams::default(),
+            debug_mode: None,
+            fixed_advance: None,
             glyphs: HashMap::new(),
         })
     }
@@ -102,38 +232,77 @@ impl Font {
         self.glyphs = HashMap::new();
     }
 
+    /// Replaces the shaper used by `layout_text_block`, which defaults to
+    /// `DefaultShaper`. Glyphs are cached by id, so the atlas stays valid.
+    pub fn set_shaper(&mut self, mut shaper: Box<dyn Shaper>) {
+        shaper.set_kerning_enabled(self.kerning_enabled);
+        self.shaper = shaper;
+    }
+
+    /// Toggles pair kerning, enabled by default. Disabling it keeps spacing
+    /// independent of kerning tables, e.g. for reproducible layouts.
+    pub fn set_kerning_enabled(&mut self, enabled: bool) {
+        self.kerning_enabled = enabled;
+        self.shaper.set_kerning_enabled(enabled);
+    }
+
+    pub fn is_kerning_enabled(&self) -> bool {
+        self.kerning_enabled
+    }
+
     pub fn allocate_glyph(&mut self, c: char) {
-        if self.glyphs.contains_key(&c) {
+        let glyph_id = self.font.glyph(c).id();
+        self.allocate_glyph_info(glyph_id);
+    }
+
+    /// Allocates a glyph by its id in the font, e.g. one produced by a shaper
+    /// which has no single char.
+    pub fn allocate_glyph_id(&mut self, glyph_id: u16) {
+        self.allocate_glyph_info(GlyphId(u32::from(glyph_id)));
+    }
+
+    fn allocate_glyph_info(&mut self, glyph_id: GlyphId) {
+        if self.glyphs.contains_key(&glyph_id) {
             return;
         }
 
-        let glyph = self.font.glyph(c);
+        let glyph = self.font.glyph(glyph_id);
+        let embolden = self.embolden();
         let allocated_shape =
-            if let Some(shape) = glyph.scaled(Scale::uniform(self.font_size as f32)).shape() {
+            if let Some(shape) = glyph.scaled(Scale::uniform(self.font_size)).shape() {
+                let mut new_texture = false;
                 loop {
                     let allocated_shape = {
                         let texture_allocator =
                             &mut self.texture_metadatas[self.free_texture_index as usize].allocator;
-                        AllocatedShape::new(
+                        AllocatedShape::with_options(
                             shape.as_slice().into(),
                             texture_allocator,
                             self.shadow_size as f32,
+                            embolden,
+                            self.simplify_tolerance,
+                            self.msdf_params,
                         )
                     };
 
-                    if let Some(s) = allocated_shape {
-                        break Some(s);
-                    } else {
-                        let (texture, allocator) =
-                            Texture::new(self.texture_width, self.texture_height);
+                    match allocated_shape {
+                        Ok(s) => break Some(s),
+                        Err(AllocateShapeError::Empty) => break None,
+                        // Glyph doesn't fit even into an empty texture.
+                        Err(AllocateShapeError::OutOfSpace) if new_texture => break None,
+                        Err(AllocateShapeError::OutOfSpace) => {
+                            let (texture, allocator) =
+                                Texture::new(self.texture_width, self.texture_height);
 
-                        self.texture_metadatas.push(TextureMetadata {
-                            texture: Arc::new(Mutex::new(texture)),
-                            allocated_shapes: Vec::new(),
-                            allocator,
-                        });
+                            self.texture_metadatas.push(TextureMetadata {
+                                texture: Arc::new(Mutex::new(texture)),
+                                allocated_shapes: Vec::new(),
+                                allocator,
+                            });
 
-                        self.free_texture_index += 1;
+                            self.free_texture_index += 1;
+                            new_texture = true;
+                        }
                     }
                 }
             } else {
@@ -159,11 +328,101 @@ impl Font {
             }
         });
 
-        self.glyphs.insert(c, glyph_info);
+        self.glyphs.insert(glyph_id, glyph_info);
+    }
+
+    /// Iterates over every allocated glyph with its place in the atlas. Glyphs
+    /// without an outline (e.g. space) have no placement. Keys are glyph ids
+    /// as glyphs are allocated after shaping.
+    pub fn iter_glyphs(&self) -> impl Iterator<Item = (GlyphId, Option<GlyphPlacement>)> + '_ {
+        let (width, height) = (self.texture_width as f32, self.texture_height as f32);
+        self.glyphs.iter().map(move |(glyph_id, glyph_info)| {
+            let placement = glyph_info.as_ref().map(|glyph_info| {
+                let uv_rect = glyph_info.texture_view;
+                GlyphPlacement {
+                    texture_id: glyph_info.texture_id,
+                    pixel_rect: Rect::new(
+                        (uv_rect.min.x * width).round() as u32,
+                        (uv_rect.min.y * height).round() as u32,
+                        (uv_rect.max.x * width).round() as u32,
+                        (uv_rect.max.y * height).round() as u32,
+                    ),
+                    uv_rect,
+                }
+            });
+            (*glyph_id, placement)
+        })
+    }
+
+    /// Copy of an atlas texture for inspecting how glyphs are packed. Free
+    /// space is tinted blue and every allocated glyph is outlined in red.
+    /// Returns `None` for unknown texture ids.
+    pub fn debug_atlas_image(&self, texture_id: u32) -> Option<Texture> {
+        let texture_metadata = self.texture_metadatas.get(texture_id as usize)?;
+        let (width, height, mut data) = {
+            let texture = texture_metadata.texture.lock().unwrap();
+            let data = texture.get_data().to_vec();
+            (texture.get_width(), texture.get_height(), data)
+        };
+        let index = |x: u32, y: u32| ((y * width + x) * 3) as usize;
+
+        for rect in texture_metadata.allocator.free_rects() {
+            for y in rect.min.y..rect.max.y.min(height) {
+                for x in rect.min.x..rect.max.x.min(width) {
+                    let i = index(x, y);
+                    data[i] /= 2;
+                    data[i + 1] /= 2;
+                    data[i + 2] = data[i + 2] / 2 + 96;
+                }
+            }
+        }
+
+        let placements = self
+            .iter_glyphs()
+            .filter_map(|(_, placement)| placement)
+            .filter(|placement| placement.texture_id == texture_id);
+        for placement in placements {
+            let rect = placement.pixel_rect;
+            let max_x = rect.max.x.min(width);
+            let max_y = rect.max.y.min(height);
+            if rect.min.x >= max_x || rect.min.y >= max_y {
+                continue;
+            }
+            for x in rect.min.x..max_x {
+                for &y in &[rect.min.y, max_y - 1] {
+                    data[index(x, y)..index(x, y) + 3].copy_from_slice(&[255, 0, 0]);
+                }
+            }
+            for y in rect.min.y..max_y {
+                for &x in &[rect.min.x, max_x - 1] {
+                    data[index(x, y)..index(x, y) + 3].copy_from_slice(&[255, 0, 0]);
+                }
+            }
+        }
+
+        let (texture, _) =
+            Texture::from_raw(width, height, data).expect("Cannot create debug atlas image");
+        Some(texture)
     }
 
     pub fn allocate_glyphs(&mut self, text: &str) {
-        text.chars().for_each(|c| self.allocate_glyph(c));
+        for line in text.split('\n') {
+            self.shape_line(line);
+        }
+    }
+
+    // Shapes a single line in visual order, see `bidi::visual_order`.
+    fn shape_visual_line(&self, line: &str) -> Vec<ShapedGlyph> {
+        self.shaper.shape(&self.font, &visual_order(line))
+    }
+
+    // Shapes a single line and allocates all of its glyphs.
+    fn shape_line(&mut self, line: &str) -> Vec<ShapedGlyph> {
+        let shaped_glyphs = self.shape_visual_line(line);
+        for shaped_glyph in &shaped_glyphs {
+            self.allocate_glyph_info(shaped_glyph.glyph_id);
+        }
+        shaped_glyphs
     }
 
     pub fn get_texture(&self, texture_id: u32) -> Arc<Mutex<Texture>> {
@@ -193,15 +452,106 @@ impl Font {
         self.invalidate();
     }
 
+    /// Returns the font size rounded to the nearest pixel size that fits in `u8`.
     pub fn get_font_size(&self) -> u8 {
-        self.font_size
+        self.font_size.round().min(255.0) as u8
     }
 
     pub fn set_font_size(&mut self, font_size: u8) {
-        self.font_size = font_size;
+        self.set_font_size_f32(f32::from(font_size));
+    }
+
+    pub fn get_font_size_f32(&self) -> f32 {
+        self.font_size
+    }
+
+    /// Sets a fractional font size, which also allows rasterizing glyphs above
+    /// 255 pixels for very large display sizes. Sizes are at least 1 pixel and
+    /// non-finite ones are ignored, keeping the current atlas.
+    pub fn set_font_size_f32(&mut self, font_size: f32) {
+        if !font_size.is_finite() {
+            return;
+        }
+        self.font_size = font_size.max(MIN_FONT_SIZE);
         self.invalidate();
     }
 
+    /// Sets a variation axis, e.g. `*b"wght"`, and invalidates the atlas as
+    /// every glyph depends on it. rusttype cannot instance variable fonts, so
+    /// `wght` falls back to synthetic emboldening of the default outlines
+    /// (400 is regular) and other axes are only stored.
+    pub fn set_variation(&mut self, axis_tag: [u8; 4], value: f32) {
+        match self.variations.iter_mut().find(|(tag, _)| *tag == axis_tag) {
+            Some((_, current)) if *current == value => return,
+            Some((_, current)) => *current = value,
+            None => self.variations.push((axis_tag, value)),
+        }
+        self.invalidate();
+    }
+
+    pub fn get_variation(&self, axis_tag: [u8; 4]) -> Option<f32> {
+        self.variations
+            .iter()
+            .find(|(tag, _)| *tag == axis_tag)
+            .map(|(_, value)| *value)
+    }
+
+    /// Enables contour simplification before rasterization, allowing the
+    /// outline to move by at most `tolerance` pixels. Fewer segments make
+    /// distance field rendering faster. Disabled by default.
+    pub fn set_simplify_tolerance(&mut self, tolerance: Option<f32>) {
+        if self.simplify_tolerance != tolerance {
+            self.simplify_tolerance = tolerance;
+            self.invalidate();
+        }
+    }
+
+    pub fn get_simplify_tolerance(&self) -> Option<f32> {
+        self.simplify_tolerance
+    }
+
+    /// Changes how glyph distance fields are generated, see `MsdfParams`.
+    pub fn set_msdf_params(&mut self, params: MsdfParams) {
+        if self.msdf_params != params {
+            self.msdf_params = params;
+            self.invalidate();
+        }
+    }
+
+    pub fn get_msdf_params(&self) -> MsdfParams {
+        self.msdf_params
+    }
+
+    /// Makes the atlas show a distance field visualization instead of the
+    /// distance field itself, see `DebugMode`. Text rendered from such an
+    /// atlas isn't expected to look right.
+    pub fn set_debug_mode(&mut self, debug_mode: Option<DebugMode>) {
+        if self.debug_mode != debug_mode {
+            self.debug_mode = debug_mode;
+            self.invalidate();
+        }
+    }
+
+    pub fn get_debug_mode(&self) -> Option<DebugMode> {
+        self.debug_mode
+    }
+
+    /// When set, every glyph is laid out centered in a cell of `advance` em
+    /// units instead of its own advance, e.g. for tabular content.
+    pub fn set_fixed_advance(&mut self, advance: Option<f32>) {
+        self.fixed_advance = advance;
+    }
+
+    pub fn get_fixed_advance(&self) -> Option<f32> {
+        self.fixed_advance
+    }
+
+    // Outline offset in pixels of the rasterized glyphs emulating the weight axis.
+    fn embolden(&self) -> f32 {
+        let weight = self.get_variation(*b"wght").unwrap_or(400.0);
+        (weight - 400.0) / 10_000.0 * self.font_size
+    }
+
     pub fn get_ascent(&self) -> f32 {
         let scale = Scale::uniform(1.0);
         let v_metrics = self.font.v_metrics(scale);
@@ -231,6 +581,7 @@ impl Font {
                     texture_id: texture_id as u32,
                     texture: texture_metadata.texture.clone(),
                     allocated_shapes,
+                    debug_mode: self.debug_mode,
                 })
             }
         }
@@ -238,75 +589,571 @@ impl Font {
         batches
     }
 
+    // Key identifying the atlas contents: font data and every setting which
+    // affects rasterization.
+    fn atlas_cache_key(&self) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET_BASIS, &self.font_hash.to_le_bytes());
+        hash = fnv1a(hash, &self.texture_width.to_le_bytes());
+        hash = fnv1a(hash, &self.texture_height.to_le_bytes());
+        hash = fnv1a(hash, &self.font_size.to_bits().to_le_bytes());
+        hash = fnv1a(hash, &[self.shadow_size]);
+        for (axis_tag, value) in &self.variations {
+            hash = fnv1a(hash, axis_tag);
+            hash = fnv1a(hash, &value.to_bits().to_le_bytes());
+        }
+        if let Some(tolerance) = self.simplify_tolerance {
+            hash = fnv1a(hash, &tolerance.to_bits().to_le_bytes());
+        }
+        if self.msdf_params != MsdfParams::default() {
+            hash = fnv1a(
+                hash,
+                &self.msdf_params.start_threshold.to_bits().to_le_bytes(),
+            );
+            hash = fnv1a(
+                hash,
+                &self.msdf_params.end_threshold.to_bits().to_le_bytes(),
+            );
+        }
+        if let Some(debug_mode) = self.debug_mode {
+            hash = fnv1a(hash, &[debug_mode as u8 + 1]);
+        }
+        hash
+    }
+
+    /// Writes all atlas textures and glyph placements to `path`. Glyphs still
+    /// waiting in render batches are saved blank, so call it once all batches
+    /// returned by `get_texture_render_batches` have been rendered.
+    pub fn save_cached_atlas<P: AsRef<Path>>(&self, path: P) -> Result<(), FontError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(ATLAS_CACHE_MAGIC)?;
+        write_u32(&mut writer, ATLAS_CACHE_VERSION)?;
+        write_u64(&mut writer, self.atlas_cache_key())?;
+        write_u32(&mut writer, self.texture_width)?;
+        write_u32(&mut writer, self.texture_height)?;
+        write_f32(&mut writer, self.font_size)?;
+        writer.write_all(&[self.shadow_size])?;
+        write_u32(&mut writer, self.free_texture_index)?;
+        write_u32(&mut writer, self.texture_metadatas.len() as u32)?;
+
+        for texture_metadata in &self.texture_metadatas {
+            let free_rects = texture_metadata.allocator.free_rects();
+            write_u32(&mut writer, free_rects.len() as u32)?;
+            for rect in free_rects {
+                write_u32(&mut writer, rect.min.x)?;
+                write_u32(&mut writer, rect.min.y)?;
+                write_u32(&mut writer, rect.max.x)?;
+                write_u32(&mut writer, rect.max.y)?;
+            }
+
+            let texture = texture_metadata.texture.lock().unwrap();
+            writer.write_all(texture.get_data())?;
+        }
+
+        write_u32(&mut writer, self.glyphs.len() as u32)?;
+        for (glyph_id, glyph_info) in &self.glyphs {
+            write_u32(&mut writer, glyph_id.0)?;
+            match glyph_info {
+                None => writer.write_all(&[0])?,
+                Some(glyph_info) => {
+                    writer.write_all(&[1])?;
+                    write_u32(&mut writer, glyph_info.texture_id)?;
+                    write_f32(&mut writer, glyph_info.texture_view.min.x)?;
+                    write_f32(&mut writer, glyph_info.texture_view.min.y)?;
+                    write_f32(&mut writer, glyph_info.texture_view.max.x)?;
+                    write_f32(&mut writer, glyph_info.texture_view.max.y)?;
+                }
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Replaces the atlas with one saved by `save_cached_atlas`. Returns
+    /// `false` and leaves the font untouched when the file is missing or was
+    /// produced for a different font or settings. Corrupted files fail with
+    /// `FontError::InvalidAtlasCache`, also leaving the font untouched, so the
+    /// atlas can be rendered and saved again. Loaded textures are not part of
+    /// any render batch, so they have to be uploaded by the caller.
+    pub fn load_cached_atlas<P: AsRef<Path>>(&mut self, path: P) -> Result<bool, FontError> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(error) => return Err(error.into()),
+        };
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        // Counts are checked against the bytes left before allocating for them.
+        let check_count = |reader: &mut BufReader<File>, count: u32, item_size: u64| {
+            let remaining = file_len.saturating_sub(reader.stream_position()?);
+            match u64::from(count).checked_mul(item_size) {
+                Some(size) if size <= remaining => Ok(()),
+                _ => Err(FontError::InvalidAtlasCache),
+            }
+        };
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != ATLAS_CACHE_MAGIC || read_u32(&mut reader)? != ATLAS_CACHE_VERSION {
+            return Err(FontError::InvalidAtlasCache);
+        }
+
+        if read_u64(&mut reader)? != self.atlas_cache_key() {
+            return Ok(false);
+        }
+
+        let width = read_u32(&mut reader)?;
+        let height = read_u32(&mut reader)?;
+        let font_size = read_f32(&mut reader)?;
+        let mut shadow_size = [0; 1];
+        reader.read_exact(&mut shadow_size)?;
+        if width != self.texture_width
+            || height != self.texture_height
+            || font_size.to_bits() != self.font_size.to_bits()
+            || shadow_size != [self.shadow_size]
+        {
+            return Ok(false);
+        }
+
+        let free_texture_index = read_u32(&mut reader)?;
+        let texture_count = read_u32(&mut reader)?;
+        if free_texture_index >= texture_count {
+            return Err(FontError::InvalidAtlasCache);
+        }
+        // Free rect count and pixels of every texture.
+        let texture_size = u64::from(width) * u64::from(height) * 3;
+        check_count(&mut reader, texture_count, 4 + texture_size)?;
+
+        let mut texture_metadatas = Vec::with_capacity(texture_count as usize);
+        for _ in 0..texture_count {
+            let free_rect_count = read_u32(&mut reader)?;
+            check_count(&mut reader, free_rect_count, 16)?;
+            let mut free_space = Vec::with_capacity(free_rect_count as usize);
+            for _ in 0..free_rect_count {
+                let rect = Rect::new(
+                    read_u32(&mut reader)?,
+                    read_u32(&mut reader)?,
+                    read_u32(&mut reader)?,
+                    read_u32(&mut reader)?,
+                );
+                if rect.min.x > rect.max.x
+                    || rect.min.y > rect.max.y
+                    || rect.max.x > width
+                    || rect.max.y > height
+                {
+                    return Err(FontError::InvalidAtlasCache);
+                }
+                free_space.push(rect);
+            }
+
+            let mut data = vec![0; texture_size as usize];
+            reader.read_exact(&mut data)?;
+
+            let (texture, allocator) = Texture::from_parts(width, height, data, free_space);
+            texture_metadatas.push(TextureMetadata {
+                texture: Arc::new(Mutex::new(texture)),
+                allocator,
+                allocated_shapes: Vec::new(),
+            });
+        }
+
+        let glyph_count = read_u32(&mut reader)?;
+        // Every glyph has at least an id and a flag.
+        check_count(&mut reader, glyph_count, 5)?;
+        let mut glyphs = HashMap::with_capacity(glyph_count as usize);
+        for _ in 0..glyph_count {
+            let glyph_id = GlyphId(read_u32(&mut reader)?);
+
+            let mut flag = [0; 1];
+            reader.read_exact(&mut flag)?;
+            let glyph_info = match flag[0] {
+                0 => None,
+                1 => {
+                    let texture_id = read_u32(&mut reader)?;
+                    if texture_id >= texture_count {
+                        return Err(FontError::InvalidAtlasCache);
+                    }
+                    let texture_view = Rect::new(
+                        read_f32(&mut reader)?,
+                        read_f32(&mut reader)?,
+                        read_f32(&mut reader)?,
+                        read_f32(&mut reader)?,
+                    );
+                    let in_texture = |v: f32| (0.0..=1.0).contains(&v);
+                    if !in_texture(texture_view.min.x)
+                        || !in_texture(texture_view.min.y)
+                        || !in_texture(texture_view.max.x)
+                        || !in_texture(texture_view.max.y)
+                        || texture_view.min.x > texture_view.max.x
+                        || texture_view.min.y > texture_view.max.y
+                    {
+                        return Err(FontError::InvalidAtlasCache);
+                    }
+                    Some(GlyphInfo {
+                        texture_id,
+                        texture_view,
+                    })
+                }
+                _ => return Err(FontError::InvalidAtlasCache),
+            };
+
+            glyphs.insert(glyph_id, glyph_info);
+        }
+
+        self.texture_metadatas = texture_metadatas;
+        self.free_texture_index = free_texture_index;
+        self.glyphs = glyphs;
+        Ok(true)
+    }
+
     pub fn layout_text_block(&mut self, text: &str) -> TextBlockLayout {
-        self.allocate_glyphs(text);
+        let shaped_lines: Vec<Vec<ShapedGlyph>> =
+            text.split('\n').map(|line| self.shape_line(line)).collect();
+        self.layout_shaped_lines(&shaped_lines)
+    }
+
+    /// Rasterizes `c` at `size` pixels as a plain 8-bit coverage bitmap,
+    /// independent of the atlas. Meant for comparing against rendered distance
+    /// fields. Rows go top to bottom. Returns `None` for glyphs without an
+    /// outline.
+    pub fn rasterize_coverage(&self, c: char, size: u32) -> Option<(Vec<u8>, u32, u32)> {
+        let glyph = self
+            .font
+            .glyph(c)
+            .scaled(Scale::uniform(size as f32))
+            .positioned(point(0.0, 0.0));
+        let bb = glyph.pixel_bounding_box()?;
+        let width = bb.width() as u32;
+        let height = bb.height() as u32;
+
+        let mut data = vec![0; (width * height) as usize];
+        glyph.draw(|x, y, coverage| {
+            data[(y * width + x) as usize] = (coverage * 255.0).round() as u8;
+        });
+        Some((data, width, height))
+    }
+
+    /// Bounding box of `text` as laid out by `layout_text_block`, in units of
+    /// the font size. Only metrics are used, no glyphs are allocated.
+    pub fn measure_text(&self, text: &str) -> Rect<f32> {
+        let scale = Scale::uniform(1.0);
+        let v_metrics = self.font.v_metrics(scale);
+        let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+        let mut bb: Rect<f32> = Rect::new(0.0, 0.0, 0.0, 0.0);
+
+        for (line_index, line) in text.split('\n').enumerate() {
+            let offset_y = -(line_index as f32) * line_height;
+            let mut offset_x = 0.0;
+            for shaped_glyph in self.shape_visual_line(line) {
+                let glyph = self.font.glyph(shaped_glyph.glyph_id).scaled(scale);
+                let (glyph_offset, glyph_advance) = self.glyph_advance(&shaped_glyph);
+                if let Some(glyph_bb) = glyph.exact_bounding_box() {
+                    let glyph_x = offset_x + glyph_offset;
+                    let glyph_y = offset_y + shaped_glyph.y_offset;
+                    bb.min.x = bb.min.x.min(glyph_x + glyph_bb.min.x);
+                    bb.min.y = bb.min.y.min(glyph_y - glyph_bb.max.y);
+                    bb.max.x = bb.max.x.max(glyph_x + glyph_bb.max.x);
+                    bb.max.y = bb.max.y.max(glyph_y - glyph_bb.min.y);
+                }
+                offset_x += glyph_advance;
+            }
+        }
+
+        bb
+    }
+
+    /// Largest size within `size_range` at which `text` fits into the box.
+    /// Text measurements scale linearly with the size, so it is computed
+    /// directly from `measure_text`.
+    pub fn fit_size(
+        &self,
+        text: &str,
+        max_width: f32,
+        max_height: f32,
+        size_range: (f32, f32),
+    ) -> f32 {
+        let bb = self.measure_text(text);
+        let mut size = size_range.1;
+        if bb.width() > 0.0 {
+            size = size.min(max_width / bb.width());
+        }
+        if bb.height() > 0.0 {
+            size = size.min(max_height / bb.height());
+        }
+        size.max(size_range.0)
+    }
+
+    /// Lays out every string of `texts` like `layout_text_block`, allocating
+    /// the union of their glyphs once. Returned layouts are independent.
+    pub fn layout_many(&mut self, texts: &[&str]) -> Vec<TextBlockLayout> {
+        let shaped_texts: Vec<Vec<Vec<ShapedGlyph>>> = texts
+            .iter()
+            .map(|text| {
+                text.split('\n')
+                    .map(|line| self.shape_visual_line(line))
+                    .collect()
+            })
+            .collect();
+
+        // Glyphs are allocated in order of appearance to keep packing deterministic.
+        let mut allocated = HashSet::new();
+        for shaped_glyph in shaped_texts.iter().flatten().flatten() {
+            if allocated.insert(shaped_glyph.glyph_id) {
+                self.allocate_glyph_info(shaped_glyph.glyph_id);
+            }
+        }
+
+        shaped_texts
+            .iter()
+            .map(|shaped_lines| self.layout_shaped_lines(shaped_lines))
+            .collect()
+    }
+
+    /// Lays out a single line of already shaped glyphs given as
+    /// `(glyph_id, x_advance, x_offset, y_offset)`, in units of the font size.
+    /// Glyphs are allocated as needed.
+    pub fn layout_glyphs(&mut self, glyph_ids: &[(u16, f32, f32, f32)]) -> TextBlockLayout {
+        let shaped_glyphs: Vec<ShapedGlyph> = glyph_ids
+            .iter()
+            .map(|&(glyph_id, x_advance, x_offset, y_offset)| ShapedGlyph {
+                glyph_id: GlyphId(u32::from(glyph_id)),
+                x_advance,
+                x_offset,
+                y_offset,
+            })
+            .collect();
+
+        for shaped_glyph in &shaped_glyphs {
+            self.allocate_glyph_info(shaped_glyph.glyph_id);
+        }
 
+        self.layout_shaped_lines(&[shaped_glyphs])
+    }
+
+    /// Lays out `text` as a single line following `curve`, given in units of the
+    /// font size. Glyph centers are placed by arc length along the curve and
+    /// rotated to its tangent with a per-glyph transform. Glyphs past the curve
+    /// end continue along its end tangent.
+    pub fn layout_text_on_curve(&mut self, text: &str, curve: Curve) -> TextBlockLayout {
+        let shaped_glyphs = self.shape_line(&text.replace('\n', " "));
+
+        // Arc length lookup table of the curve.
+        const SAMPLES: usize = 64;
+        let mut lengths = vec![0.0; SAMPLES + 1];
+        for i in 1..=SAMPLES {
+            let p0 = curve.point((i - 1) as f32 / SAMPLES as f32);
+            let p1 = curve.point(i as f32 / SAMPLES as f32);
+            lengths[i] = lengths[i - 1] + (p1 - p0).magnitude();
+        }
+        let total_length = lengths[SAMPLES];
+
+        // Point and tangent angle at the arc length `s`.
+        let point_at = |s: f32| {
+            if s >= total_length || total_length == 0.0 {
+                let tangent = curve.tangent(1.0);
+                let angle = tangent.y.atan2(tangent.x);
+                let overshoot = (s - total_length).max(0.0);
+                let p = curve.p2 + Vector2::new(angle.cos(), angle.sin()) * overshoot;
+                return (p, angle);
+            }
+            let s = s.max(0.0);
+            let i = lengths
+                .iter()
+                .position(|&length| length >= s)
+                .unwrap_or(SAMPLES)
+                .max(1);
+            let segment = lengths[i] - lengths[i - 1];
+            let fraction = if segment > 0.0 {
+                (s - lengths[i - 1]) / segment
+            } else {
+                0.0
+            };
+            let t = ((i - 1) as f32 + fraction) / SAMPLES as f32;
+            let tangent = curve.tangent(t);
+            (curve.point(t), tangent.y.atan2(tangent.x))
+        };
+
+        let shadow = (self.shadow_size as f32 + self.embolden().max(0.0)) / self.font_size;
+        let scale = Scale::uniform(1.0);
         let mut glyph_layouts = Vec::new();
+        let mut advances = Vec::with_capacity(shaped_glyphs.len());
+        let mut bb: Option<Rect<f32>> = None;
+        let mut offset_x = 0.0;
+
+        for shaped_glyph in &shaped_glyphs {
+            let glyph = self.font.glyph(shaped_glyph.glyph_id).scaled(scale);
+            let glyph_info = self.glyphs.get(&shaped_glyph.glyph_id).unwrap();
+            let (glyph_offset, glyph_advance) = self.glyph_advance(shaped_glyph);
+
+            if let Some(glyph_bb) = glyph.exact_bounding_box() {
+                // Glyph quad relative to the middle of its advance on the baseline.
+                let anchor_x = offset_x + glyph_advance / 2.0;
+                let min_x = offset_x + glyph_offset + glyph_bb.min.x - anchor_x;
+                let max_x = offset_x + glyph_offset + glyph_bb.max.x - anchor_x;
+                let min_y = shaped_glyph.y_offset - glyph_bb.max.y;
+                let max_y = shaped_glyph.y_offset - glyph_bb.min.y;
+
+                let (point, angle) = point_at(anchor_x);
+                let transform = Transform2D::rotation(angle, Point2::new(0.0, 0.0))
+                    .then(&Transform2D::translation(point.to_vec()));
+
+                let layout = GlyphLayout {
+                    texture_id: glyph_info.as_ref().map_or(0, |info| info.texture_id),
+                    screen_coord: Rect::new(min_x, min_y, max_x, max_y),
+                    texture_coord: glyph_info
+                        .as_ref()
+                        .map_or(Rect::new(0.0, 0.0, 0.0, 0.0), |info| info.texture_view),
+                    transform: Some(transform),
+                };
+
+                for corner in &layout.screen_corners() {
+                    bb = Some(match bb {
+                        Some(bb) => Rect::new(
+                            bb.min.x.min(corner.x),
+                            bb.min.y.min(corner.y),
+                            bb.max.x.max(corner.x),
+                            bb.max.y.max(corner.y),
+                        ),
+                        None => Rect::new(corner.x, corner.y, corner.x, corner.y),
+                    });
+                }
+
+                if glyph_info.is_some() {
+                    glyph_layouts.push(GlyphLayout {
+                        screen_coord: Rect::new(
+                            layout.screen_coord.min.x - shadow,
+                            layout.screen_coord.min.y - shadow,
+                            layout.screen_coord.max.x + shadow,
+                            layout.screen_coord.max.y + shadow,
+                        ),
+                        ..layout
+                    });
+                }
+            }
+
+            advances.push(glyph_advance);
+            offset_x += glyph_advance;
+        }
+
+        TextBlockLayout {
+            font_size: self.get_font_size(),
+            shadow_size: self.shadow_size,
+            bounding_box: bb.unwrap_or_else(|| Rect::new(0.0, 0.0, 0.0, 0.0)),
+            lines: vec![LineInfo {
+                baseline_y: 0.0,
+                glyph_range: 0..glyph_layouts.len(),
+                width: offset_x,
+                advances,
+            }],
+            glyph_layouts,
+            baseline_y: 0.0,
+            advance: offset_x,
+        }
+    }
+
+    // Horizontal offset of the glyph and the pen advance after it. Fixed advance
+    // centers the glyph in its cell and ignores kerning.
+    fn glyph_advance(&self, shaped_glyph: &ShapedGlyph) -> (f32, f32) {
+        match self.fixed_advance {
+            Some(advance) => {
+                let glyph = self.font.glyph(shaped_glyph.glyph_id);
+                let natural_advance = glyph.scaled(Scale::uniform(1.0)).h_metrics().advance_width;
+                (
+                    (advance - natural_advance) / 2.0 + shaped_glyph.x_offset,
+                    advance,
+                )
+            }
+            None => (shaped_glyph.x_offset, shaped_glyph.x_advance),
+        }
+    }
+
+    // Positions shaped lines below each other. Glyphs have to be allocated.
+    fn layout_shaped_lines(&self, shaped_lines: &[Vec<ShapedGlyph>]) -> TextBlockLayout {
+        let mut glyph_layouts = Vec::new();
+        let mut lines = Vec::new();
+        let mut line_start = 0;
+        let mut advances = Vec::new();
 
         let mut bb_min_x = 0.0;
         let mut bb_min_y = 0.0;
         let mut bb_max_x = 0.0;
         let mut bb_max_y = 0.0;
 
-        let shadow = self.shadow_size as f32 / self.font_size as f32;
+        // Quads cover the same padding around outlines as allocated shapes.
+        let shadow = (self.shadow_size as f32 + self.embolden().max(0.0)) / self.font_size;
         let scale = Scale::uniform(1.0);
         let v_metrics = self.font.v_metrics(scale);
 
-        let mut last_glyph = None;
         let mut offset_x = 0.0;
         let mut offset_y = 0.0;
 
-        for c in text.chars() {
-            if c == '\n' {
+        for (line_index, shaped_glyphs) in shaped_lines.iter().enumerate() {
+            if line_index > 0 {
+                lines.push(LineInfo {
+                    baseline_y: offset_y,
+                    glyph_range: line_start..glyph_layouts.len(),
+                    width: offset_x,
+                    advances: take(&mut advances),
+                });
+                line_start = glyph_layouts.len();
                 offset_x = 0.0;
-                last_glyph = None;
                 offset_y -= v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
-                continue;
             }
 
-            let glyph = self.font.glyph(c).scaled(scale);
-            let glyph_info = self.glyphs.get(&c).unwrap();
+            for shaped_glyph in shaped_glyphs {
+                let glyph = self.font.glyph(shaped_glyph.glyph_id).scaled(scale);
+                let glyph_info = self.glyphs.get(&shaped_glyph.glyph_id).unwrap();
+                let (glyph_offset, glyph_advance) = self.glyph_advance(shaped_glyph);
+                let glyph_x = offset_x + glyph_offset;
+                let glyph_y = offset_y + shaped_glyph.y_offset;
 
-            if let Some(last_glyph) = last_glyph {
-                offset_x += self.font.pair_kerning(scale, last_glyph, glyph.id());
-            }
+                if let Some(bb) = glyph.exact_bounding_box() {
+                    let min_x = glyph_x + bb.min.x;
+                    let min_y = glyph_y - bb.max.y;
+                    let max_x = glyph_x + bb.max.x;
+                    let max_y = glyph_y - bb.min.y;
 
-            let advance_width = glyph.h_metrics().advance_width;
+                    bb_min_x = min_x.min(bb_min_x);
+                    bb_min_y = min_y.min(bb_min_y);
+                    bb_max_x = max_x.max(bb_max_x);
+                    bb_max_y = max_y.max(bb_max_y);
 
-            if let Some(bb) = glyph.exact_bounding_box() {
-                let min_x = offset_x + bb.min.x;
-                let min_y = offset_y - bb.max.y;
-                let max_x = offset_x + bb.max.x;
-                let max_y = offset_y - bb.min.y;
-
-                bb_min_x = min_x.min(bb_min_x);
-                bb_min_y = min_y.min(bb_min_y);
-                bb_max_x = max_x.max(bb_max_x);
-                bb_max_y = max_y.max(bb_max_y);
-
-                if let Some(glyph_info) = glyph_info {
-                    glyph_layouts.push(GlyphLayout {
-                        texture_id: glyph_info.texture_id,
-                        screen_coord: Rect::new(
-                            min_x - shadow,
-                            min_y - shadow,
-                            max_x + shadow,
-                            max_y + shadow,
-                        ),
-                        texture_coord: glyph_info.texture_view,
-                    });
+                    if let Some(glyph_info) = glyph_info {
+                        glyph_layouts.push(GlyphLayout {
+                            texture_id: glyph_info.texture_id,
+                            screen_coord: Rect::new(
+                                min_x - shadow,
+                                min_y - shadow,
+                                max_x + shadow,
+                                max_y + shadow,
+                            ),
+                            texture_coord: glyph_info.texture_view,
+                            transform: None,
+                        });
+                    }
                 }
-            }
 
-            offset_x += advance_width;
-            last_glyph = Some(glyph.id());
+                advances.push(glyph_advance);
+                offset_x += glyph_advance;
+            }
         }
 
+        lines.push(LineInfo {
+            baseline_y: offset_y,
+            glyph_range: line_start..glyph_layouts.len(),
+            width: offset_x,
+            advances,
+        });
+
         TextBlockLayout {
-            font_size: self.font_size,
+            font_size: self.get_font_size(),
             shadow_size: self.shadow_size,
             bounding_box: Rect::new(bb_min_x, bb_min_y, bb_max_x, bb_max_y),
             glyph_layouts,
+            lines,
+            baseline_y: offset_y,
+            advance: offset_x,
         }
     }
 }
@@ -337,3 +1184,157 @@ impl<'a> From<&'a [Contour]> for Shape {
         Shape::from_iter(segments)
     }
 }
+
+// FNV-1a is used instead of std's hasher because its output has to stay
+// stable between builds for the on-disk atlas cache.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_f32<W: Write>(writer: &mut W, value: f32) -> io::Result<()> {
+    write_u32(writer, value.to_bits())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> io::Result<f32> {
+    read_u32(reader).map(f32::from_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Offset of the first texture's free rect count in a saved atlas.
+    const FREE_RECT_COUNT_OFFSET: usize = 41;
+
+    fn font() -> Font {
+        let data = include_bytes!("../../../assets/monserat.ttf").to_vec();
+        Font::new(64, 64, 24, 4, data).expect("Cannot load font")
+    }
+
+    // Saves an atlas, lets `corrupt` modify the file and loads it again.
+    fn load_corrupted<F: FnOnce(&mut Vec<u8>)>(name: &str, corrupt: F) -> Result<bool, FontError> {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("mcsdf-{}-{}.atlas", name, std::process::id()));
+        font().save_cached_atlas(&path).expect("Cannot save atlas");
+        let mut data = fs::read(&path).unwrap();
+        corrupt(&mut data);
+        fs::write(&path, &data).unwrap();
+        let result = font().load_cached_atlas(&path);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn write_u32_at(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn invalid_font_sizes_are_clamped_or_ignored() {
+        let mut font = font();
+        font.set_font_size_f32(12.5);
+        assert_eq!(font.get_font_size_f32(), 12.5);
+        for &size in &[f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            font.set_font_size_f32(size);
+            assert_eq!(font.get_font_size_f32(), 12.5);
+        }
+        for &size in &[0.0, -4.0, 0.25] {
+            font.set_font_size_f32(size);
+            assert_eq!(font.get_font_size_f32(), MIN_FONT_SIZE);
+        }
+        font.set_font_size(0);
+        assert_eq!(font.get_font_size(), 1);
+    }
+
+    #[test]
+    fn glyph_without_outline_has_no_placement() {
+        let mut font = font();
+        font.allocate_glyph(' ');
+        let glyphs: Vec<_> = font.iter_glyphs().collect();
+        assert_eq!(glyphs.len(), 1);
+        assert!(glyphs[0].1.is_none());
+        assert_eq!(font.texture_metadatas.len(), 1);
+    }
+
+    #[test]
+    fn oversized_glyph_is_out_of_space() {
+        let mut font = font();
+        font.set_font_size(200);
+        font.allocate_glyph('W');
+        let glyphs: Vec<_> = font.iter_glyphs().collect();
+        assert_eq!(glyphs.len(), 1);
+        assert!(glyphs[0].1.is_none());
+        // A single empty texture is tried before giving up.
+        assert_eq!(font.texture_metadatas.len(), 2);
+    }
+
+    #[test]
+    fn cached_atlas_round_trip() {
+        assert!(matches!(load_corrupted("valid", |_| {}), Ok(true)));
+    }
+
+    #[test]
+    fn cached_atlas_counts_are_checked() {
+        let result = load_corrupted("free-rects", |data| {
+            write_u32_at(data, FREE_RECT_COUNT_OFFSET, u32::MAX)
+        });
+        assert!(matches!(result, Err(FontError::InvalidAtlasCache)));
+
+        let result = load_corrupted("textures", |data| {
+            write_u32_at(data, FREE_RECT_COUNT_OFFSET - 4, u32::MAX)
+        });
+        assert!(matches!(result, Err(FontError::InvalidAtlasCache)));
+
+        // The glyph count is the last field of an atlas without glyphs.
+        let result = load_corrupted("glyphs", |data| {
+            let offset = data.len() - 4;
+            write_u32_at(data, offset, u32::MAX)
+        });
+        assert!(matches!(result, Err(FontError::InvalidAtlasCache)));
+    }
+
+    #[test]
+    fn cached_atlas_free_rects_are_checked() {
+        let rect = FREE_RECT_COUNT_OFFSET + 4;
+        // Min beyond max.
+        let result = load_corrupted("inverted-x", |data| {
+            write_u32_at(data, rect, 48);
+            write_u32_at(data, rect + 8, 16);
+        });
+        assert!(matches!(result, Err(FontError::InvalidAtlasCache)));
+        let result = load_corrupted("inverted-y", |data| {
+            write_u32_at(data, rect + 4, 48);
+            write_u32_at(data, rect + 12, 16);
+        });
+        assert!(matches!(result, Err(FontError::InvalidAtlasCache)));
+        // Outside of the texture.
+        let result = load_corrupted("outside", |data| write_u32_at(data, rect + 12, 65));
+        assert!(matches!(result, Err(FontError::InvalidAtlasCache)));
+    }
+}
@@ -1,8 +1,29 @@
-use super::math::solve_cubic;
+use super::math::{cos, sin, solve_cubic, sqrt};
+#[cfg(not(feature = "std"))]
+use super::vector::{dot, Matrix2, Point2, Vector2};
+#[cfg(feature = "std")]
 use cgmath::prelude::*;
-use cgmath::{dot, Point2, Vector2};
-use std::f32::MAX;
-use std::ops::Sub;
+#[cfg(feature = "std")]
+use cgmath::{dot, Matrix2, Point2, Vector2};
+use core::f32::MAX;
+use core::ops::Sub;
+
+// Vectors shorter than this, in shape units, have no reliable direction. Font
+// coordinates are a few hundred units at most, where f32 keeps about 1e-5.
+const DEGENERATE_LENGTH: f32 = 1e-4;
+
+// Same as `magnitude` and `normalize`, which use std directly.
+fn length(v: Vector2<f32>) -> f32 {
+    sqrt(v.magnitude2())
+}
+
+fn normalized(v: Vector2<f32>) -> Vector2<f32> {
+    v / length(v)
+}
+
+fn is_degenerate(v: Vector2<f32>) -> bool {
+    v.magnitude2() < DEGENERATE_LENGTH * DEGENERATE_LENGTH
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct SignedDistance {
@@ -37,6 +58,54 @@ impl<T: Sub<Output = T> + Copy> Rect<T> {
     }
 }
 
+impl<T: PartialOrd + Copy> Rect<T> {
+    /// Whether the rects share any area. Touching edges don't count.
+    pub fn intersects(&self, other: &Rect<T>) -> bool {
+        self.min.x < other.max.x
+            && other.min.x < self.max.x
+            && self.min.y < other.max.y
+            && other.min.y < self.max.y
+    }
+}
+
+/// Affine transform mapping `p` to `linear * p + translation`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform2D {
+    pub linear: Matrix2<f32>,
+    pub translation: Vector2<f32>,
+}
+
+impl Transform2D {
+    pub fn translation(offset: Vector2<f32>) -> Self {
+        Transform2D {
+            linear: Matrix2::identity(),
+            translation: offset,
+        }
+    }
+
+    /// Counter-clockwise rotation in radians around `center`.
+    pub fn rotation(angle: f32, center: Point2<f32>) -> Self {
+        let (sin, cos) = (sin(angle), cos(angle));
+        let linear = Matrix2::new(cos, sin, -sin, cos);
+        Transform2D {
+            linear,
+            translation: center.to_vec() - linear * center.to_vec(),
+        }
+    }
+
+    /// Transform applying `self` first and `other` second.
+    pub fn then(&self, other: &Transform2D) -> Self {
+        Transform2D {
+            linear: other.linear * self.linear,
+            translation: other.linear * self.translation + other.translation,
+        }
+    }
+
+    pub fn transform_point(&self, p: Point2<f32>) -> Point2<f32> {
+        Point2::from_vec(self.linear * p.to_vec() + self.translation)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Line {
     pub p0: Point2<f32>,
@@ -70,19 +139,32 @@ impl Line {
         let p1_p0 = self.p1 - self.p0;
         let p_p0 = p - self.p0;
 
+        // A line with (nearly) coincident points is a point without a direction.
+        if is_degenerate(p1_p0) {
+            let dist = length(p_p0);
+            return SignedDistance {
+                real_dist: dist,
+                real_pos: 0.0,
+                extended_dist: dist,
+                extended_pos: 0.0,
+                orthogonality: 0.0,
+                sign: 1.0,
+            };
+        }
+
         let extended_pos = dot(p_p0, p1_p0) / dot(p1_p0, p1_p0);
         let real_pos = extended_pos.max(0.0).min(1.0);
 
-        let extended_dist = (extended_pos * p1_p0 - p_p0).magnitude();
-        let real_dist = (real_pos * p1_p0 - p_p0).magnitude();
+        let extended_dist = length(extended_pos * p1_p0 - p_p0);
+        let real_dist = length(real_pos * p1_p0 - p_p0);
 
         let pt = self.p0 + real_pos * p1_p0;
         let p_pt = p - pt;
 
-        let orthogonality = if p_pt.x == 0.0 && p_pt.y == 0.0 {
+        let orthogonality = if is_degenerate(p_pt) {
             0.0
         } else {
-            p1_p0.normalize().perp_dot(p_pt.normalize())
+            normalized(p1_p0).perp_dot(normalized(p_pt))
         };
 
         let sign = orthogonality.signum();
@@ -161,6 +243,11 @@ impl Curve {
         );
     }
 
+    /// Derivative of the curve at `t`.
+    pub fn tangent(&self, t: f32) -> Vector2<f32> {
+        2.0 * (1.0 - t) * (self.p1 - self.p0) + 2.0 * t * (self.p2 - self.p1)
+    }
+
     pub fn area(&self) -> f32 {
         (self.p2.x * (-self.p0.y - 2.0 * self.p1.y)
             + 2.0 * self.p1.x * (self.p2.y - self.p0.y)
@@ -179,6 +266,14 @@ impl Curve {
         let v1 = p1 - p0;
         let v2 = p2 - 2.0 * p1 + p0;
 
+        // Without curvature, or with the control point on an end point, the
+        // curve traces the straight segment between its end points. Positions
+        // are then reported along that line. Nearly degenerate curves would
+        // make the cubic below ill-conditioned, so they are treated the same.
+        if is_degenerate(v1) || is_degenerate(v2) {
+            return Line::new(self.p0, self.p2).signed_distance(Point2::from_vec(p));
+        }
+
         let a = v2.dot(v2);
         let b = 3.0 * v1.dot(v2);
         let c = 2.0 * v1.dot(v1) - v2.dot(v);
@@ -220,18 +315,17 @@ impl Curve {
 
         let dir = 2.0 * real_pos * v2 + 2.0 * v1;
         let p_pt = p - dist_result.pt;
-        let orthogonality = if p_pt.is_zero() || dir.is_zero() {
+        let orthogonality = if is_degenerate(p_pt) || is_degenerate(dir) {
             0.0
         } else {
-            dir.normalize().perp_dot(p_pt.normalize())
+            normalized(dir).perp_dot(normalized(p_pt))
         };
 
         let sign = orthogonality.signum();
         let orthogonality = orthogonality.abs();
 
-        let real_dist = dist_result.dist2.sqrt();
-        let extended_dist =
-            (extended_pos * extended_pos * v2 + 2.0 * extended_pos * v1 - v).magnitude();
+        let real_dist = sqrt(dist_result.dist2);
+        let extended_dist = length(extended_pos * extended_pos * v2 + 2.0 * extended_pos * v1 - v);
 
         SignedDistance {
             real_dist,
@@ -243,3 +337,122 @@ impl Curve {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn assert_finite(distance: SignedDistance) {
+        for value in &[
+            distance.real_dist,
+            distance.real_pos,
+            distance.extended_dist,
+            distance.extended_pos,
+            distance.orthogonality,
+            distance.sign,
+        ] {
+            assert!(value.is_finite(), "{:?} isn't finite", distance);
+        }
+    }
+
+    #[test]
+    fn zero_length_line_is_a_point() {
+        let line = Line::new(Point2::new(1.0, 1.0), Point2::new(1.0, 1.0));
+        let distance = line.signed_distance(Point2::new(4.0, 5.0));
+        assert_finite(distance);
+        assert_near(distance.real_dist, 5.0);
+        assert_near(distance.extended_dist, 5.0);
+        assert_near(distance.orthogonality, 0.0);
+
+        // Not exactly coincident points are treated the same.
+        let line = Line::new(Point2::new(1.0, 1.0), Point2::new(1.0 + 1e-6, 1.0));
+        let distance = line.signed_distance(Point2::new(4.0, 5.0));
+        assert_finite(distance);
+        assert_near(distance.real_dist, 5.0);
+    }
+
+    #[test]
+    fn line_distance_and_sign() {
+        let line = Line::new(Point2::new(0.0, 0.0), Point2::new(2.0, 0.0));
+        let left = line.signed_distance(Point2::new(1.0, 1.0));
+        assert_near(left.real_dist, 1.0);
+        assert_near(left.real_pos, 0.5);
+        assert_near(left.sign, 1.0);
+        let right = line.signed_distance(Point2::new(3.0, -1.0));
+        assert_near(right.real_dist, 2f32.sqrt());
+        assert_near(right.extended_dist, 1.0);
+        assert_near(right.extended_pos, 1.5);
+        assert_near(right.sign, -1.0);
+    }
+
+    #[test]
+    fn collapsed_curve_is_a_point() {
+        let point = Point2::new(2.0, 2.0);
+        let curve = Curve::new(point, point, point);
+        let distance = curve.signed_distance(Point2::new(5.0, 6.0));
+        assert_finite(distance);
+        assert_near(distance.real_dist, 5.0);
+    }
+
+    #[test]
+    fn straight_curves_are_lines() {
+        // Control point in the middle, so there is no curvature.
+        let curve = Curve::new(
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 0.0),
+            Point2::new(2.0, 0.0),
+        );
+        let distance = curve.signed_distance(Point2::new(1.0, 1.0));
+        assert_finite(distance);
+        assert_near(distance.real_dist, 1.0);
+        assert_near(distance.real_pos, 0.5);
+        assert_near(distance.sign, 1.0);
+
+        // Control point on an end point.
+        let curve = Curve::new(
+            Point2::new(0.0, 0.0),
+            Point2::new(0.0, 0.0),
+            Point2::new(2.0, 0.0),
+        );
+        let distance = curve.signed_distance(Point2::new(1.0, -1.0));
+        assert_finite(distance);
+        assert_near(distance.real_dist, 1.0);
+        assert_near(distance.sign, -1.0);
+
+        // Almost no curvature.
+        let curve = Curve::new(
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 1e-6),
+            Point2::new(2.0, 0.0),
+        );
+        let distance = curve.signed_distance(Point2::new(1.0, 1.0));
+        assert_finite(distance);
+        assert_near(distance.real_dist, 1.0);
+    }
+
+    #[test]
+    fn curve_distance() {
+        let curve = Curve::new(
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 2.0),
+            Point2::new(2.0, 0.0),
+        );
+        // The apex is at (1, 1).
+        let outside = curve.signed_distance(Point2::new(1.0, 3.0));
+        assert_finite(outside);
+        assert_near(outside.real_dist, 2.0);
+        assert_near(outside.real_pos, 0.5);
+        let inside = curve.signed_distance(Point2::new(1.0, 0.75));
+        assert_near(inside.real_dist, 0.25);
+        assert!(inside.sign != outside.sign);
+    }
+}
@@ -0,0 +1,217 @@
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+// Characters which never start a cluster and stick to the previous one.
+fn is_extend(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x0591..=0x05BD
+        | 0x05BF
+        | 0x05C1..=0x05C2
+        | 0x05C4..=0x05C5
+        | 0x05C7
+        | 0x0610..=0x061A
+        | 0x064B..=0x065F
+        | 0x0670
+        | 0x06D6..=0x06DC
+        | 0x06DF..=0x06E4
+        | 0x06E7..=0x06E8
+        | 0x06EA..=0x06ED
+        | 0x0900..=0x0903
+        | 0x093A..=0x094F
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200C..=0x200D
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0x1F3FB..=0x1F3FF
+        | 0xE0020..=0xE007F
+        | 0xE0100..=0xE01EF
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Hangul {
+    Leading,
+    Vowel,
+    Trailing,
+    // Precomposed syllable without a trailing consonant.
+    Lv,
+    // Precomposed syllable with a trailing consonant.
+    Lvt,
+}
+
+fn hangul(c: char) -> Option<Hangul> {
+    match c as u32 {
+        0x1100..=0x115F | 0xA960..=0xA97C => Some(Hangul::Leading),
+        0x1160..=0x11A7 | 0xD7B0..=0xD7C6 => Some(Hangul::Vowel),
+        0x11A8..=0x11FF | 0xD7CB..=0xD7FB => Some(Hangul::Trailing),
+        c @ 0xAC00..=0xD7A3 if (c - 0xAC00) % 28 == 0 => Some(Hangul::Lv),
+        0xAC00..=0xD7A3 => Some(Hangul::Lvt),
+        _ => None,
+    }
+}
+
+// Whether jamo or syllables `before` and `after` belong to one syllable.
+fn joins_hangul(before: char, after: char) -> bool {
+    use self::Hangul::*;
+    matches!(
+        (hangul(before), hangul(after)),
+        (Some(Leading), Some(Leading | Vowel | Lv | Lvt))
+            | (Some(Vowel | Lv), Some(Vowel | Trailing))
+            | (Some(Trailing | Lvt), Some(Trailing))
+    )
+}
+
+/// Whether a user-perceived character (grapheme cluster) starts at char
+/// index `index` of `chars`. Both ends of the text are boundaries.
+///
+/// This is a subset of the Unicode text segmentation rules covering CR LF,
+/// combining marks, variation selectors, emoji modifiers, ZWJ sequences,
+/// flags and Hangul syllables. Indic conjunct rules aren't supported.
+pub fn is_grapheme_boundary(chars: &[char], index: usize) -> bool {
+    if index == 0 || index >= chars.len() {
+        return true;
+    }
+    let (before, after) = (chars[index - 1], chars[index]);
+    if before == '\r' && after == '\n' {
+        return false;
+    }
+    if before.is_control() || after.is_control() {
+        return true;
+    }
+    if joins_hangul(before, after) || is_extend(after) || before == ZERO_WIDTH_JOINER {
+        return false;
+    }
+    if is_regional_indicator(before) && is_regional_indicator(after) {
+        // Flags pair indicators from the start of the run.
+        let run = chars[..index]
+            .iter()
+            .rev()
+            .take_while(|&&c| is_regional_indicator(c))
+            .count();
+        return run % 2 == 0;
+    }
+    true
+}
+
+/// Char index of the cluster boundary before `index`, or 0.
+pub fn previous_grapheme_boundary(chars: &[char], index: usize) -> usize {
+    let mut index = index.min(chars.len());
+    while index > 0 {
+        index -= 1;
+        if is_grapheme_boundary(chars, index) {
+            break;
+        }
+    }
+    index
+}
+
+/// Char index of the cluster boundary after `index`, or the text length.
+pub fn next_grapheme_boundary(chars: &[char], index: usize) -> usize {
+    let mut index = index;
+    while index < chars.len() {
+        index += 1;
+        if is_grapheme_boundary(chars, index) {
+            break;
+        }
+    }
+    index.min(chars.len())
+}
+
+/// Number of user-perceived characters in `text`.
+pub fn grapheme_count(text: &str) -> usize {
+    let chars: Vec<char> = text.chars().collect();
+    (0..chars.len())
+        .filter(|&index| is_grapheme_boundary(&chars, index))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clusters(text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut clusters = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let end = next_grapheme_boundary(&chars, start);
+            clusters.push(chars[start..end].iter().collect());
+            start = end;
+        }
+        clusters
+    }
+
+    #[test]
+    fn combining_marks_stick_to_base() {
+        assert_eq!(clusters("e\u{301}a"), vec!["e\u{301}", "a"]);
+        assert_eq!(clusters("a\u{308}\u{323}"), vec!["a\u{308}\u{323}"]);
+        assert_eq!(grapheme_count("cafe\u{301}"), 4);
+    }
+
+    #[test]
+    fn crlf_is_one_cluster() {
+        assert_eq!(clusters("a\r\nb"), vec!["a", "\r\n", "b"]);
+        assert_eq!(clusters("\n\r"), vec!["\n", "\r"]);
+        // Marks don't attach to controls.
+        assert_eq!(clusters("\n\u{301}"), vec!["\n", "\u{301}"]);
+    }
+
+    #[test]
+    fn zwj_sequences_are_one_cluster() {
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(clusters(family), vec![family]);
+        let waving = "\u{1F44B}\u{1F3FD}";
+        assert_eq!(clusters(&format!("{}x", waving)), vec![waving, "x"]);
+        assert_eq!(grapheme_count(&format!("a{}b", family)), 3);
+    }
+
+    #[test]
+    fn regional_indicators_pair_up() {
+        let poland = "\u{1F1F5}\u{1F1F1}";
+        let japan = "\u{1F1EF}\u{1F1F5}";
+        assert_eq!(
+            clusters(&format!("{}{}", poland, japan)),
+            vec![poland, japan]
+        );
+        // An odd indicator is left alone.
+        assert_eq!(
+            clusters(&format!("{}\u{1F1EF}", poland)),
+            vec![poland, "\u{1F1EF}"]
+        );
+    }
+
+    #[test]
+    fn hangul_syllables() {
+        // Precomposed syllables are clusters of their own.
+        assert_eq!(clusters("\u{D55C}\u{AD6D}"), vec!["\u{D55C}", "\u{AD6D}"]);
+        // Conjoining jamo L V T form one syllable.
+        assert_eq!(
+            clusters("\u{1112}\u{1161}\u{11AB}\u{1100}"),
+            vec!["\u{1112}\u{1161}\u{11AB}", "\u{1100}"]
+        );
+        // An LV syllable takes a trailing consonant, an LVT one doesn't take a vowel.
+        assert_eq!(clusters("\u{D558}\u{11AB}"), vec!["\u{D558}\u{11AB}"]);
+        assert_eq!(clusters("\u{D55C}\u{1161}"), vec!["\u{D55C}", "\u{1161}"]);
+    }
+
+    #[test]
+    fn boundaries_move_by_cluster() {
+        let chars: Vec<char> = "ae\u{301}\u{1F1F5}\u{1F1F1}".chars().collect();
+        assert_eq!(next_grapheme_boundary(&chars, 0), 1);
+        assert_eq!(next_grapheme_boundary(&chars, 1), 3);
+        assert_eq!(next_grapheme_boundary(&chars, 3), 5);
+        assert_eq!(next_grapheme_boundary(&chars, 5), 5);
+        assert_eq!(previous_grapheme_boundary(&chars, 5), 3);
+        assert_eq!(previous_grapheme_boundary(&chars, 3), 1);
+        assert_eq!(previous_grapheme_boundary(&chars, 2), 1);
+        assert_eq!(previous_grapheme_boundary(&chars, 0), 0);
+    }
+}
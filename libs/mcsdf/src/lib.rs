@@ -3,16 +3,37 @@
 
 It allows you to create multi channel signed distance fields rendered
 to the memory. Currently rendering from fonts is supported as well.
+
+Without the default `std` feature only `geometry` and `math` are built, on
+`core` with float functions from the `libm` feature.
 */
 
 //#![warn(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("mcsdf needs the `std` or the `libm` feature for float functions");
 
+#[cfg(feature = "std")]
 extern crate cgmath;
+#[cfg(feature = "std")]
 extern crate rusttype;
 
+#[cfg(feature = "std")]
+pub mod bidi;
+#[cfg(feature = "std")]
 pub mod font;
 pub mod geometry;
+#[cfg(feature = "std")]
+pub mod grapheme;
 pub mod math;
+#[cfg(feature = "std")]
 pub mod renderer;
+#[cfg(feature = "std")]
 pub mod shape;
+#[cfg(feature = "std")]
+pub mod shaper;
+#[cfg(feature = "std")]
 pub mod texture;
+#[cfg(not(feature = "std"))]
+pub mod vector;
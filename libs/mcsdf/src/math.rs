@@ -1,4 +1,69 @@
-use std::f32::consts::PI;
+use core::f32::consts::PI;
+
+// Float functions which core doesn't provide, used by the root solvers and by
+// `geometry`. They come from std, or from the C math library with the `libm`
+// feature, which takes precedence so it can be tested next to std.
+#[cfg(feature = "libm")]
+mod libm {
+    #[link(name = "m")]
+    extern "C" {
+        pub fn sqrtf(x: f32) -> f32;
+        pub fn acosf(x: f32) -> f32;
+        pub fn cosf(x: f32) -> f32;
+        pub fn sinf(x: f32) -> f32;
+        pub fn powf(x: f32, n: f32) -> f32;
+    }
+}
+
+#[cfg(feature = "libm")]
+pub(crate) fn sqrt(x: f32) -> f32 {
+    unsafe { libm::sqrtf(x) }
+}
+
+#[cfg(feature = "libm")]
+fn acos(x: f32) -> f32 {
+    unsafe { libm::acosf(x) }
+}
+
+#[cfg(feature = "libm")]
+pub(crate) fn cos(x: f32) -> f32 {
+    unsafe { libm::cosf(x) }
+}
+
+#[cfg(feature = "libm")]
+pub(crate) fn sin(x: f32) -> f32 {
+    unsafe { libm::sinf(x) }
+}
+
+#[cfg(feature = "libm")]
+fn powf(x: f32, n: f32) -> f32 {
+    unsafe { libm::powf(x, n) }
+}
+
+#[cfg(not(feature = "libm"))]
+pub(crate) fn sqrt(x: f32) -> f32 {
+    x.sqrt()
+}
+
+#[cfg(not(feature = "libm"))]
+fn acos(x: f32) -> f32 {
+    x.acos()
+}
+
+#[cfg(not(feature = "libm"))]
+pub(crate) fn cos(x: f32) -> f32 {
+    x.cos()
+}
+
+#[cfg(not(feature = "libm"))]
+pub(crate) fn sin(x: f32) -> f32 {
+    x.sin()
+}
+
+#[cfg(not(feature = "libm"))]
+fn powf(x: f32, n: f32) -> f32 {
+    x.powf(n)
+}
 
 pub fn solve_quadratic(a: f32, b: f32, c: f32) -> (Option<f32>, Option<f32>) {
     if a == 0.0 {
@@ -15,7 +80,7 @@ pub fn solve_quadratic(a: f32, b: f32, c: f32) -> (Option<f32>, Option<f32>) {
         return (Some(-0.5 * b / a), None);
     }
 
-    let sqrt_delta = discriminant.sqrt();
+    let sqrt_delta = sqrt(discriminant);
     let t1 = (-b + sqrt_delta) / (2.0 * a);
     let t2 = (-b - sqrt_delta) / (2.0 * a);
 
@@ -41,16 +106,16 @@ pub fn solve_cubic(a: f32, b: f32, c: f32, d: f32) -> (Option<f32>, Option<f32>,
 
     if discriminant >= 0.0 {
         let twopi = 2.0 * PI;
-        let theta = (r / qqq.sqrt()).acos();
-        let mult = -2.0 * q.sqrt();
+        let theta = acos(r / sqrt(qqq));
+        let mult = -2.0 * sqrt(q);
         let add = -b * third;
-        let r0 = mult * (third * theta).cos() + add;
-        let r1 = mult * (third * (theta + twopi)).cos() + add;
-        let r2 = mult * (third * (theta + twopi + twopi)).cos() + add;
+        let r0 = mult * cos(third * theta) + add;
+        let r1 = mult * cos(third * (theta + twopi)) + add;
+        let r2 = mult * cos(third * (theta + twopi + twopi)) + add;
         return (Some(r0), Some(r1), Some(r2));
     }
 
-    let temp = ((-discriminant).sqrt() + r.abs()).powf(third);
+    let temp = powf(sqrt(-discriminant) + r.abs(), third);
     let sign = r.signum();
     let r = -sign * (temp + q / temp) - third * b;
     return (Some(r), None, None);
@@ -1,38 +1,158 @@
 use super::geometry::SignedDistance;
 use super::math::{clamp_f32, max, median, median_f32, min};
 use super::shape::{AllocatedShape, Shape, ShapeSegment};
-use super::texture::{LockedTexture, PixelView};
+use super::texture::PixelView;
 use cgmath::Point2;
 use std::f32;
 
-pub fn render_shape(allocated_shape: &mut AllocatedShape, locked_texture: &LockedTexture) {
+/// Tuning of the distance field generation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MsdfParams {
+    /// Distance from an edge, as a fraction of `max_distance`, where the
+    /// pseudo-distance starts blending from the extended edge distance to the
+    /// real one. Higher values keep corners sharp further away from the outline.
+    pub start_threshold: f32,
+    /// Length of that blend, as a fraction of `max_distance`. Shorter blends
+    /// round the field around corners more abruptly.
+    pub end_threshold: f32,
+}
+
+impl Default for MsdfParams {
+    fn default() -> Self {
+        Self {
+            start_threshold: 0.3,
+            end_threshold: 0.5,
+        }
+    }
+}
+
+/// Where the first row of a rendered texture view lies. Shapes use y pointing
+/// up, so the origin decides whether the glyph's bottom or top is written first.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum TextureOrigin {
+    /// Row 0 holds the bottom of the shape, matching OpenGL texture
+    /// coordinates, where v = 0 is the first row sampled from memory.
+    #[default]
+    BottomLeft,
+    /// Row 0 holds the top of the shape, matching image files and APIs which
+    /// put v = 0 at the top. The glyph appears upright when the view is saved
+    /// as an image.
+    TopLeft,
+}
+
+/// What `render_shape_debug` writes instead of the packed distance field.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DebugMode {
+    /// Channel distances as they are before error correction.
+    Channels,
+    /// Median of the channels as grayscale, which is what shaders reconstruct.
+    Median,
+    /// Median dimmed, with pixels replaced by error correction shown in red.
+    EdgeError,
+}
+
+/// Renders the distance field of a shape into its texture view, with rows in the
+/// order given by `AllocatedShape::origin`. Copy the view to the texture with
+/// `Texture::write_view` afterwards.
+pub fn render_shape(allocated_shape: &mut AllocatedShape) {
+    render_msdf(allocated_shape, |_, _, _| {});
+}
+
+/// Renders a visualization of the distance field for diagnostics. The shape
+/// is rendered the same way as by `render_shape` first, so the result matches
+/// what would end up in the atlas.
+pub fn render_shape_debug(allocated_shape: &mut AllocatedShape, mode: DebugMode) {
+    let mut pixels = Vec::new();
+    render_msdf(allocated_shape, |_, pixel, clashing| {
+        pixels.push((pixel, clashing));
+    });
+
+    let mut pixels = pixels.into_iter();
+    allocated_shape.texture_view.modify(|_| {
+        let (pixel, clashing) = pixels.next().expect("Cannot find rendered debug pixel");
+        let m = median(pixel);
+        match mode {
+            DebugMode::Channels => pixel,
+            DebugMode::Median => [m, m, m],
+            DebugMode::EdgeError if clashing => [255, 0, 0],
+            DebugMode::EdgeError => [m / 2, m / 2, m / 2],
+        }
+    });
+}
+
+// Renders the distance field, passing every pixel before error correction and
+// whether it was corrected to `inspect`, in the order the view is modified.
+fn render_msdf<F: FnMut(&PixelView, [u8; 3], bool)>(
+    allocated_shape: &mut AllocatedShape,
+    mut inspect: F,
+) {
     let bb = allocated_shape.shape_bb;
     let shape = &allocated_shape.shape;
     let max_distance = allocated_shape.max_distance;
-    let mut texture_view = &mut allocated_shape.texture_view;
+    let embolden = allocated_shape.embolden;
+    let params = allocated_shape.params;
+    let origin = allocated_shape.origin;
 
-    locked_texture.modify_view(&mut texture_view, |pixel_view| {
-        let pixel = Point2::new(
-            bb.min.x + pixel_view.x as f32,
-            // bb.min.y + (pixel_view.height - 1 - pixel_view.y) as f32,
-            bb.min.y + pixel_view.y as f32,
-        );
+    allocated_shape.texture_view.modify(|pixel_view| {
+        let row = match origin {
+            TextureOrigin::BottomLeft => pixel_view.y,
+            TextureOrigin::TopLeft => pixel_view.height - 1 - pixel_view.y,
+        };
+        let pixel = Point2::new(bb.min.x + pixel_view.x as f32, bb.min.y + row as f32);
 
-        let (rd, bd, gd) = render_shape_pixel(shape, max_distance, pixel);
+        let (rd, bd, gd) = render_shape_pixel(shape, max_distance, embolden, &params, pixel);
         let mut current_pixel = [(rd * 255.0) as u8, (gd * 255.0) as u8, (bd * 255.0) as u8];
+        let raw_pixel = current_pixel;
 
-        if is_pixel_clashing(max_distance, pixel_view, current_pixel) {
+        let clashing = is_pixel_clashing(max_distance, &pixel_view, current_pixel);
+        if clashing {
             let m = median(current_pixel);
             current_pixel[0] = m;
             current_pixel[1] = m;
             current_pixel[2] = m;
         }
 
+        inspect(&pixel_view, raw_pixel, clashing);
         current_pixel
     });
 }
 
-fn render_shape_pixel(shape: &Shape, max_distance: f32, pixel: Point2<f32>) -> (f32, f32, f32) {
+fn render_shape_pixel(
+    shape: &Shape,
+    max_distance: f32,
+    embolden: f32,
+    params: &MsdfParams,
+    pixel: Point2<f32>,
+) -> (f32, f32, f32) {
+    let distance = shape_distance(shape, max_distance, params, pixel);
+
+    (
+        normalize_distance(distance[0] + embolden, max_distance),
+        normalize_distance(distance[1] + embolden, max_distance),
+        normalize_distance(distance[2] + embolden, max_distance),
+    )
+}
+
+// Maps a distance to 0..1, where 0.5 is the outline. Non-finite distances
+// coming from degenerate segments are treated as far outside: a channel at 0
+// is ignored by the median whenever the other two agree on the inside, and
+// clash correction evens it out otherwise. A wrong inside value would instead
+// show up as a blot up to `max_distance` away from the outline. The same value
+// for NaN and both infinities also keeps the result independent of the sign
+// the arithmetic happened to produce.
+fn normalize_distance(distance: f32, max_distance: f32) -> f32 {
+    if !distance.is_finite() {
+        return 0.0;
+    }
+    clamp_f32(distance / max_distance, -1.0, 1.0) * 0.5 + 0.5
+}
+
+pub(crate) fn shape_distance(
+    shape: &Shape,
+    max_distance: f32,
+    params: &MsdfParams,
+    pixel: Point2<f32>,
+) -> [f32; 3] {
     const MAX: [f32; 3] = [f32::MAX, f32::MAX, f32::MAX];
     const ZERO: [f32; 3] = [0.0, 0.0, 0.0];
 
@@ -85,10 +205,8 @@ fn render_shape_pixel(shape: &Shape, max_distance: f32, pixel: Point2<f32>) -> (
                 distance[i] = sd.real_dist;
                 orthogonality[i] = sd.orthogonality;
 
-                const START_THRESHOLD: f32 = 0.3;
-                const END_THRESHOLD: f32 = 0.5;
-
-                let mut rd = (sd.real_dist / max_distance - START_THRESHOLD) / END_THRESHOLD;
+                let mut rd =
+                    (sd.real_dist / max_distance - params.start_threshold) / params.end_threshold;
                 rd = clamp_f32(rd, 0.0, 1.0);
 
                 pseudo_distance[i] = -sd.sign * ((1.0 - rd) * sd.extended_dist + rd * sd.real_dist);
@@ -96,11 +214,7 @@ fn render_shape_pixel(shape: &Shape, max_distance: f32, pixel: Point2<f32>) -> (
         }
     }
 
-    (
-        clamp_f32(final_distance[0] / max_distance, -1.0, 1.0) * 0.5 + 0.5,
-        clamp_f32(final_distance[1] / max_distance, -1.0, 1.0) * 0.5 + 0.5,
-        clamp_f32(final_distance[2] / max_distance, -1.0, 1.0) * 0.5 + 0.5,
-    )
+    final_distance
 }
 
 fn is_closer_to_segment(sd: &SignedDistance, distance: f32, orthogonality: f32) -> bool {
@@ -111,7 +225,7 @@ fn is_closer_to_segment(sd: &SignedDistance, distance: f32, orthogonality: f32)
     }
 }
 
-fn is_pixel_clashing(max_distance: f32, pixel_view: PixelView, current_pixel: [u8; 3]) -> bool {
+fn is_pixel_clashing(max_distance: f32, pixel_view: &PixelView, current_pixel: [u8; 3]) -> bool {
     if pixel_view.x == pixel_view.width - 1 || pixel_view.y == pixel_view.height - 1 {
         return true;
     }
@@ -161,3 +275,190 @@ fn is_pixel_pair_clashing(clashing_threshold: i16, p1: [u8; 3], p2: [u8; 3]) ->
 
     clashing
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Curve, Line};
+    use crate::shape::Segment;
+    use crate::texture::Texture;
+
+    // Closed polygon through `points`. Clockwise polygons have a positive inside.
+    fn polygon(points: &[(f32, f32)]) -> Shape {
+        let points: Vec<Point2<f32>> = points.iter().map(|&(x, y)| Point2::new(x, y)).collect();
+        let lines = (0..points.len()).map(|i| Segment::Line {
+            line: Line::new(points[i], points[(i + 1) % points.len()]),
+        });
+        std::iter::once(Segment::Start {
+            count: points.len(),
+        })
+        .chain(lines)
+        .collect()
+    }
+
+    // Rendered view of `shape` and its width in pixels.
+    fn render(shape: Shape, params: MsdfParams, origin: TextureOrigin) -> (Vec<u8>, usize) {
+        let (_texture, mut allocator) = Texture::new(16, 16);
+        let mut allocated_shape =
+            AllocatedShape::with_options(shape, &mut allocator, 3.0, 0.0, None, params)
+                .expect("Cannot allocate shape");
+        allocated_shape.origin = origin;
+        render_shape(&mut allocated_shape);
+        let width = allocated_shape.texture_view.get_view().width() as usize;
+        (allocated_shape.texture_view.get_data().to_vec(), width)
+    }
+
+    fn render_square(params: MsdfParams) -> Vec<u8> {
+        let square = polygon(&[(0.0, 0.0), (0.0, 2.0), (2.0, 2.0), (2.0, 0.0)]);
+        render(square, params, TextureOrigin::BottomLeft).0
+    }
+
+    // Square rendered before the thresholds became configurable.
+    #[rustfmt::skip]
+    const SQUARE: [u8; 8 * 8 * 3] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 0, 0, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 7, 7, 7, 33, 38, 33, 42, 189, 42, 42, 216, 42, 42, 65, 42, 33, 38, 33, 7, 7, 7,
+        0, 0, 0, 38, 33, 33, 78, 78, 78, 84, 130, 84, 84, 176, 84, 84, 124, 84, 78, 78, 78, 33, 33, 33,
+        0, 0, 0, 65, 42, 42, 124, 84, 84, 127, 127, 127, 127, 170, 127, 127, 127, 127, 84, 84, 130, 42, 42, 42,
+        255, 0, 0, 216, 42, 42, 176, 84, 84, 127, 127, 127, 170, 170, 170, 127, 127, 127, 84, 84, 176, 42, 42, 42,
+        0, 0, 0, 65, 42, 42, 124, 84, 84, 127, 127, 127, 127, 170, 127, 127, 127, 127, 84, 84, 124, 42, 42, 42,
+        0, 0, 0, 38, 33, 33, 78, 78, 78, 84, 124, 84, 84, 176, 84, 84, 124, 84, 78, 78, 78, 33, 33, 33,
+        0, 0, 0, 7, 7, 7, 33, 33, 33, 42, 42, 42, 42, 42, 42, 42, 42, 42, 33, 33, 33, 7, 7, 7,
+    ];
+
+    #[test]
+    fn default_params_match_previous_output() {
+        assert_eq!(render_square(MsdfParams::default()), SQUARE.to_vec());
+    }
+
+    #[test]
+    fn custom_params_change_output() {
+        let params = MsdfParams {
+            start_threshold: 0.0,
+            end_threshold: 0.1,
+        };
+        let pixels = render_square(params);
+        assert_eq!(pixels.len(), SQUARE.len());
+        assert_ne!(pixels, SQUARE.to_vec());
+    }
+
+    #[test]
+    fn normalize_distance_range() {
+        assert_eq!(normalize_distance(0.0, 4.0), 0.5);
+        assert_eq!(normalize_distance(4.0, 4.0), 1.0);
+        assert_eq!(normalize_distance(-4.0, 4.0), 0.0);
+        assert_eq!(normalize_distance(2.0, 4.0), 0.75);
+        // Distances beyond `max_distance` are clamped.
+        assert_eq!(normalize_distance(100.0, 4.0), 1.0);
+        assert_eq!(normalize_distance(-100.0, 4.0), 0.0);
+        assert_eq!(normalize_distance(f32::MAX, 4.0), 1.0);
+        assert_eq!(normalize_distance(-f32::MAX, 4.0), 0.0);
+    }
+
+    #[test]
+    fn normalize_distance_non_finite_is_outside() {
+        assert_eq!(normalize_distance(f32::NAN, 4.0), 0.0);
+        assert_eq!(normalize_distance(f32::INFINITY, 4.0), 0.0);
+        assert_eq!(normalize_distance(f32::NEG_INFINITY, 4.0), 0.0);
+    }
+
+    #[test]
+    fn top_left_origin_flips_rows() {
+        // A triangle leaning right, so it's asymmetric vertically.
+        let triangle = || polygon(&[(0.0, 0.0), (0.0, 4.0), (3.0, 0.0)]);
+        let params = MsdfParams::default();
+        let (bottom_left, width) = render(triangle(), params, TextureOrigin::BottomLeft);
+        let (top_left, _) = render(triangle(), params, TextureOrigin::TopLeft);
+        assert_eq!(bottom_left.len(), top_left.len());
+        assert_ne!(bottom_left, top_left);
+
+        // Error correction compares pixels with ones written before them, so
+        // single channels may differ between origins. The median, which is
+        // what shaders reconstruct, must not.
+        let medians = |pixels: &[u8]| -> Vec<u8> {
+            pixels
+                .chunks(3)
+                .map(|p| median([p[0], p[1], p[2]]))
+                .collect()
+        };
+        let bottom_left = medians(&bottom_left);
+        let top_left = medians(&top_left);
+        let last = bottom_left.len() / width - 1;
+        assert_eq!(bottom_left[..width], top_left[last * width..]);
+        assert_eq!(bottom_left[last * width..], top_left[..width]);
+
+        // Channels before error correction are mirrored exactly.
+        let channels = |origin| {
+            let (_texture, mut allocator) = Texture::new(16, 16);
+            let mut allocated_shape = AllocatedShape::new(triangle(), &mut allocator, 3.0)
+                .expect("Cannot allocate shape");
+            allocated_shape.origin = origin;
+            render_shape_debug(&mut allocated_shape, DebugMode::Channels);
+            allocated_shape.texture_view.get_data().to_vec()
+        };
+        let bottom_left = channels(TextureOrigin::BottomLeft);
+        let top_left = channels(TextureOrigin::TopLeft);
+        let row = width * 3;
+        assert_eq!(bottom_left[..row], top_left[last * row..]);
+        assert_eq!(bottom_left[last * row..], top_left[..row]);
+    }
+
+    #[test]
+    fn zero_length_segments_render_defined_pixels() {
+        // The square with a repeated corner and a curve collapsed to a point.
+        let point = Point2::new(2.0, 2.0);
+        let shape: Shape = vec![
+            Segment::Start { count: 6 },
+            Segment::Line {
+                line: Line::new(Point2::new(0.0, 0.0), Point2::new(0.0, 2.0)),
+            },
+            Segment::Line {
+                line: Line::new(Point2::new(0.0, 2.0), point),
+            },
+            Segment::Line {
+                line: Line::new(point, point),
+            },
+            Segment::Curve {
+                curve: Curve {
+                    p0: point,
+                    p1: point,
+                    p2: point,
+                },
+            },
+            Segment::Line {
+                line: Line::new(point, Point2::new(2.0, 0.0)),
+            },
+            Segment::Line {
+                line: Line::new(Point2::new(2.0, 0.0), Point2::new(0.0, 0.0)),
+            },
+        ]
+        .into_iter()
+        .collect();
+
+        let params = MsdfParams::default();
+        for y in -3..6 {
+            for x in -3..6 {
+                let pixel = Point2::new(x as f32, y as f32);
+                let (r, g, b) = render_shape_pixel(&shape, 3.0, 0.0, &params, pixel);
+                for channel in [r, g, b] {
+                    assert!(
+                        (0.0..=1.0).contains(&channel),
+                        "{:?} at {:?}",
+                        channel,
+                        pixel
+                    );
+                }
+            }
+        }
+
+        // The outline is the same as the square's, so is what shaders see.
+        let medians = |pixels: &[u8]| -> Vec<u8> {
+            pixels
+                .chunks(3)
+                .map(|p| median([p[0], p[1], p[2]]))
+                .collect()
+        };
+        let (pixels, _) = render(shape, params, TextureOrigin::BottomLeft);
+        assert_eq!(medians(&pixels), medians(&SQUARE));
+    }
+}
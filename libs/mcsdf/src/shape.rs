@@ -1,5 +1,8 @@
 use super::geometry::{Curve, Line, Rect};
+use super::math::median_f32;
+use super::renderer::{shape_distance, MsdfParams, TextureOrigin};
 use super::texture::{TextureView, TextureViewAllocator};
+use cgmath::{InnerSpace, Point2};
 use std::f32;
 use std::iter::FromIterator;
 
@@ -15,6 +18,117 @@ impl Shape {
     pub fn get_segments(&self) -> &[ShapeSegment] {
         &self.segments
     }
+
+    /// Returns a copy with near-flat curves replaced by lines and runs of lines
+    /// reduced Douglas–Peucker style, so no part of the outline moves by more
+    /// than `tolerance` shape units. Channel masks are assigned again.
+    pub fn simplified(&self, tolerance: f32) -> Shape {
+        let mut segments = Vec::new();
+        let mut contour = Vec::new();
+        for segment in &self.segments {
+            match *segment {
+                ShapeSegment::Line { line, .. } => contour.push(Segment::Line { line }),
+                ShapeSegment::Curve { curve, .. } => {
+                    // The curve lies inside the triangle of its control points, so
+                    // it is within the control point distance from the chord.
+                    let chord = Line::new(curve.p0, curve.p2);
+                    if point_line_distance(curve.p1, chord) <= tolerance {
+                        contour.push(Segment::Line { line: chord });
+                    } else {
+                        contour.push(Segment::Curve { curve });
+                    }
+                }
+                ShapeSegment::End { .. } => {
+                    let contour = simplify_contour(std::mem::take(&mut contour), tolerance);
+                    segments.push(Segment::Start {
+                        count: contour.len(),
+                    });
+                    segments.extend(contour);
+                }
+            }
+        }
+
+        segments.into_iter().collect()
+    }
+}
+
+fn simplify_contour(contour: Vec<Segment>, tolerance: f32) -> Vec<Segment> {
+    let mut simplified = Vec::new();
+    let mut points = Vec::new();
+    for segment in &contour {
+        match segment {
+            Segment::Line { line } => {
+                if points.is_empty() {
+                    points.push(line.p0);
+                }
+                points.push(line.p1);
+            }
+            Segment::Curve { curve } => {
+                flush_polyline(&mut points, tolerance, &mut simplified);
+                simplified.push(Segment::Curve { curve: *curve });
+            }
+            Segment::Start { .. } => {}
+        }
+    }
+    flush_polyline(&mut points, tolerance, &mut simplified);
+
+    // Tiny contours could collapse into a degenerate polygon.
+    let only_lines = simplified
+        .iter()
+        .all(|segment| matches!(segment, Segment::Line { .. }));
+    if only_lines && simplified.len() < 3 {
+        contour
+    } else {
+        simplified
+    }
+}
+
+fn flush_polyline(points: &mut Vec<Point2<f32>>, tolerance: f32, segments: &mut Vec<Segment>) {
+    if points.len() < 2 {
+        points.clear();
+        return;
+    }
+
+    let mut kept = vec![points[0]];
+    douglas_peucker(points, tolerance, &mut kept);
+    for pair in kept.windows(2) {
+        segments.push(Segment::Line {
+            line: Line::new(pair[0], pair[1]),
+        });
+    }
+    points.clear();
+}
+
+// Pushes every kept point of `points` apart from the first one.
+fn douglas_peucker(points: &[Point2<f32>], tolerance: f32, kept: &mut Vec<Point2<f32>>) {
+    let last = points.len() - 1;
+    let chord = Line::new(points[0], points[last]);
+    let mut farthest = (0, 0.0);
+    for (index, point) in points.iter().enumerate().take(last).skip(1) {
+        let distance = point_line_distance(*point, chord);
+        if distance > farthest.1 {
+            farthest = (index, distance);
+        }
+    }
+
+    let (index, distance) = farthest;
+    if distance > tolerance {
+        douglas_peucker(&points[..=index], tolerance, kept);
+        douglas_peucker(&points[index..], tolerance, kept);
+    } else {
+        kept.push(points[last]);
+    }
+}
+
+fn point_line_distance(p: Point2<f32>, line: Line) -> f32 {
+    let d = line.p1 - line.p0;
+    let length2 = d.dot(d);
+    let t = if length2 > 0.0 {
+        ((p - line.p0).dot(d) / length2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (p - line.point(t)).magnitude()
 }
 
 #[derive(Clone, Copy)]
@@ -34,11 +148,25 @@ impl ShapeSegment {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AllocateShapeError {
+    /// The shape has no segments, e.g. a whitespace glyph, so there is nothing
+    /// to render.
+    Empty,
+    /// No free texture space is big enough for the shape.
+    OutOfSpace,
+}
+
 pub struct AllocatedShape {
     pub shape: Shape,
     pub shape_bb: Rect<f32>,
     pub texture_view: TextureView,
     pub max_distance: f32,
+    /// Distance added to the whole field, growing the outline when positive.
+    pub embolden: f32,
+    pub params: MsdfParams,
+    /// Row order of the rendered view. Bottom left by default.
+    pub origin: TextureOrigin,
 }
 
 impl AllocatedShape {
@@ -46,7 +174,41 @@ impl AllocatedShape {
         shape: Shape,
         texture_allocator: &mut TextureViewAllocator,
         max_distance: f32,
-    ) -> Option<Self> {
+    ) -> Result<Self, AllocateShapeError> {
+        Self::with_embolden(shape, texture_allocator, max_distance, 0.0)
+    }
+
+    /// Same as `with_embolden`, but the shape is simplified first when
+    /// `simplify_tolerance` is set (see `Shape::simplified`) and rendered
+    /// with custom `params`.
+    pub fn with_options(
+        shape: Shape,
+        texture_allocator: &mut TextureViewAllocator,
+        max_distance: f32,
+        embolden: f32,
+        simplify_tolerance: Option<f32>,
+        params: MsdfParams,
+    ) -> Result<Self, AllocateShapeError> {
+        let shape = match simplify_tolerance {
+            Some(tolerance) => shape.simplified(tolerance),
+            None => shape,
+        };
+        let allocated_shape =
+            Self::with_embolden(shape, texture_allocator, max_distance, embolden)?;
+        Ok(Self {
+            params,
+            ..allocated_shape
+        })
+    }
+
+    /// Same as `new`, but the rendered outline is moved outwards by `embolden`
+    /// shape units (inwards when negative).
+    pub fn with_embolden(
+        shape: Shape,
+        texture_allocator: &mut TextureViewAllocator,
+        max_distance: f32,
+        embolden: f32,
+    ) -> Result<Self, AllocateShapeError> {
         let mut max_bb: Option<Rect<f32>> = None;
         for segment in &shape.segments {
             if let Some(bb) = segment.bounding_box() {
@@ -61,22 +223,39 @@ impl AllocatedShape {
             }
         }
 
-        let mut max_bb = max_bb?;
-        max_bb.min.x -= max_distance;
-        max_bb.min.y -= max_distance;
-        max_bb.max.x += max_distance;
-        max_bb.max.y += max_distance;
+        let padding = max_distance + embolden.max(0.0);
+        let mut max_bb = max_bb.ok_or(AllocateShapeError::Empty)?;
+        max_bb.min.x -= padding;
+        max_bb.min.y -= padding;
+        max_bb.max.x += padding;
+        max_bb.max.y += padding;
 
         let texture_view = texture_allocator
-            .allocate(max_bb.width().ceil() as u32, max_bb.height().ceil() as u32)?;
+            .allocate(max_bb.width().ceil() as u32, max_bb.height().ceil() as u32)
+            .ok_or(AllocateShapeError::OutOfSpace)?;
 
-        Some(Self {
+        Ok(Self {
             shape,
             shape_bb: max_bb,
             texture_view,
             max_distance,
+            embolden,
+            params: MsdfParams::default(),
+            origin: TextureOrigin::default(),
         })
     }
+
+    /// Signed distance from `p` to the shape outline, reconstructed the same
+    /// way as a rendered texel (median of channels), but without clamping to
+    /// `max_distance`. Uses shape units and is positive inside the glyph.
+    pub fn signed_distance_at(&self, p: Point2<f32>) -> f32 {
+        median_f32(shape_distance(
+            &self.shape,
+            self.max_distance,
+            &self.params,
+            p,
+        )) + self.embolden
+    }
 }
 
 pub enum Segment {
@@ -138,3 +317,214 @@ impl<'a> FromIterator<Segment> for Shape {
         Shape::new(shape_segments)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::median;
+    use crate::renderer::render_shape;
+    use crate::texture::Texture;
+
+    // Closed polygon through `points`.
+    fn polygon(points: &[(f32, f32)]) -> Shape {
+        let points: Vec<Point2<f32>> = points.iter().map(|&(x, y)| Point2::new(x, y)).collect();
+        let lines = (0..points.len()).map(|i| Segment::Line {
+            line: Line::new(points[i], points[(i + 1) % points.len()]),
+        });
+        std::iter::once(Segment::Start {
+            count: points.len(),
+        })
+        .chain(lines)
+        .collect()
+    }
+
+    // Lines of every contour of `shape`.
+    fn contour_lines(shape: &Shape) -> Vec<Vec<Line>> {
+        let mut contours = vec![Vec::new()];
+        for segment in shape.get_segments() {
+            match *segment {
+                ShapeSegment::Line { line, .. } => contours.last_mut().unwrap().push(line),
+                ShapeSegment::Curve { .. } => panic!("Expected only lines"),
+                ShapeSegment::End { .. } => contours.push(Vec::new()),
+            }
+        }
+        contours.pop();
+        contours
+    }
+
+    fn assert_closed(contour: &[Line]) {
+        for pair in contour.windows(2) {
+            assert_eq!(pair[0].p1, pair[1].p0);
+        }
+        assert_eq!(contour.last().unwrap().p1, contour[0].p0);
+    }
+
+    #[test]
+    fn simplified_collapses_collinear_points() {
+        // Square with extra points along every side and a tiny bump.
+        let square = polygon(&[
+            (0.0, 0.0),
+            (5.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 3.0),
+            (10.01, 5.0),
+            (10.0, 10.0),
+            (5.0, 10.0),
+            (0.0, 10.0),
+            (0.0, 5.0),
+        ]);
+        let contours = contour_lines(&square.simplified(0.1));
+        assert_eq!(contours.len(), 1);
+        let corners: Vec<Point2<f32>> = contours[0].iter().map(|line| line.p0).collect();
+        assert_eq!(
+            corners,
+            vec![
+                Point2::new(0.0, 0.0),
+                Point2::new(10.0, 0.0),
+                Point2::new(10.0, 10.0),
+                Point2::new(0.0, 10.0),
+            ]
+        );
+        assert_closed(&contours[0]);
+    }
+
+    #[test]
+    fn simplified_keeps_corners_beyond_tolerance() {
+        let notched = polygon(&[
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (5.0, 1.0),
+            (6.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (0.0, 10.0),
+        ]);
+        let contours = contour_lines(&notched.simplified(0.5));
+        assert_eq!(contours[0].len(), 7);
+        assert_closed(&contours[0]);
+
+        // The notch is within a bigger tolerance.
+        let contours = contour_lines(&notched.simplified(2.0));
+        assert_eq!(contours[0].len(), 4);
+        assert_closed(&contours[0]);
+    }
+
+    #[test]
+    fn simplified_keeps_every_contour_closed() {
+        let outer = polygon(&[
+            (0.0, 0.0),
+            (5.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (0.0, 10.0),
+        ]);
+        let inner = polygon(&[(2.0, 2.0), (2.0, 8.0), (8.0, 8.0), (8.0, 5.0), (8.0, 2.0)]);
+        let shape = Shape::new(
+            outer
+                .get_segments()
+                .iter()
+                .chain(inner.get_segments())
+                .cloned()
+                .collect(),
+        );
+        let contours = contour_lines(&shape.simplified(0.1));
+        assert_eq!(contours.len(), 2);
+        for contour in &contours {
+            assert_eq!(contour.len(), 4);
+            assert_closed(contour);
+        }
+
+        // A triangle can't lose any point without collapsing.
+        let thin = polygon(&[(0.0, 0.0), (10.0, 0.0), (5.0, 0.05)]);
+        let contours = contour_lines(&thin.simplified(1.0));
+        assert_eq!(contours[0].len(), 3);
+        assert_closed(&contours[0]);
+    }
+
+    // Outline of `c` from the bundled font, `size` pixels tall.
+    fn glyph_shape(c: char, size: f32) -> Shape {
+        let font = rusttype::Font::from_bytes(&include_bytes!("../../../assets/monserat.ttf")[..])
+            .expect("Cannot load the bundled font");
+        let contours = font
+            .glyph(c)
+            .scaled(rusttype::Scale::uniform(size))
+            .shape()
+            .expect("Glyph has no outline");
+        contours.as_slice().into()
+    }
+
+    // Medians of a shape rendered over `shape_bb` instead of its own bounding
+    // box, so two shapes can be compared pixel by pixel.
+    fn render_medians(shape: Shape, shape_bb: Rect<f32>, max_distance: f32) -> Vec<u8> {
+        let (_, mut allocator) = Texture::new(256, 256);
+        let mut allocated = AllocatedShape::new(shape, &mut allocator, max_distance)
+            .expect("Cannot allocate the glyph");
+        allocated.shape_bb = shape_bb;
+        allocated.texture_view = allocator
+            .allocate(
+                shape_bb.width().ceil() as u32,
+                shape_bb.height().ceil() as u32,
+            )
+            .expect("Cannot allocate the view");
+        render_shape(&mut allocated);
+        allocated
+            .texture_view
+            .get_data()
+            .chunks(3)
+            .map(|pixel| median([pixel[0], pixel[1], pixel[2]]))
+            .collect()
+    }
+
+    #[test]
+    fn simplified_glyph_field_stays_within_tolerance() {
+        let max_distance = 4.0;
+        let tolerance = 0.5;
+        for &c in &['S', 'g', '@', '&'] {
+            let original = glyph_shape(c, 48.0);
+            let simplified = original.simplified(tolerance);
+
+            let (_, mut allocator) = Texture::new(256, 256);
+            let shape_bb = AllocatedShape::new(glyph_shape(c, 48.0), &mut allocator, max_distance)
+                .expect("Cannot allocate the glyph")
+                .shape_bb;
+            let expected = render_medians(original, shape_bb, max_distance);
+            let actual = render_medians(simplified, shape_bb, max_distance);
+            assert_eq!(expected.len(), actual.len());
+            assert_ne!(expected, actual);
+
+            // A distance of `max_distance` spans half of the 0..255 range. One
+            // more step for rounding.
+            let max_difference = (tolerance / max_distance * 127.5).ceil() as i32 + 1;
+            let worst = expected
+                .iter()
+                .zip(&actual)
+                .map(|(&e, &a)| (e as i32 - a as i32).abs())
+                .max()
+                .unwrap();
+            assert!(worst <= max_difference, "{:?} differs by {}", c, worst);
+        }
+    }
+
+    #[test]
+    fn empty_shape_is_not_allocated() {
+        let (_, mut allocator) = Texture::new(64, 64);
+        let result = AllocatedShape::new(Shape::new(Vec::new()), &mut allocator, 2.0);
+        assert_eq!(result.err(), Some(AllocateShapeError::Empty));
+    }
+
+    #[test]
+    fn shape_larger_than_texture_is_out_of_space() {
+        let (_, mut allocator) = Texture::new(32, 32);
+        let square = polygon(&[(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (0.0, 100.0)]);
+        let result = AllocatedShape::new(square, &mut allocator, 2.0);
+        assert_eq!(result.err(), Some(AllocateShapeError::OutOfSpace));
+
+        // The same allocator still fits a small shape.
+        let square = polygon(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        let allocated = AllocatedShape::new(square, &mut allocator, 2.0)
+            .ok()
+            .unwrap();
+        let view = allocated.texture_view.get_view();
+        assert_eq!((view.width(), view.height()), (14, 14));
+    }
+}
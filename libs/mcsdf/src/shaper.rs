@@ -0,0 +1,60 @@
+use rusttype::{Font as RustTypeFont, GlyphId, Scale};
+
+/// Glyph positioned by a `Shaper`. Distances are in units of the font size.
+#[derive(Copy, Clone, Debug)]
+pub struct ShapedGlyph {
+    pub glyph_id: GlyphId,
+    pub x_advance: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+}
+
+/// Turns a single line of text into positioned glyphs. Implement it to plug in
+/// a full shaping engine (e.g. rustybuzz) for scripts which need joining,
+/// reordering or ligatures.
+pub trait Shaper: Send + Sync {
+    fn shape(&self, font: &RustTypeFont<'static>, text: &str) -> Vec<ShapedGlyph>;
+
+    /// Toggles kerning. Shapers which can't turn it off ignore it.
+    fn set_kerning_enabled(&mut self, _enabled: bool) {}
+}
+
+/// Maps every char to a single glyph and applies pair kerning unless disabled.
+pub struct DefaultShaper {
+    kerning: bool,
+}
+
+impl Default for DefaultShaper {
+    fn default() -> Self {
+        DefaultShaper { kerning: true }
+    }
+}
+
+impl Shaper for DefaultShaper {
+    fn shape(&self, font: &RustTypeFont<'static>, text: &str) -> Vec<ShapedGlyph> {
+        let scale = Scale::uniform(1.0);
+        let mut glyphs: Vec<ShapedGlyph> = Vec::with_capacity(text.len());
+
+        for c in text.chars() {
+            let glyph = font.glyph(c).scaled(scale);
+            let glyph_id = glyph.id();
+
+            if let Some(last_glyph) = glyphs.last_mut().filter(|_| self.kerning) {
+                last_glyph.x_advance += font.pair_kerning(scale, last_glyph.glyph_id, glyph_id);
+            }
+
+            glyphs.push(ShapedGlyph {
+                glyph_id,
+                x_advance: glyph.h_metrics().advance_width,
+                x_offset: 0.0,
+                y_offset: 0.0,
+            });
+        }
+
+        glyphs
+    }
+
+    fn set_kerning_enabled(&mut self, enabled: bool) {
+        self.kerning = enabled;
+    }
+}
@@ -1,5 +1,4 @@
 use super::geometry::Rect;
-use std::marker::PhantomData;
 
 pub struct Texture {
     data: Vec<u8>,
@@ -8,47 +7,127 @@ pub struct Texture {
 }
 
 pub struct TextureViewAllocator {
-    data: *mut [u8],
     width: u32,
     height: u32,
     free_space: Vec<Rect<u32>>,
+    // Views handed out so far, to catch packing bugs in debug builds.
+    #[cfg(debug_assertions)]
+    allocated: Vec<Rect<u32>>,
 }
 
+/// Region of a texture with its own pixel buffer. Views are rendered
+/// independently, so they can be safely modified from many threads, and then
+/// copied to the texture with `Texture::write_view`.
 pub struct TextureView {
-    data: *mut [u8],
     view: Rect<u32>,
+    data: Vec<u8>,
 }
 
-pub struct LockedTexture<'a> {
-    texture: *mut Texture,
-    phantom: PhantomData<&'a Texture>,
+#[derive(Debug)]
+pub enum TextureError {
+    InvalidDataSize { expected: usize, actual: usize },
 }
 
-unsafe impl Send for TextureViewAllocator {}
-unsafe impl Sync for TextureViewAllocator {}
-unsafe impl Send for TextureView {}
-unsafe impl Sync for TextureView {}
-unsafe impl<'a> Send for LockedTexture<'a> {}
-unsafe impl<'a> Sync for LockedTexture<'a> {}
-
 impl TextureView {
     pub fn get_view(&self) -> Rect<u32> {
         self.view
     }
+
+    /// RGB rows of the view, empty until the view is modified.
+    pub fn get_data(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    /// Sets pixels of the view to values returned by `func`, row by row from
+    /// the top. Neighbours passed in `PixelView` outside the view are black.
+    pub fn modify<F: FnMut(PixelView) -> [u8; 3]>(&mut self, mut func: F) {
+        let width = self.view.width();
+        let height = self.view.height();
+        self.data.resize((width * height * 3) as usize, 0);
+
+        let data = &mut self.data;
+        let pixel = |data: &[u8], x: u32, y: u32| {
+            let offset = 3 * (y * width + x) as usize;
+            [data[offset], data[offset + 1], data[offset + 2]]
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let black = [0, 0, 0];
+                let value = func(PixelView {
+                    x,
+                    y,
+                    width,
+                    height,
+                    top_pixel: if y > 0 { pixel(data, x, y - 1) } else { black },
+                    left_pixel: if x > 0 { pixel(data, x - 1, y) } else { black },
+                    top_left_pixel: if x > 0 && y > 0 {
+                        pixel(data, x - 1, y - 1)
+                    } else {
+                        black
+                    },
+                    top_right_pixel: if x + 1 < width && y > 0 {
+                        pixel(data, x + 1, y - 1)
+                    } else {
+                        black
+                    },
+                });
+
+                let offset = 3 * (y * width + x) as usize;
+                data[offset..offset + 3].copy_from_slice(&value);
+            }
+        }
+    }
 }
 
 impl Texture {
     pub fn new(width: u32, height: u32) -> (Self, TextureViewAllocator) {
-        let mut texture = Texture {
-            data: vec![0; (width * height * 3) as usize],
+        Self::from_parts(
+            width,
+            height,
+            vec![0; (width * height * 3) as usize],
+            vec![Rect::new(0, 0, width, height)],
+        )
+    }
+
+    /// Creates a texture from existing RGB `data`. The returned allocator has
+    /// no free space, so loaded pixels are never overwritten.
+    pub fn from_raw(
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+    ) -> Result<(Self, TextureViewAllocator), TextureError> {
+        // Sizes which don't fit into usize can't match any data.
+        let expected = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .unwrap_or(usize::MAX);
+        if data.len() != expected {
+            return Err(TextureError::InvalidDataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self::from_parts(width, height, data, Vec::new()))
+    }
+
+    pub(crate) fn from_parts(
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+        free_space: Vec<Rect<u32>>,
+    ) -> (Self, TextureViewAllocator) {
+        let texture = Texture {
+            data,
             width,
             height,
         };
         let allocator = TextureViewAllocator {
-            data: texture.data.as_mut_slice(),
-            width: width,
-            height: height,
-            free_space: vec![Rect::new(0, 0, width, height)],
+            width,
+            height,
+            free_space,
+            #[cfg(debug_assertions)]
+            allocated: Vec::new(),
         };
         (texture, allocator)
     }
@@ -65,15 +144,34 @@ impl Texture {
         self.data.as_slice()
     }
 
-    pub fn lock(&mut self) -> LockedTexture {
-        LockedTexture {
-            texture: self,
-            phantom: PhantomData,
+    /// Copies pixels of a modified `view` into the texture.
+    pub fn write_view(&mut self, view: &TextureView) {
+        let rect = view.view;
+        assert!(rect.max.x <= self.width && rect.max.y <= self.height);
+        if view.data.is_empty() {
+            return;
+        }
+
+        let row_bytes = (rect.width() * 3) as usize;
+        for (row, y) in (rect.min.y..rect.max.y).enumerate() {
+            let offset = 3 * (y * self.width + rect.min.x) as usize;
+            self.data[offset..offset + row_bytes]
+                .copy_from_slice(&view.data[row * row_bytes..(row + 1) * row_bytes]);
         }
     }
 }
 
 impl TextureViewAllocator {
+    /// Rects which are still available for allocation. They never overlap.
+    pub fn free_rects(&self) -> &[Rect<u32>] {
+        &self.free_space
+    }
+
+    /// Fraction of the texture area which is already allocated.
+    pub fn allocated_area_fraction(&self) -> f32 {
+        1.0 - self.get_free_space()
+    }
+
     pub fn get_free_space(&self) -> f32 {
         let free_space_area: f32 = self
             .free_space
@@ -137,14 +235,26 @@ impl TextureViewAllocator {
         self.free_space
             .sort_by(|x, y| (x.width() * x.height()).cmp(&(y.width() * y.height())));
 
+        let view = Rect::new(
+            slot.min.x,
+            slot.min.y,
+            slot.min.x + width,
+            slot.min.y + height,
+        );
+
+        #[cfg(debug_assertions)]
+        {
+            assert!(view.max.x <= self.width && view.max.y <= self.height);
+            assert!(
+                !self.allocated.iter().any(|rect| rect.intersects(&view)),
+                "Allocated texture view overlaps another one"
+            );
+            self.allocated.push(view);
+        }
+
         Some(TextureView {
-            data: self.data,
-            view: Rect::new(
-                slot.min.x,
-                slot.min.y,
-                slot.min.x + width,
-                slot.min.y + height,
-            ),
+            view,
+            data: Vec::new(),
         })
     }
 }
@@ -160,54 +270,29 @@ pub struct PixelView {
     pub top_right_pixel: [u8; 3],
 }
 
-impl<'a> LockedTexture<'a> {
-    pub fn modify_view<F: Fn(PixelView) -> [u8; 3]>(&self, view: &mut TextureView, func: F) {
-        let texture = unsafe { &mut *self.texture };
-        assert!(view.data == texture.data.as_mut_slice());
-
-        let mut top_pixel = [0, 0, 0];
-        let mut left_pixel = [0, 0, 0];
-        let mut top_left_pixel = [0, 0, 0];
-        let mut top_right_pixel = [0, 0, 0];
-
-        for y in view.view.min.y..view.view.max.y {
-            for x in view.view.min.x..view.view.max.x {
-                if y > view.view.min.y {
-                    let top_offset = 3 * ((y - 1) * texture.width + x) as usize;
-                    top_pixel[0] = texture.data[top_offset];
-                    top_pixel[1] = texture.data[top_offset + 1];
-                    top_pixel[2] = texture.data[top_offset + 2];
-
-                    if x >= view.view.max.x {
-                        top_right_pixel = [0, 0, 0];
-                    } else {
-                        top_right_pixel[0] = texture.data[top_offset + 3];
-                        top_right_pixel[1] = texture.data[top_offset + 4];
-                        top_right_pixel[2] = texture.data[top_offset + 5];
-                    }
-                }
-
-                let pixel = func(PixelView {
-                    x: x - view.view.min.x,
-                    y: y - view.view.min.y,
-                    width: view.view.width(),
-                    height: view.view.height(),
-                    top_pixel,
-                    left_pixel,
-                    top_left_pixel,
-                    top_right_pixel,
-                });
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                let offset = 3 * (y * texture.width + x) as usize;
-                texture.data[offset] = pixel[0];
-                texture.data[offset + 1] = pixel[1];
-                texture.data[offset + 2] = pixel[2];
-
-                left_pixel = pixel;
-                top_left_pixel = top_pixel;
+    #[test]
+    fn from_raw_checks_data_size() {
+        assert!(Texture::from_raw(2, 3, vec![0; 18]).is_ok());
+        match Texture::from_raw(2, 3, vec![0; 12]) {
+            Err(TextureError::InvalidDataSize { expected, actual }) => {
+                assert_eq!((expected, actual), (18, 12))
             }
-            left_pixel = [0, 0, 0];
-            top_left_pixel = [0, 0, 0];
+            _ => panic!("Expected InvalidDataSize"),
         }
+
+        // Overflows even in 64-bit arithmetic.
+        assert!(matches!(
+            Texture::from_raw(u32::MAX, u32::MAX, Vec::new()),
+            Err(TextureError::InvalidDataSize { .. })
+        ));
+        // Wraps to 0 in u32 arithmetic.
+        assert!(matches!(
+            Texture::from_raw(1 << 16, 1 << 16, Vec::new()),
+            Err(TextureError::InvalidDataSize { .. })
+        ));
     }
 }
@@ -0,0 +1,159 @@
+//! The subset of cgmath used by `geometry`, for builds without std. Methods
+//! mirror cgmath's names, so `geometry` reads the same with either backend.
+
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point2<S> {
+    pub x: S,
+    pub y: S,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector2<S> {
+    pub x: S,
+    pub y: S,
+}
+
+/// Column major 2x2 matrix, like cgmath's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix2<S> {
+    pub x: Vector2<S>,
+    pub y: Vector2<S>,
+}
+
+impl<S> Point2<S> {
+    pub const fn new(x: S, y: S) -> Self {
+        Point2 { x, y }
+    }
+}
+
+impl Point2<f32> {
+    pub fn from_vec(v: Vector2<f32>) -> Self {
+        Point2::new(v.x, v.y)
+    }
+
+    pub fn to_vec(self) -> Vector2<f32> {
+        Vector2::new(self.x, self.y)
+    }
+}
+
+impl<S> Vector2<S> {
+    pub const fn new(x: S, y: S) -> Self {
+        Vector2 { x, y }
+    }
+}
+
+impl Vector2<f32> {
+    pub fn dot(self, other: Vector2<f32>) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn perp_dot(self, other: Vector2<f32>) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn magnitude2(self) -> f32 {
+        self.dot(self)
+    }
+}
+
+pub fn dot(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    a.dot(b)
+}
+
+impl Matrix2<f32> {
+    pub fn new(c0r0: f32, c0r1: f32, c1r0: f32, c1r1: f32) -> Self {
+        Matrix2 {
+            x: Vector2::new(c0r0, c0r1),
+            y: Vector2::new(c1r0, c1r1),
+        }
+    }
+
+    pub fn identity() -> Self {
+        Matrix2::new(1.0, 0.0, 0.0, 1.0)
+    }
+}
+
+impl Sub for Point2<f32> {
+    type Output = Vector2<f32>;
+
+    fn sub(self, other: Point2<f32>) -> Vector2<f32> {
+        Vector2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Add<Vector2<f32>> for Point2<f32> {
+    type Output = Point2<f32>;
+
+    fn add(self, v: Vector2<f32>) -> Point2<f32> {
+        Point2::new(self.x + v.x, self.y + v.y)
+    }
+}
+
+impl Add for Vector2<f32> {
+    type Output = Vector2<f32>;
+
+    fn add(self, other: Vector2<f32>) -> Vector2<f32> {
+        Vector2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Vector2<f32> {
+    type Output = Vector2<f32>;
+
+    fn sub(self, other: Vector2<f32>) -> Vector2<f32> {
+        Vector2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Neg for Vector2<f32> {
+    type Output = Vector2<f32>;
+
+    fn neg(self) -> Vector2<f32> {
+        Vector2::new(-self.x, -self.y)
+    }
+}
+
+impl Mul<Vector2<f32>> for f32 {
+    type Output = Vector2<f32>;
+
+    fn mul(self, v: Vector2<f32>) -> Vector2<f32> {
+        Vector2::new(self * v.x, self * v.y)
+    }
+}
+
+impl Mul<f32> for Vector2<f32> {
+    type Output = Vector2<f32>;
+
+    fn mul(self, s: f32) -> Vector2<f32> {
+        Vector2::new(self.x * s, self.y * s)
+    }
+}
+
+impl Div<f32> for Vector2<f32> {
+    type Output = Vector2<f32>;
+
+    fn div(self, s: f32) -> Vector2<f32> {
+        Vector2::new(self.x / s, self.y / s)
+    }
+}
+
+impl Mul<Vector2<f32>> for Matrix2<f32> {
+    type Output = Vector2<f32>;
+
+    fn mul(self, v: Vector2<f32>) -> Vector2<f32> {
+        self.x * v.x + self.y * v.y
+    }
+}
+
+impl Mul for Matrix2<f32> {
+    type Output = Matrix2<f32>;
+
+    fn mul(self, other: Matrix2<f32>) -> Matrix2<f32> {
+        Matrix2 {
+            x: self * other.x,
+            y: self * other.y,
+        }
+    }
+}
@@ -6,19 +6,58 @@ pub mod utils;
 use crate::renderer_thread::*;
 use crate::ui::block::*;
 use crate::ui::button::*;
+use crate::ui::collapsible::*;
+use crate::ui::easing::*;
+use crate::ui::image::*;
 use crate::ui::label::*;
 use crate::ui::layout::*;
+use crate::ui::palette::*;
+use crate::ui::preset::*;
+use crate::ui::radio::*;
 use crate::ui::slider::*;
 use crate::ui::text_area::*;
 use crate::ui::widget::*;
 
+use glium::texture::Texture2d;
 use glium::{glutin, Surface};
-use mcsdf::font::Font;
+use mcsdf::font::{Font, TextureRenderBatch};
+use mcsdf::geometry::Rect;
+use mcsdf::texture::Texture;
 use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::mem::take;
 use std::rc::Rc;
-use std::sync::mpsc::channel;
+use std::sync::mpsc::{channel, sync_channel, TrySendError};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+// Bytes of every atlas texture uploaded per frame.
+const TEXTURE_UPLOAD_BUDGET: usize = 512 * 1024;
+// Render commands which can wait for the renderer thread. Batches which don't
+// fit are kept and merged with newer ones until there is space again.
+const RENDERER_QUEUE_CAPACITY: usize = 4;
+// Time without any result after which the renderer is reported as stuck.
+const RENDERER_TIMEOUT: Duration = Duration::from_secs(5);
+// Upload text area glyphs one by one as the renderer finishes them, instead of
+// the whole atlas once its batch is done. Streamed glyphs are uploaded within
+// TEXTURE_UPLOAD_BUDGET too, before atlas textures.
+const STREAM_GLYPHS: bool = true;
+
+// Atlas texture shown in the preview, optionally with its packing drawn over.
+fn preview_texture(
+    context: &UITextAreaContext,
+    id: u32,
+    show_packing: bool,
+) -> Option<Rc<Texture2d>> {
+    if !show_packing {
+        return context.get_shared_texture(id);
+    }
+    context.create_packing_texture(id).unwrap_or_else(|e| {
+        println!("Couldn't create packing texture: {:?}", e);
+        None
+    })
+}
 
 fn main() {
     // Create GL objects
@@ -31,9 +70,13 @@ fn main() {
     let window = glutin::window::WindowBuilder::new()
         .with_inner_size(screen_dim)
         .with_title("Multi-channel Signed Distance Fields Font Demo");
-    let context = glutin::ContextBuilder::new().with_vsync(true);
+    let frame_pacing = utils::FramePacing::Vsync;
+    let context =
+        glutin::ContextBuilder::new().with_vsync(frame_pacing == utils::FramePacing::Vsync);
     let display = glium::Display::new(window, context, &event_loop).unwrap();
     let mut current_scale_factor = display.gl_window().window().scale_factor();
+    // Whether the window encodes shader output to sRGB, see `UIColorSpace`.
+    let srgb_target = display.gl_window().get_pixel_format().srgb;
 
     // Create state
     let mut manager = UIWidgetManager::new(UISize {
@@ -66,20 +109,39 @@ fn main() {
 
     // Create UI contexts
     let block_context = Rc::new(UIBlockContext::new(&display));
+    block_context.set_pixel_snapping(Some(current_scale_factor as f32));
+    let image_context = Rc::new(UIImageContext::new(&display));
     let label_context = Rc::new(RefCell::new(UILabelContext::new(&display, font)));
     let button_context = Rc::new(UIButtonContext::new(
         block_context.clone(),
         label_context.clone(),
     ));
+    let collapsible_context = Rc::new(UICollapsibleContext::new(
+        block_context.clone(),
+        label_context.clone(),
+    ));
     let slider_context = Rc::new(UISliderContext::new(
         block_context.clone(),
         label_context.clone(),
     ));
     let text_area_context = Rc::new(RefCell::new(UITextAreaContext::new(
         &display,
+        block_context.clone(),
         text_area_font,
     )));
 
+    // Compile shaders before the first frame.
+    let warm_up_results = [
+        block_context.warm_up(&display),
+        label_context.borrow().warm_up(&display),
+        text_area_context.borrow().warm_up(&display),
+    ];
+    for result in &warm_up_results {
+        if let Err(e) = result {
+            println!("Couldn't warm up UI programs: {:?}", e);
+        }
+    }
+
     // Prepare UI elements styles and common functions.
     let label_style = UILabelStyle {
         size: 16.0,
@@ -87,6 +149,8 @@ fn main() {
         color: [1.0, 1.0, 1.0, 1.0],
         shadow_color: [0.0, 0.0, 0.0, 1.0],
         opacity: 1.0,
+        edge: 0.45,
+        auto_contrast: None,
     };
 
     let label_right_style = UILabelStyle {
@@ -100,6 +164,8 @@ fn main() {
         color: [1.0, 1.0, 1.0, 1.0],
         shadow_color: [0.0, 0.0, 0.0, 1.0],
         opacity: 1.0,
+        edge: 0.45,
+        auto_contrast: None,
     };
 
     let mut text_style = UITextAreaStyle {
@@ -115,6 +181,12 @@ fn main() {
         shadow_alpha: 0.05,
         texture_visibility: 0.0,
         animation: false,
+        shadow_offset: [0.0, 0.0],
+        stroke: None,
+        glow: None,
+        background: None,
+        content_align: UITextAreaAlignment::Center,
+        auto_contrast: None,
     };
 
     let text_area = manager.create(UITextArea::new(
@@ -134,6 +206,7 @@ fn main() {
         Enjoy!"#,
         text_style,
     ));
+    manager.update(text_area, |t| t.set_dynamic(true));
 
     let drawer_block_style = UIBlockStyle {
         alpha: 0.99,
@@ -145,10 +218,16 @@ fn main() {
         right_color: [0.015, 0.015, 0.015],
         inner_shadow: 30.0,
         shade_color: [0.005, 0.005, 0.005],
+        border_width: 1.0,
+        border_color: [0.08, 0.08, 0.08],
+        shadow_color: [0.0, 0.0, 0.0, 0.6],
+        shadow_blur: 24.0,
+        shadow_offset: [0.0, -6.0],
     };
 
     let left_drawer_block = manager.create(UIBlock::new(block_context.clone(), drawer_block_style));
-    let right_drawer_block = manager.create(UIBlock::new(block_context, drawer_block_style));
+    let right_drawer_block =
+        manager.create(UIBlock::new(block_context.clone(), drawer_block_style));
 
     macro_rules! create_styled_label {
         ($text:expr, $style:expr) => {
@@ -173,17 +252,20 @@ fn main() {
     }
 
     macro_rules! create_slider {
-        ($default:expr) => {
-            manager.create(UISlider::new(
+        ($default:expr) => {{
+            let slider = manager.create(UISlider::new(
                 &slider_context,
                 0.0,
                 1.0,
                 1.0 / 256.0,
                 $default,
                 2,
-            ))
-        };
-        ($default:expr, $min:expr, $max:expr, $step:expr, $precision:expr) => {
+                UISliderScale::Linear,
+            ));
+            manager.update(slider, |s| s.set_snap_points(&[0.0, 0.5, 1.0], 4.0));
+            slider
+        }};
+        ($default:expr, $min:expr, $max:expr, $step:expr, $precision:expr, $scale:expr) => {
             manager.create(UISlider::new(
                 &slider_context,
                 $min,
@@ -191,12 +273,19 @@ fn main() {
                 $step,
                 $default,
                 $precision,
+                $scale,
             ))
         };
     }
 
     // Create UI elements
-    let outline_label = create_styled_label!("Outline", title_label_style);
+    let outline_section = manager.create(UICollapsible::new(
+        &collapsible_context,
+        "Outline",
+        title_label_style,
+        50.0,
+        8.0,
+    ));
 
     let red_label = create_label!("red", 0.988, 0.576, 0.576);
     let red_slider = create_slider!(text_style.text_color.r);
@@ -207,6 +296,25 @@ fn main() {
     let blue_label = create_label!("blue", 0.716, 0.708, 0.933);
     let blue_slider = create_slider!(text_style.text_color.b);
 
+    let text_palette = manager.create(UIPalette::new(
+        block_context.clone(),
+        vec![
+            Color::white(),
+            Color::new(0.988, 0.576, 0.576),
+            Color::new(0.735, 0.941, 0.724),
+            Color::new(0.716, 0.708, 0.933),
+            Color::new(0.992, 0.859, 0.482),
+            Color::new(0.6, 0.1, 0.9),
+            Color::new(0.5, 0.5, 0.5),
+            Color::new(0.9, 0.2, 0.2),
+            Color::new(0.2, 0.6, 0.3),
+            Color::new(0.016, 0.404, 0.557),
+            Color::new(0.9, 0.5, 0.1),
+            Color::black(),
+        ],
+        6,
+    ));
+
     let inner_dist_label = create_label!("inner distance");
     let inner_dist_slider = create_slider!(text_style.inner_dist);
 
@@ -216,7 +324,13 @@ fn main() {
     let sharpness_label = create_label!("sharpness");
     let sharpness_slider = create_slider!(text_style.sharpness);
 
-    let shadow_label = create_styled_label!("Shadow", title_label_style);
+    let shadow_section = manager.create(UICollapsible::new(
+        &collapsible_context,
+        "Shadow",
+        title_label_style,
+        50.0,
+        8.0,
+    ));
 
     let shadow_red_label = create_label!("red", 0.988, 0.576, 0.576);
     let shadow_red_slider = create_slider!(text_style.shadow_color.r);
@@ -239,20 +353,70 @@ fn main() {
     let texture_label = create_styled_label!("Texture", title_label_style);
 
     let texture_size_label = create_label!("size");
+    let max_texture_size = text_area_context.borrow().get_max_texture_size() as f32;
     let texture_size_slider = create_slider!(
         text_area_texture_size as f32,
         1024.0,
-        1024.0 * 8.0,
+        max_texture_size.min(1024.0 * 8.0),
         512.0,
-        0
+        0,
+        UISliderScale::Logarithmic
     );
 
     let texture_font_size_label = create_label!("font size");
-    let texture_font_size_slider = create_slider!(text_area_font_size as f32, 16.0, 255.0, 1.0, 0);
+    let texture_font_size_slider = create_slider!(
+        text_area_font_size as f32,
+        16.0,
+        512.0,
+        1.0,
+        0,
+        UISliderScale::Linear
+    );
 
     let texture_shadow_size_label = create_label!("shadow size");
-    let texture_shadow_size_slider =
-        create_slider!(text_area_shadow_size as f32, 1.0, 64.0, 1.0, 0);
+    let texture_shadow_size_slider = create_slider!(
+        text_area_shadow_size as f32,
+        1.0,
+        64.0,
+        1.0,
+        0,
+        UISliderScale::Linear
+    );
+
+    let texture_weight_label = create_label!("weight");
+    let texture_weight_slider =
+        create_slider!(400.0, 100.0, 900.0, 100.0, 0, UISliderScale::Linear);
+
+    // Color sliders show the channel they control, texture size its steps.
+    let channel_gradients = [
+        (red_slider, [1.0, 0.0, 0.0]),
+        (green_slider, [0.0, 1.0, 0.0]),
+        (blue_slider, [0.0, 0.0, 1.0]),
+        (shadow_red_slider, [1.0, 0.0, 0.0]),
+        (shadow_green_slider, [0.0, 1.0, 0.0]),
+        (shadow_blue_slider, [0.0, 0.0, 1.0]),
+    ];
+    for &(slider, color) in &channel_gradients {
+        manager.update(slider, |s| {
+            s.set_track_decoration(UISliderTrackDecoration::Gradient([0.0, 0.0, 0.0], color))
+        });
+    }
+    manager.update(texture_size_slider, |s| {
+        s.set_track_decoration(UISliderTrackDecoration::Ticks)
+    });
+
+    // Texture settings are discrete, so keep their values in sight.
+    for &slider in &[
+        texture_size_slider,
+        texture_font_size_slider,
+        texture_shadow_size_slider,
+        texture_weight_slider,
+    ] {
+        manager.update(slider, |s| s.set_label_mode(UISliderLabelMode::Right));
+    }
+
+    let texture_preview =
+        manager.create(UIImage::new(image_context.clone(), None, UIImageFit::Fit));
 
     let render_stats_label = create_styled_label!("Render stats", title_label_style);
 
@@ -262,20 +426,46 @@ fn main() {
     let render_texture_label = create_label!("Avg. texture copy time:");
     let render_texture_value_label = create_styled_label!("-", label_right_style);
 
+    // Stats change every few frames, so keep their buffers around.
+    manager.update(render_glyph_value_label, |l| l.set_dynamic(true));
+    manager.update(render_texture_value_label, |l| l.set_dynamic(true));
+
     let other_label = create_styled_label!("Other", title_label_style);
 
     let texture_visibility_label = create_label!("texture visibility");
     let texture_visibility_slider = create_slider!(text_style.texture_visibility);
 
     let animation_button = manager.create(UIButton::new(&button_context, "Show animation"));
+    let packing_button = manager.create(UIButton::new(&button_context, "Show packing"));
+    let linear_button = manager.create(UIButton::new(&button_context, "Linear blending"));
+    let save_preset_button = manager.create(UIButton::new(&button_context, "Save preset"));
+    let load_preset_button = manager.create(UIButton::new(&button_context, "Load preset"));
+
+    let alignments = [
+        UITextAreaAlignment::Left,
+        UITextAreaAlignment::Center,
+        UITextAreaAlignment::Right,
+    ];
+    let alignment_radio = manager.create(UIRadioGroup::new(
+        &button_context,
+        &["Left", "Center", "Right"],
+        1,
+    ));
 
     // Create screen layout
-    let main_layout = manager.create(UIMainLayout {
-        padding: 20.0,
-        min_width: 300.0,
-        max_width: 400.0,
-        ratio: 0.3,
-    });
+    let background = manager.create(UIBackground::new(
+        block_context.clone(),
+        [0.02, 0.02, 0.02],
+        [0.03, 0.025, 0.04],
+    ));
+
+    let main_layout = manager.create(UIMainLayout::new(
+        block_context.clone(),
+        300.0,
+        400.0,
+        0.3,
+        20.0,
+    ));
 
     let left_drawer_layout = manager.create(UIRelativeLayout {
         size: UISize {
@@ -338,6 +528,7 @@ fn main() {
     let texture_size_layout = manager.create(slider_layout);
     let texture_font_size_layout = manager.create(slider_layout);
     let texture_shadow_size_layout = manager.create(slider_layout);
+    let texture_weight_layout = manager.create(slider_layout);
 
     let render_glyph_layout = manager.create(hbox_layout);
     let render_texture_layout = manager.create(hbox_layout);
@@ -346,7 +537,8 @@ fn main() {
 
     // Organize views
 
-    manager.root(main_layout);
+    manager.root(background);
+    manager.add_child(background, main_layout);
     manager.add_child(main_layout, left_drawer_layout);
     manager.add_child(left_drawer_layout, left_drawer_block);
 
@@ -366,21 +558,22 @@ fn main() {
 
     // Left drawer
 
-    manager.add_child(left_vbox_layout, outline_label);
-    manager.add_child(left_vbox_layout, red_layout);
-    manager.add_child(left_vbox_layout, green_layout);
-    manager.add_child(left_vbox_layout, blue_layout);
-    manager.add_child(left_vbox_layout, inner_dist_layout);
-    manager.add_child(left_vbox_layout, outer_dist_layout);
-    manager.add_child(left_vbox_layout, sharpness_layout);
-
-    manager.add_child(left_vbox_layout, shadow_label);
-    manager.add_child(left_vbox_layout, shadow_red_layout);
-    manager.add_child(left_vbox_layout, shadow_green_layout);
-    manager.add_child(left_vbox_layout, shadow_blue_layout);
-    manager.add_child(left_vbox_layout, shadow_alpha_layout);
-    manager.add_child(left_vbox_layout, shadow_pos_layout);
-    manager.add_child(left_vbox_layout, shadow_size_layout);
+    manager.add_child(left_vbox_layout, outline_section);
+    manager.add_child(outline_section, red_layout);
+    manager.add_child(outline_section, green_layout);
+    manager.add_child(outline_section, blue_layout);
+    manager.add_child(outline_section, text_palette);
+    manager.add_child(outline_section, inner_dist_layout);
+    manager.add_child(outline_section, outer_dist_layout);
+    manager.add_child(outline_section, sharpness_layout);
+
+    manager.add_child(left_vbox_layout, shadow_section);
+    manager.add_child(shadow_section, shadow_red_layout);
+    manager.add_child(shadow_section, shadow_green_layout);
+    manager.add_child(shadow_section, shadow_blue_layout);
+    manager.add_child(shadow_section, shadow_alpha_layout);
+    manager.add_child(shadow_section, shadow_pos_layout);
+    manager.add_child(shadow_section, shadow_size_layout);
 
     manager.add_child(red_layout, red_slider);
     manager.add_child(red_layout, red_label);
@@ -414,6 +607,8 @@ fn main() {
     manager.add_child(right_vbox_layout, texture_size_layout);
     manager.add_child(right_vbox_layout, texture_font_size_layout);
     manager.add_child(right_vbox_layout, texture_shadow_size_layout);
+    manager.add_child(right_vbox_layout, texture_weight_layout);
+    manager.add_child(right_vbox_layout, texture_preview);
 
     manager.add_child(right_vbox_layout, render_stats_label);
     manager.add_child(right_vbox_layout, render_glyph_layout);
@@ -421,6 +616,11 @@ fn main() {
 
     manager.add_child(right_vbox_layout, other_label);
     manager.add_child(right_vbox_layout, animation_button);
+    manager.add_child(right_vbox_layout, packing_button);
+    manager.add_child(right_vbox_layout, linear_button);
+    manager.add_child(right_vbox_layout, save_preset_button);
+    manager.add_child(right_vbox_layout, load_preset_button);
+    manager.add_child(right_vbox_layout, alignment_radio);
     manager.add_child(right_vbox_layout, texture_visibility_layout);
 
     manager.add_child(texture_visibility_layout, texture_visibility_slider);
@@ -435,50 +635,149 @@ fn main() {
     manager.add_child(texture_shadow_size_layout, texture_shadow_size_slider);
     manager.add_child(texture_shadow_size_layout, texture_shadow_size_label);
 
+    manager.add_child(texture_weight_layout, texture_weight_slider);
+    manager.add_child(texture_weight_layout, texture_weight_label);
+
     // Handle font renderer command queues.
-    let (renderer_command_sender, renderer_command_receiver) = channel();
+    let (renderer_command_sender, renderer_command_receiver) =
+        sync_channel(RENDERER_QUEUE_CAPACITY);
     let (renderer_result_sender, renderer_result_receiver) = channel();
     let renderer_context = RendererContext {
         receiver: renderer_command_receiver,
         sender: renderer_result_sender,
         proxy: event_loop.create_proxy(),
+        thread_pool: None,
+        stream_glyphs: STREAM_GLYPHS,
     };
 
     let mut renderer_thread = Some(thread::spawn(|| {
         renderer_entry_point(renderer_context).expect("Got an error on renderer thread");
     }));
 
-    let mut text = String::new();
-    utils::start_loop(event_loop, move |events| {
+    let mut modifiers = glutin::event::ModifiersState::empty();
+    // Atlas textures of the text area which are still being uploaded.
+    let mut pending_texture_uploads: HashMap<u32, Arc<Mutex<Texture>>> = HashMap::new();
+    let mut pending_glyph_uploads: VecDeque<(u32, Arc<Mutex<Texture>>, Rect<u32>)> =
+        VecDeque::new();
+    let mut preview_texture_id = None;
+    // Batches which didn't fit into the renderer queue yet.
+    let mut unsent_batches: Vec<(&str, TextureRenderBatch)> = Vec::new();
+    let mut renderer_alive_time = Instant::now();
+    let mut renderer_stuck = false;
+    let mut show_packing = false;
+    let presets = UITextAreaPresets::new("presets");
+    let mut next_preset = 0;
+    // Style last given to the text area, which keeps its own while animating.
+    let mut applied_style = text_style;
+
+    utils::start_loop(event_loop, frame_pacing, move |events, scale_factor| {
+        if let Some(scale_factor) = scale_factor {
+            current_scale_factor = scale_factor;
+            block_context.set_pixel_snapping(Some(current_scale_factor as f32));
+        }
+
+        // Streamed glyphs go first, at least one per frame, and leave the rest
+        // of the budget to atlas textures.
+        let mut upload_budget = TEXTURE_UPLOAD_BUDGET;
+        while let Some((id, texture, view)) = pending_glyph_uploads.pop_front() {
+            let bytes = (view.width() * view.height() * 3) as usize;
+            if bytes > upload_budget && upload_budget < TEXTURE_UPLOAD_BUDGET {
+                pending_glyph_uploads.push_front((id, texture, view));
+                break;
+            }
+            upload_budget = upload_budget.saturating_sub(bytes);
+            if let Err(e) = text_area_context.borrow_mut().update_texture_cache_rect(
+                id,
+                &texture.lock().unwrap(),
+                view,
+            ) {
+                println!("Couldn't upload glyph to text area context: {:?}", e);
+            }
+        }
+
+        // Upload a part of every pending atlas texture to avoid frame spikes.
+        for (id, texture) in take(&mut pending_texture_uploads) {
+            let texture_upload_time = Instant::now();
+            let result = text_area_context
+                .borrow_mut()
+                .update_texture_cache_budgeted(id, &texture.lock().unwrap(), upload_budget);
+            manager.update(render_texture_value_label, |l| {
+                l.set_text(&format!("{:?}", texture_upload_time.elapsed()));
+            });
+
+            match result {
+                Ok(true) => {
+                    pending_texture_uploads.insert(id, texture);
+                }
+                Ok(false) => {
+                    let preview = preview_texture(&text_area_context.borrow(), id, show_packing);
+                    manager.update(texture_preview, |i| i.set_texture(preview.clone()));
+                    preview_texture_id = Some(id);
+                }
+                Err(e) => println!("Couldn't upload texture to text area context: {:?}", e),
+            }
+        }
+
         // Update widgets
-        manager.update(text_area, |t| {
-            t.set_style(text_style);
-        });
+        if text_style != applied_style {
+            manager.update(text_area, |t| {
+                t.set_style(text_style);
+            });
+            manager.update(text_palette, |p| p.set_selected(text_style.text_color));
+            applied_style = text_style;
+        }
 
         // Draw scene
         let mut target = display.draw();
-        target.clear_color(0.02, 0.02, 0.02, 1.0);
+        // The backdrop is a widget, clearing only resets the alpha channel.
+        target.clear_color(0.0, 0.0, 0.0, 1.0);
 
         // Render UI
-        manager.render(&mut target);
+        if let Err(e) = manager.render(&mut target) {
+            println!("Couldn't render UI: {:?}", e);
+        }
 
         // Vsync
         target.finish().expect("finish failed");
 
         // Send fonts to renderer thread
         {
-            for batch in label_context.borrow_mut().get_texture_render_batches() {
-                renderer_command_sender
-                    .send(RendererCommand::RenderShapes("label_context".into(), batch))
-                    .expect("Cannot send render shapes to the renderer");
+            let batches = label_context
+                .borrow_mut()
+                .get_texture_render_batches()
+                .into_iter()
+                .map(|batch| ("label_context", batch))
+                .chain(
+                    text_area_context
+                        .borrow_mut()
+                        .get_texture_render_batches()
+                        .into_iter()
+                        .map(|batch| ("text_area_context", batch)),
+                );
+            for (name, batch) in batches {
+                let unsent = unsent_batches.iter_mut().find(|(unsent_name, unsent)| {
+                    *unsent_name == name && Arc::ptr_eq(&unsent.texture, &batch.texture)
+                });
+                match unsent {
+                    Some((_, unsent)) => unsent.allocated_shapes.extend(batch.allocated_shapes),
+                    None => unsent_batches.push((name, batch)),
+                }
             }
-            for batch in text_area_context.borrow_mut().get_texture_render_batches() {
-                renderer_command_sender
-                    .send(RendererCommand::RenderShapes(
-                        "text_area_context".into(),
-                        batch,
-                    ))
-                    .expect("Cannot send render shapes to the renderer");
+
+            // Textures only referenced by their batches were dropped by an
+            // invalidated font, so there is no point in rendering them.
+            unsent_batches.retain(|(_, batch)| Arc::strong_count(&batch.texture) > 1);
+
+            for (name, batch) in take(&mut unsent_batches) {
+                match renderer_command_sender
+                    .try_send(RendererCommand::RenderShapes(name.into(), batch))
+                {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(RendererCommand::RenderShapes(_, batch))) => {
+                        unsent_batches.push((name, batch));
+                    }
+                    Err(e) => println!("Cannot send render shapes to the renderer: {:?}", e),
+                }
             }
         }
 
@@ -486,43 +785,91 @@ fn main() {
         let mut exit = false;
         manager.set_mouse_wheel_delta(None);
 
+        // Keyboard edits the text area, unless another widget took the focus.
+        let text_area_focused = manager.focused().is_none() || manager.is_focused(text_area);
+
         for event in events {
             match event {
                 glutin::event::Event::WindowEvent { event, .. } => match event {
-                    glutin::event::WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
-                        dbg!(scale_factor);
-                        current_scale_factor = *scale_factor
+                    glutin::event::WindowEvent::ModifiersChanged(state) => {
+                        modifiers = *state;
+                        manager.set_shift_pressed(modifiers.shift());
                     }
-                    glutin::event::WindowEvent::ReceivedCharacter(c) => {
-                        if (!c.is_whitespace() && *c != '\x08' && *c != '\x7f') || *c == ' ' {
-                            text.push(*c);
-                        }
+                    glutin::event::WindowEvent::ReceivedCharacter(c)
+                        if text_area_focused
+                            && ((!c.is_whitespace() && !c.is_control()) || *c == ' ') =>
+                    {
                         manager.update(text_area, |t| {
-                            t.set_text(&text);
+                            t.insert_text(c.encode_utf8(&mut [0; 4]));
                         });
                     }
                     glutin::event::WindowEvent::KeyboardInput { input, .. } => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            let update = match input.virtual_keycode {
+                        let pressed = input.state == glutin::event::ElementState::Pressed;
+                        let ui_key = match input.virtual_keycode {
+                            Some(glutin::event::VirtualKeyCode::Left) => Some(UIKey::Left),
+                            Some(glutin::event::VirtualKeyCode::Right) => Some(UIKey::Right),
+                            Some(glutin::event::VirtualKeyCode::Up) => Some(UIKey::Up),
+                            Some(glutin::event::VirtualKeyCode::Down) => Some(UIKey::Down),
+                            _ => None,
+                        };
+                        if let Some(key) = ui_key {
+                            manager.key_input(key, pressed);
+                        }
+
+                        if pressed {
+                            let ctrl = modifiers.ctrl();
+                            match input.virtual_keycode {
                                 Some(glutin::event::VirtualKeyCode::Escape) => {
                                     exit = true;
-                                    false
                                 }
+                                _ if !text_area_focused => (),
                                 Some(glutin::event::VirtualKeyCode::Back) => {
-                                    text.pop();
-                                    true
+                                    manager.update(text_area, |t| t.delete_backward());
+                                }
+                                Some(glutin::event::VirtualKeyCode::Delete) => {
+                                    manager.update(text_area, |t| t.delete_forward());
                                 }
                                 Some(glutin::event::VirtualKeyCode::Return) => {
-                                    text.push('\n');
-                                    true
+                                    manager.update(text_area, |t| t.insert_text("\n"));
+                                }
+                                Some(glutin::event::VirtualKeyCode::Left) => {
+                                    manager.update(text_area, |t| {
+                                        if ctrl {
+                                            t.move_word_left()
+                                        } else {
+                                            t.move_left()
+                                        }
+                                    });
+                                }
+                                Some(glutin::event::VirtualKeyCode::Right) => {
+                                    manager.update(text_area, |t| {
+                                        if ctrl {
+                                            t.move_word_right()
+                                        } else {
+                                            t.move_right()
+                                        }
+                                    });
+                                }
+                                Some(glutin::event::VirtualKeyCode::Z) if ctrl => {
+                                    manager.update(text_area, |t| {
+                                        if modifiers.shift() {
+                                            t.redo()
+                                        } else {
+                                            t.undo()
+                                        }
+                                    });
                                 }
-                                _ => false,
+                                Some(glutin::event::VirtualKeyCode::Y) if ctrl => {
+                                    manager.update(text_area, |t| t.redo());
+                                }
+                                Some(glutin::event::VirtualKeyCode::Home) => {
+                                    manager.update(text_area, |t| t.move_line_start());
+                                }
+                                Some(glutin::event::VirtualKeyCode::End) => {
+                                    manager.update(text_area, |t| t.move_line_end());
+                                }
+                                _ => (),
                             };
-                            if update {
-                                manager.update(text_area, |t| {
-                                    t.set_text(&text);
-                                });
-                            }
                         }
                     }
                     glutin::event::WindowEvent::CursorMoved { position, .. } => {
@@ -566,16 +913,22 @@ fn main() {
                 },
                 glutin::event::Event::UserEvent(()) => {
                     while let Ok(result) = renderer_result_receiver.try_recv() {
+                        renderer_alive_time = Instant::now();
+                        renderer_stuck = false;
                         match result {
                             RendererResult::ShapesRendered(name, batch, avg_duration) => {
                                 let texture = batch.texture.lock().unwrap();
-                                let texture_upload_time = Instant::now();
 
                                 if name == "label_context" {
-                                    label_context
+                                    if let Err(e) = label_context
                                         .borrow_mut()
                                         .update_texture_cache(batch.texture_id, &texture)
-                                        .expect("Coudn't upload texture to label context");
+                                    {
+                                        println!(
+                                            "Coudn't upload texture to label context: {:?}",
+                                            e
+                                        );
+                                    }
                                 }
 
                                 if name == "text_area_context" {
@@ -583,16 +936,28 @@ fn main() {
                                         l.set_text(&format!("{:?}", avg_duration));
                                     });
 
-                                    text_area_context
-                                        .borrow_mut()
-                                        .update_texture_cache(batch.texture_id, &texture)
-                                        .expect("Couldn't upload texture to text area context");
-
-                                    manager.update(render_texture_value_label, |l| {
-                                        l.set_text(&format!("{:?}", texture_upload_time.elapsed()));
-                                    });
+                                    // Uploaded in parts over the next frames, unless the
+                                    // glyphs were already uploaded as they were streamed.
+                                    if !STREAM_GLYPHS {
+                                        text_area_context
+                                            .borrow_mut()
+                                            .restart_texture_upload(batch.texture_id);
+                                    }
+                                    pending_texture_uploads
+                                        .insert(batch.texture_id, batch.texture.clone());
+                                }
+                            }
+                            RendererResult::GlyphRendered {
+                                name,
+                                texture_id,
+                                texture,
+                                view,
+                            } => {
+                                if name == "text_area_context" {
+                                    pending_glyph_uploads.push_back((texture_id, texture, view));
                                 }
                             }
+                            RendererResult::Shutdown | RendererResult::Heartbeat => {}
                         }
                     }
                 }
@@ -600,6 +965,14 @@ fn main() {
             };
         }
 
+        if !renderer_stuck && renderer_alive_time.elapsed() > RENDERER_TIMEOUT {
+            println!(
+                "Renderer thread didn't respond for {:?}, it may be stuck",
+                renderer_alive_time.elapsed()
+            );
+            renderer_stuck = true;
+        }
+
         // Handle font style
         macro_rules! handle_font_style_slider {
             ($slider:expr, $name:ident, $map:expr) => {
@@ -632,6 +1005,16 @@ fn main() {
             text_style.text_color.g,
             v
         ));
+        let mut palette_color = None;
+        manager.poll_events(text_palette, |e| match e {
+            UIPaletteEvent::Selected(color) => palette_color = Some(*color),
+        });
+        if let Some(color) = palette_color {
+            text_style.text_color = color;
+            manager.update(red_slider, |s| s.set_value(color.r));
+            manager.update(green_slider, |s| s.set_value(color.g));
+            manager.update(blue_slider, |s| s.set_value(color.b));
+        }
         handle_font_style_slider!(inner_dist_slider, inner_dist, |v: f32| v);
         handle_font_style_slider!(outer_dist_slider, outer_dist, |v: f32| v);
         handle_font_style_slider!(sharpness_slider, sharpness, |v: f32| v);
@@ -669,6 +1052,10 @@ fn main() {
                 });
                 if let Some(v) = value {
                     text_area_context.borrow_mut().$func(v);
+                    // Atlas ids start over, so queued parts of the old
+                    // atlas must not end up in the new cache.
+                    pending_texture_uploads.clear();
+                    pending_glyph_uploads.clear();
                     manager.update(text_area, |t| {
                         t.invalidate();
                     });
@@ -679,6 +1066,7 @@ fn main() {
         handle_texture_setting!(texture_size_slider, set_texture_size);
         handle_texture_setting!(texture_font_size_slider, set_font_size);
         handle_texture_setting!(texture_shadow_size_slider, set_shadow_size);
+        handle_texture_setting!(texture_weight_slider, set_font_weight);
 
         manager.poll_events(animation_button, |e| match e {
             UIButtonEvent::Toggled(toggled) => {
@@ -689,11 +1077,125 @@ fn main() {
             }
         });
 
+        let mut packing_toggled = false;
+        manager.poll_events(packing_button, |e| match e {
+            UIButtonEvent::Toggled(toggled) => {
+                show_packing = *toggled;
+                packing_toggled = true;
+            }
+        });
+        if let (true, Some(id)) = (packing_toggled, preview_texture_id) {
+            let preview = preview_texture(&text_area_context.borrow(), id, show_packing);
+            manager.update(texture_preview, |i| i.set_texture(preview.clone()));
+        }
+
+        let mut color_space = None;
+        manager.poll_events(linear_button, |e| match e {
+            UIButtonEvent::Toggled(true) => {
+                color_space = Some(UIColorSpace::Linear { srgb_target })
+            }
+            UIButtonEvent::Toggled(false) => color_space = Some(UIColorSpace::Srgb),
+        });
+        if let Some(color_space) = color_space {
+            block_context.set_color_space(color_space);
+            image_context.set_color_space(color_space);
+            label_context.borrow().set_color_space(color_space);
+            text_area_context.borrow().set_color_space(color_space);
+        }
+
+        // Buttons toggle, so every click counts as a press.
+        let mut save_preset = false;
+        manager.poll_events(save_preset_button, |e| match e {
+            UIButtonEvent::Toggled(_) => save_preset = true,
+        });
+        if save_preset {
+            let count = presets.list().map_or(0, |names| names.len());
+            let name = format!("preset{}", count + 1);
+            match presets.save(&name, &text_style) {
+                Ok(()) => println!("Saved preset {}", name),
+                Err(e) => println!("Couldn't save preset {}: {:?}", name, e),
+            }
+        }
+
+        let mut load_preset = false;
+        manager.poll_events(load_preset_button, |e| match e {
+            UIButtonEvent::Toggled(_) => load_preset = true,
+        });
+        let loaded_style = if load_preset {
+            match presets.list() {
+                Ok(names) if !names.is_empty() => {
+                    let name = &names[next_preset % names.len()];
+                    next_preset += 1;
+                    match presets.load(name) {
+                        Ok(style) => Some(style),
+                        Err(e) => {
+                            println!("Couldn't load preset {}: {:?}", name, e);
+                            None
+                        }
+                    }
+                }
+                Ok(_) => None,
+                Err(e) => {
+                    println!("Couldn't list presets: {:?}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        if let Some(style) = loaded_style {
+            text_style = style;
+            applied_style = style;
+            manager.update(text_area, |t| {
+                t.animate_to(style, Duration::from_millis(600), UIEasing::default())
+            });
+
+            macro_rules! sync_slider {
+                ($slider:expr, $value:expr) => {
+                    manager.update($slider, |s| s.set_value($value));
+                };
+            }
+            sync_slider!(red_slider, style.text_color.r);
+            sync_slider!(green_slider, style.text_color.g);
+            sync_slider!(blue_slider, style.text_color.b);
+            sync_slider!(inner_dist_slider, style.inner_dist);
+            sync_slider!(outer_dist_slider, style.outer_dist);
+            sync_slider!(sharpness_slider, style.sharpness);
+            sync_slider!(shadow_red_slider, style.shadow_color.r);
+            sync_slider!(shadow_green_slider, style.shadow_color.g);
+            sync_slider!(shadow_blue_slider, style.shadow_color.b);
+            sync_slider!(shadow_pos_slider, style.shadow_pos);
+            sync_slider!(shadow_size_slider, style.shadow_size);
+            sync_slider!(shadow_alpha_slider, style.shadow_alpha);
+            sync_slider!(texture_visibility_slider, style.texture_visibility);
+            manager.update(text_palette, |p| p.set_selected(style.text_color));
+            manager.update(animation_button, |b| b.set_toggled(style.animation));
+            if let Some(index) = alignments.iter().position(|a| *a == style.content_align) {
+                manager.update(alignment_radio, |r| r.set_selected(index));
+            }
+        }
+
+        manager.poll_events(alignment_radio, |e| match e {
+            UIRadioEvent::Selected(index) => {
+                text_style = UITextAreaStyle {
+                    content_align: alignments[*index],
+                    ..text_style
+                };
+            }
+        });
+
         return if exit {
             renderer_command_sender
                 .send(RendererCommand::Exit)
                 .expect("Coudn't terminate renderer thread before exit.");
 
+            // Results still in flight hold textures, release them before joining.
+            for result in renderer_result_receiver.iter() {
+                if let RendererResult::Shutdown = result {
+                    break;
+                }
+            }
+
             renderer_thread
                 .take()
                 .unwrap()
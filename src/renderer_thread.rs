@@ -1,14 +1,25 @@
 use glium::glutin::event_loop::EventLoopProxy;
 use mcsdf::font::TextureRenderBatch;
-use mcsdf::renderer::render_shape;
+use mcsdf::geometry::Rect;
+use mcsdf::renderer::{render_shape, render_shape_debug, DebugMode};
+use mcsdf::shape::AllocatedShape;
+use mcsdf::texture::Texture;
 use rayon::prelude::*;
-use std::sync::mpsc::{Receiver, RecvError, Sender};
-use std::time::Instant;
+use rayon::ThreadPool;
+use std::sync::mpsc::{Receiver, RecvError, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub struct RendererContext {
     pub receiver: Receiver<RendererCommand>,
     pub sender: Sender<RendererResult>,
     pub proxy: EventLoopProxy<()>,
+    /// Pool rendering shapes, e.g. to limit the number of cores used for SDF
+    /// generation. Rayon's global pool is used when not set.
+    pub thread_pool: Option<ThreadPool>,
+    /// Reports every shape with `GlyphRendered` as soon as it's finished,
+    /// instead of only whole batches, so glyphs can be shown progressively.
+    pub stream_glyphs: bool,
 }
 
 pub enum RendererCommand {
@@ -18,26 +29,105 @@ pub enum RendererCommand {
 
 pub enum RendererResult {
     ShapesRendered(String, TextureRenderBatch, std::time::Duration),
+    /// A single shape of a batch, sent in the order shapes finish when
+    /// `stream_glyphs` is set. It's already written to `texture` at `view`.
+    /// `ShapesRendered` still follows once the whole batch is done.
+    GlyphRendered {
+        name: String,
+        texture_id: u32,
+        texture: Arc<Mutex<Texture>>,
+        view: Rect<u32>,
+    },
+    /// Last result sent, after the current batch was finished on `Exit`.
+    Shutdown,
+    /// Sent every `HEARTBEAT_INTERVAL` while there is nothing to render.
+    Heartbeat,
 }
 
+// Calls `on_rendered` for every shape as soon as it's finished, from the
+// thread which rendered it.
+fn render_shapes<F>(shapes: &mut [AllocatedShape], debug_mode: Option<DebugMode>, on_rendered: F)
+where
+    F: Fn(&AllocatedShape) + Sync,
+{
+    shapes.par_iter_mut().for_each(|shape| {
+        match debug_mode {
+            Some(mode) => render_shape_debug(shape, mode),
+            None => render_shape(shape),
+        }
+        on_rendered(shape);
+    });
+}
+
+/// How often an idle renderer reports that it is still alive.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
 #[allow(clippy::needless_pass_by_value)]
 pub fn renderer_entry_point(context: RendererContext) -> Result<(), RecvError> {
     println!("Renderer thread started");
     loop {
-        let command = context.receiver.recv()?;
+        let command = match context.receiver.recv_timeout(HEARTBEAT_INTERVAL) {
+            Ok(command) => command,
+            Err(RecvTimeoutError::Timeout) => {
+                context
+                    .sender
+                    .send(RendererResult::Heartbeat)
+                    .unwrap_or_else(|_| {
+                        println!("Coudn't send renderer heartbeat");
+                    });
+                context.proxy.send_event(()).unwrap_or_else(|_| {
+                    println!("Coudn't wakeup main thread!");
+                });
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return Err(RecvError),
+        };
         match command {
             RendererCommand::RenderShapes(name, mut batch) => {
-                let time = {
-                    let mut texture_mutex = batch.texture.lock().unwrap();
-                    let render_time = Instant::now();
-                    let texture_lock = texture_mutex.lock();
-
-                    batch.allocated_shapes.par_iter_mut().for_each(|shape| {
-                        render_shape(shape, &texture_lock);
+                let render_time = Instant::now();
+                let shapes = &mut batch.allocated_shapes;
+                let debug_mode = batch.debug_mode;
+                let texture = &batch.texture;
+                let texture_id = batch.texture_id;
+                let stream_glyphs = context.stream_glyphs;
+                let sender = &context.sender;
+                // The proxy isn't shared between threads on every platform.
+                let proxy = Mutex::new(context.proxy.clone());
+                let on_rendered = |shape: &AllocatedShape| {
+                    if !stream_glyphs {
+                        return;
+                    }
+                    texture.lock().unwrap().write_view(&shape.texture_view);
+                    sender
+                        .send(RendererResult::GlyphRendered {
+                            name: name.clone(),
+                            texture_id,
+                            texture: texture.clone(),
+                            view: shape.texture_view.get_view(),
+                        })
+                        .unwrap_or_else(|_| {
+                            println!("Coudn't send rendered glyph result");
+                        });
+                    proxy.lock().unwrap().send_event(()).unwrap_or_else(|_| {
+                        println!("Coudn't wakeup main thread!");
                     });
-
-                    render_time.elapsed() / batch.allocated_shapes.len() as u32
                 };
+                match &context.thread_pool {
+                    Some(thread_pool) => {
+                        thread_pool.install(|| render_shapes(shapes, debug_mode, on_rendered))
+                    }
+                    None => render_shapes(shapes, debug_mode, on_rendered),
+                }
+                let time = render_time.elapsed() / batch.allocated_shapes.len() as u32;
+
+                // Shapes render without the texture, it's locked only for copying.
+                // Streamed glyphs were copied as they finished.
+                if !context.stream_glyphs {
+                    let mut texture = batch.texture.lock().unwrap();
+                    for shape in &batch.allocated_shapes {
+                        texture.write_view(&shape.texture_view);
+                    }
+                }
 
                 context
                     .sender
@@ -48,6 +138,12 @@ pub fn renderer_entry_point(context: RendererContext) -> Result<(), RecvError> {
             }
             RendererCommand::Exit => {
                 println!("Closing renderer thread...");
+                context
+                    .sender
+                    .send(RendererResult::Shutdown)
+                    .unwrap_or_else(|_| {
+                        println!("Coudn't send renderer shutdown result");
+                    });
                 break;
             }
         };
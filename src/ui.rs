@@ -1,7 +1,13 @@
 pub mod block;
 pub mod button;
+pub mod collapsible;
+pub mod easing;
+pub mod image;
 pub mod label;
 pub mod layout;
+pub mod palette;
+pub mod preset;
+pub mod radio;
 pub mod slider;
 pub mod text_area;
 pub mod widget;
@@ -1,10 +1,14 @@
-use crate::ui::widget::{UILayout, UISize, UIWidget};
+use crate::ui::widget::{
+    ui_draw_parameters, UIColorSpace, UILayout, UISize, UIWidget, DISABLED_OPACITY,
+};
 use glium::backend::Facade;
-use glium::draw_parameters::DrawParameters;
 use glium::index::PrimitiveType;
+use glium::texture::Texture2d;
+use glium::uniforms::EmptyUniforms;
 use glium::{
-    implement_vertex, program, uniform, Blend, Frame, IndexBuffer, Program, Surface, VertexBuffer,
+    implement_vertex, program, uniform, DrawError, IndexBuffer, Program, Surface, VertexBuffer,
 };
+use std::cell::Cell;
 use std::rc::Rc;
 
 #[derive(Copy, Clone)]
@@ -24,6 +28,9 @@ pub struct UIBlockContext {
     program: Program,
     vertex_buffer: VertexBuffer<UIBlockVertex>,
     index_buffer: IndexBuffer<u16>,
+    write_alpha: Cell<bool>,
+    pixel_snapping: Cell<Option<f32>>,
+    color_space: Cell<UIColorSpace>,
 }
 
 impl UIBlockContext {
@@ -42,15 +49,16 @@ impl UIBlockContext {
             uniform vec2 uScreen;
             uniform vec2 uPosition;
             uniform vec2 uSize;
+            uniform float uShadowMargin;
 
             void main() {
-                vec2 sharpness = vec2(uSharpness);
+                vec2 margin = vec2(uSharpness + uShadowMargin);
                 vec2 radius = vec2(uRadius);
-                vec2 blockSize = uSize + 2.0 * sharpness;
+                vec2 blockSize = uSize + 2.0 * margin;
                 vec2 size = pos / uScreen * blockSize;
-                vec2 offset = (uPosition - sharpness) / uScreen;
+                vec2 offset = (uPosition - margin) / uScreen;
                 gl_Position = vec4((size + offset) * 2.0 - 1.0, 0.0, 1.0);
-                vPos = pos * blockSize - sharpness - radius;
+                vPos = pos * blockSize - margin - radius;
                 vMask = uSize - 2.0 * radius;
             }
         "#,
@@ -72,18 +80,60 @@ impl UIBlockContext {
             uniform vec3 uRightColor;
             uniform float uInnerShadow;
             uniform vec3 uShadeColor;
+            uniform float uBorderWidth;
+            uniform vec3 uBorderColor;
+            uniform vec4 uShadowColor;
+            uniform float uShadowBlur;
+            uniform vec2 uShadowOffset;
+            uniform bool uLinear;
+            uniform bool uEncodeSrgb;
+
+            vec3 toLinear(vec3 c) {
+                if (!uLinear) {
+                    return c;
+                }
+                return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+            }
+
+            vec3 toOutput(vec3 c) {
+                if (!uEncodeSrgb) {
+                    return c;
+                }
+                return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+            }
 
             void main() {
                 vec2 mask = clamp(vPos, vec2(0.0), vMask);
                 float dist = length(vPos - mask);
                 float area = 1.0 - clamp((dist - uRadius) / uSharpness, 0.0, 1.0);
                 float shade = smoothstep(uInnerShadow, 0.0, dist);
-                vec3 c = mix(uLeftColor, uRightColor, smoothstep(uLeftOffset, uRightOffset, vPos.x));
-                c = mix(uShadeColor, c, shade);
-                color = vec4(c, area * uAlpha);
+                vec3 c = mix(toLinear(uLeftColor), toLinear(uRightColor),
+                             smoothstep(uLeftOffset, uRightOffset, vPos.x));
+                c = mix(toLinear(uShadeColor), c, shade);
+                if (uBorderWidth > 0.0) {
+                    float border = clamp((dist - uRadius + uBorderWidth) / uSharpness, 0.0, 1.0);
+                    c = mix(c, toLinear(uBorderColor), border);
+                }
+
+                float alpha = area * uAlpha;
+                if (uShadowBlur > 0.0) {
+                    vec2 shadowPos = vPos - uShadowOffset;
+                    vec2 shadowMask = clamp(shadowPos, vec2(0.0), vMask);
+                    float shadowDist = length(shadowPos - shadowMask);
+                    float shadow = 1.0 - smoothstep(uRadius - uShadowBlur * 0.5,
+                                                    uRadius + uShadowBlur * 0.5, shadowDist);
+                    float shadowAlpha = shadow * uShadowColor.a * uAlpha * (1.0 - alpha);
+                    float totalAlpha = alpha + shadowAlpha;
+                    if (totalAlpha > 0.0) {
+                        c = (c * alpha + toLinear(uShadowColor.rgb) * shadowAlpha) / totalAlpha;
+                    }
+                    alpha = totalAlpha;
+                }
+                color = vec4(toOutput(c), alpha);
             }
         "#,
-        }).expect("Cannot create program for UIBlock");
+        })
+        .expect("Cannot create program for UIBlock");
 
         let vertex_buffer = VertexBuffer::immutable(
             facade,
@@ -104,11 +154,58 @@ impl UIBlockContext {
             program,
             vertex_buffer,
             index_buffer,
+            write_alpha: Cell::new(false),
+            pixel_snapping: Cell::new(None),
+            color_space: Cell::new(UIColorSpace::default()),
         }
     }
+
+    /// Makes widgets drawn with this context write their coverage into the
+    /// target alpha channel, leaving premultiplied output for compositing.
+    pub fn set_write_alpha(&self, write_alpha: bool) {
+        self.write_alpha.set(write_alpha);
+    }
+
+    pub fn get_write_alpha(&self) -> bool {
+        self.write_alpha.get()
+    }
+
+    /// Rounds block edges to device pixels of a display with `scale_factor`
+    /// device pixels per UI unit, so thin blocks aren't blurred across two
+    /// pixels. Disabled with `None`, which is the default.
+    pub fn set_pixel_snapping(&self, scale_factor: Option<f32>) {
+        self.pixel_snapping.set(scale_factor);
+    }
+
+    pub fn get_pixel_snapping(&self) -> Option<f32> {
+        self.pixel_snapping.get()
+    }
+
+    /// Color space the shaders of this context blend in. See `UIColorSpace`.
+    pub fn set_color_space(&self, color_space: UIColorSpace) {
+        self.color_space.set(color_space);
+    }
+
+    pub fn get_color_space(&self) -> UIColorSpace {
+        self.color_space.get()
+    }
+
+    /// Draws once into a 1x1 offscreen target, so the driver finishes compiling
+    /// the shader at init instead of stalling the first frame.
+    pub fn warm_up<F: ?Sized + Facade>(&self, facade: &F) -> Result<(), DrawError> {
+        let texture =
+            Texture2d::empty(facade, 1, 1).expect("Cannot create warm up texture for block");
+        texture.as_surface().draw(
+            &self.vertex_buffer,
+            &self.index_buffer,
+            &self.program,
+            &EmptyUniforms,
+            &ui_draw_parameters(self.write_alpha.get()),
+        )
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct UIBlockStyle {
     pub alpha: f32,
     pub radius: f32,
@@ -119,17 +216,37 @@ pub struct UIBlockStyle {
     pub right_color: [f32; 3],
     pub inner_shadow: f32,
     pub shade_color: [f32; 3],
+    pub border_width: f32,
+    pub border_color: [f32; 3],
+    /// Outer shadow drawn behind the block when `shadow_blur` is positive.
+    /// The offset uses UI coordinates, so a negative y moves it down.
+    pub shadow_color: [f32; 4],
+    pub shadow_blur: f32,
+    pub shadow_offset: [f32; 2],
 }
 
 #[derive(Clone)]
 pub struct UIBlock {
     context: Rc<UIBlockContext>,
     style: UIBlockStyle,
+    enabled: bool,
+    snap: bool,
 }
 
 impl UIBlock {
     pub fn new(context: Rc<UIBlockContext>, style: UIBlockStyle) -> Self {
-        Self { context, style }
+        Self {
+            context,
+            style,
+            enabled: true,
+            snap: true,
+        }
+    }
+
+    /// Opts the block out of the context's pixel snapping. Blocks whose layout
+    /// is animated by fractional amounts would otherwise move in 1px steps.
+    pub fn set_snap(&mut self, snap: bool) {
+        self.snap = snap;
     }
 
     pub fn set_style(&mut self, style: UIBlockStyle) {
@@ -140,49 +257,162 @@ impl UIBlock {
         self.style
     }
 
-    pub fn render_styled(
+    pub fn render_styled<S: Surface>(
         &self,
-        frame: &mut Frame,
+        frame: &mut S,
         layout: UILayout,
         style: UIBlockStyle,
         screen: UISize,
-    ) {
+    ) -> Result<(), DrawError> {
+        let layout = match self.context.pixel_snapping.get() {
+            Some(scale_factor) if self.snap => snap_layout(layout, scale_factor),
+            _ => layout,
+        };
         let screen = [screen.width, screen.height];
+        let (linear, encode_srgb) = self.context.color_space.get().uniforms();
         let limit = layout.width.min(layout.height) / 2.0;
+        let shadow_margin = if style.shadow_blur > 0.0 {
+            style.shadow_blur
+                + style.shadow_offset[0]
+                    .abs()
+                    .max(style.shadow_offset[1].abs())
+        } else {
+            0.0
+        };
 
-        frame
-            .draw(
-                &self.context.vertex_buffer,
-                &self.context.index_buffer,
-                &self.context.program,
-                &uniform! {
-                    uAlpha: style.alpha,
-                    uRadius: style.radius.min(limit),
-                    uSharpness: style.sharpness.min(limit),
-                    uSize: [layout.width, layout.height],
-                    uScreen: screen,
-                    uPosition: [layout.left, layout.top],
-                    uLeftOffset: style.left_offset,
-                    uLeftColor: style.left_color,
-                    uRightOffset: style.right_offset,
-                    uRightColor: style.right_color,
-                    uInnerShadow: style.inner_shadow,
-                    uShadeColor: style.shade_color,
-                },
-                &DrawParameters {
-                    blend: Blend::alpha_blending(),
-                    color_mask: (true, true, true, false),
-                    ..Default::default()
-                },
-            )
-            .expect("Cannot draw UIBlock");
+        frame.draw(
+            &self.context.vertex_buffer,
+            &self.context.index_buffer,
+            &self.context.program,
+            &uniform! {
+                uAlpha: style.alpha,
+                uRadius: style.radius.min(limit),
+                uSharpness: style.sharpness.min(limit),
+                uSize: [layout.width, layout.height],
+                uScreen: screen,
+                uPosition: [layout.left, layout.top],
+                uLeftOffset: style.left_offset,
+                uLeftColor: style.left_color,
+                uRightOffset: style.right_offset,
+                uRightColor: style.right_color,
+                uInnerShadow: style.inner_shadow,
+                uShadeColor: style.shade_color,
+                uBorderWidth: style.border_width,
+                uBorderColor: style.border_color,
+                uShadowColor: style.shadow_color,
+                uShadowBlur: style.shadow_blur,
+                uShadowOffset: style.shadow_offset,
+                uShadowMargin: shadow_margin,
+                uLinear: linear,
+                uEncodeSrgb: encode_srgb,
+            },
+            &ui_draw_parameters(self.context.write_alpha.get()),
+        )
+    }
+}
+
+// Snaps edges rather than the size, so blocks sharing an edge stay adjacent.
+fn snap_layout(layout: UILayout, scale_factor: f32) -> UILayout {
+    let snap = |value: f32| (value * scale_factor).round() / scale_factor;
+    let left = snap(layout.left);
+    let top = snap(layout.top);
+    UILayout {
+        left,
+        top,
+        width: snap(layout.left + layout.width) - left,
+        height: snap(layout.top + layout.height) - top,
     }
 }
 
 impl UIWidget for UIBlock {
     type Event = ();
 
-    fn render(&self, frame: &mut Frame, layout: UILayout, screen: UISize) {
-        self.render_styled(frame, layout, self.style, screen);
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let style = if self.enabled {
+            self.style
+        } else {
+            UIBlockStyle {
+                alpha: self.style.alpha * DISABLED_OPACITY,
+                ..self.style
+            }
+        };
+        self.render_styled(frame, layout, style, screen)
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+// ============ Background ==========================================================
+
+/// Screen filling backdrop with a solid color or a horizontal two-stop
+/// gradient. Meant as the root widget: every child gets the whole layout.
+pub struct UIBackground {
+    block: UIBlock,
+}
+
+impl UIBackground {
+    pub fn new(context: Rc<UIBlockContext>, left_color: [f32; 3], right_color: [f32; 3]) -> Self {
+        let block = UIBlock::new(
+            context,
+            UIBlockStyle {
+                alpha: 1.0,
+                radius: 0.0,
+                sharpness: 1.0,
+                left_offset: 0.0,
+                left_color,
+                right_offset: 0.0,
+                right_color,
+                inner_shadow: 1.0,
+                shade_color: left_color,
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
+            },
+        );
+        Self { block }
+    }
+
+    pub fn solid(context: Rc<UIBlockContext>, color: [f32; 3]) -> Self {
+        Self::new(context, color, color)
+    }
+
+    pub fn set_colors(&mut self, left_color: [f32; 3], right_color: [f32; 3]) {
+        self.block.set_style(UIBlockStyle {
+            left_color,
+            right_color,
+            ..self.block.get_style()
+        });
+    }
+}
+
+impl UIWidget for UIBackground {
+    type Event = ();
+
+    fn layout(&self, layout: UILayout, children: &mut [UILayout]) {
+        for child in children.iter_mut() {
+            *child = layout;
+        }
+    }
+
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let style = UIBlockStyle {
+            right_offset: layout.width,
+            ..self.block.get_style()
+        };
+        self.block.render_styled(frame, layout, style, screen)
     }
 }
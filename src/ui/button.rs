@@ -1,9 +1,9 @@
 use crate::ui::block::{UIBlock, UIBlockContext, UIBlockStyle};
 use crate::ui::label::{UILabel, UILabelAlignment, UILabelContext, UILabelStyle};
 use crate::ui::layout::UIScaleLayout;
-use crate::ui::widget::{UIFrameInput, UILayout, UIPoint, UISize, UIWidget};
+use crate::ui::widget::{UIFrameInput, UILayout, UIPoint, UISize, UIWidget, DISABLED_OPACITY};
 use crate::utils::*;
-use glium::Frame;
+use glium::{DrawError, Surface};
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::time::Instant;
@@ -32,6 +32,7 @@ pub struct UIButton {
     pressed: bool,
     active: bool,
     toggled: bool,
+    enabled: bool,
     hover_from: f32,
     hover_to: f32,
     hover_time: Instant,
@@ -39,7 +40,7 @@ pub struct UIButton {
 
 impl UIButton {
     pub fn new(context: &Rc<UIButtonContext>, title: &str) -> Self {
-        let block = UIBlock::new(
+        let mut block = UIBlock::new(
             context.block_context.clone(),
             UIBlockStyle {
                 alpha: 0.95,
@@ -51,8 +52,15 @@ impl UIButton {
                 radius: 4.0,
                 inner_shadow: 10.0,
                 shade_color: [0.0, 0.0, 0.0],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
             },
         );
+        // Scaled on hover.
+        block.set_snap(false);
 
         let label = UILabel::new(
             context.label_context.clone(),
@@ -61,8 +69,7 @@ impl UIButton {
                 size: 0.0,
                 align: UILabelAlignment::Center,
                 color: [0.0, 0.0, 0.0, 1.0],
-                shadow_color: [0.0, 0.0, 0.0, 1.0],
-                opacity: 1.0,
+                ..Default::default()
             },
         );
 
@@ -73,12 +80,17 @@ impl UIButton {
             pressed: false,
             active: false,
             toggled: false,
+            enabled: true,
             hover_from: 0.0,
             hover_to: 0.0,
             hover_time: Instant::now(),
         }
     }
 
+    pub fn set_toggled(&mut self, toggled: bool) {
+        self.toggled = toggled;
+    }
+
     fn calc_layout(&self, layout: UILayout) -> UILayout {
         let scale = 1.0 + 0.1 * self.hover_value();
         let scale_layout = UIScaleLayout {
@@ -91,9 +103,7 @@ impl UIButton {
                 top: 0.5,
             },
         };
-        let mut result = [UILayout::zero()];
-        scale_layout.layout(layout, &mut result);
-        result[0]
+        scale_layout.transform(layout)
     }
 
     fn hover_value(&self) -> f32 {
@@ -110,17 +120,23 @@ pub enum UIButtonEvent {
 impl UIWidget for UIButton {
     type Event = UIButtonEvent;
 
-    fn render(&self, frame: &mut Frame, layout: UILayout, screen: UISize) {
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
         let scale = 1.0 + 0.1 * self.hover_value();
         let hover_value = self.hover_value();
         let pressed_value = if self.active { 1.0 } else { 0.0 };
         let toggle_value = if self.toggled { 1.0 } else { 0.1 };
+        let opacity = if self.enabled { 1.0 } else { DISABLED_OPACITY };
 
         let scale_layout = self.calc_layout(layout);
         let size = [scale_layout.width, scale_layout.height];
 
         let style = UIBlockStyle {
-            alpha: 0.95,
+            alpha: 0.95 * opacity,
             sharpness: 1.0,
             left_offset: 0.0,
             left_color: [
@@ -133,9 +149,15 @@ impl UIWidget for UIButton {
             radius: 4.0 + 2.0 * hover_value,
             inner_shadow: 10.0 + 10.0 * pressed_value,
             shade_color: [pressed_value, pressed_value, pressed_value],
+            border_width: 0.0,
+            border_color: [0.0, 0.0, 0.0],
+            shadow_color: [0.0, 0.0, 0.0, 0.0],
+            shadow_blur: 0.0,
+            shadow_offset: [0.0, 0.0],
         };
 
-        self.block.render_styled(frame, scale_layout, style, screen);
+        self.block
+            .render_styled(frame, scale_layout, style, screen)?;
         let label_style = UILabelStyle {
             size: 25.0 * scale,
             color: [
@@ -144,10 +166,18 @@ impl UIWidget for UIButton {
                 0.11 * hover_value + 0.11 / toggle_value,
                 1.0,
             ],
+            opacity,
             ..self.label.get_style()
         };
         self.label
-            .render_styled(frame, scale_layout, label_style, screen);
+            .render_styled(frame, scale_layout, label_style, screen)
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.active = false;
+        }
     }
 
     fn update_input(
@@ -0,0 +1,202 @@
+use crate::ui::block::{UIBlock, UIBlockContext, UIBlockStyle};
+use crate::ui::label::{UILabel, UILabelContext, UILabelStyle};
+use crate::ui::widget::{UIFrameInput, UILayout, UISize, UIWidget};
+use crate::utils::*;
+use glium::{DrawError, Surface};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+pub struct UICollapsibleContext {
+    block_context: Rc<UIBlockContext>,
+    label_context: Rc<RefCell<UILabelContext>>,
+}
+
+impl UICollapsibleContext {
+    pub fn new(
+        block_context: Rc<UIBlockContext>,
+        label_context: Rc<RefCell<UILabelContext>>,
+    ) -> Self {
+        Self {
+            block_context,
+            label_context,
+        }
+    }
+}
+
+/// Section with a clickable header which stacks its children below it and
+/// hides them when collapsed. Measures its animated height so that vertical
+/// layouts reflow around it.
+pub struct UICollapsible {
+    label: UILabel,
+    chevron: UIBlock,
+    header_height: f32,
+    item_height: f32,
+    vpadding: f32,
+    expanded: bool,
+    pressed: bool,
+    active: bool,
+    expand_from: f32,
+    expand_to: f32,
+    expand_time: Instant,
+}
+
+impl UICollapsible {
+    pub fn new(
+        context: &Rc<UICollapsibleContext>,
+        title: &str,
+        label_style: UILabelStyle,
+        item_height: f32,
+        vpadding: f32,
+    ) -> Self {
+        let label = UILabel::new(context.label_context.clone(), title, label_style);
+
+        let chevron = UIBlock::new(
+            context.block_context.clone(),
+            UIBlockStyle {
+                alpha: 0.95,
+                sharpness: 1.0,
+                radius: 2.0,
+                left_offset: 0.0,
+                left_color: [0.016, 0.404, 0.557],
+                right_offset: 12.0,
+                right_color: [0.6, 0.1, 0.9],
+                inner_shadow: 1.0,
+                shade_color: [0.0, 0.0, 0.0],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
+            },
+        );
+
+        Self {
+            label,
+            chevron,
+            header_height: item_height,
+            item_height,
+            vpadding,
+            expanded: true,
+            pressed: false,
+            active: false,
+            expand_from: 1.0,
+            expand_to: 1.0,
+            expand_time: Instant::now(),
+        }
+    }
+
+    pub fn is_expanded(&self) -> bool {
+        self.expanded
+    }
+
+    pub fn set_expanded(&mut self, expanded: bool) {
+        if self.expanded != expanded {
+            self.expand_from = self.expand_value();
+            self.expand_to = if expanded { 1.0 } else { 0.0 };
+            self.expand_time = Instant::now();
+            self.expanded = expanded;
+        }
+    }
+
+    fn expand_value(&self) -> f32 {
+        let animation = (self.expand_time.elapsed_seconds() * 8.0).min(1.0) as f32;
+        let t = (self.expand_to - self.expand_from) * animation + self.expand_from;
+        1.0 - (t - 1.0).powf(2.0)
+    }
+
+    fn calc_header_layout(&self, layout: UILayout) -> UILayout {
+        UILayout {
+            top: layout.top + layout.height - self.header_height,
+            height: self.header_height,
+            ..layout
+        }
+    }
+
+    fn calc_chevron_layout(&self, header_layout: UILayout) -> UILayout {
+        // Square while collapsed, flattens into a bar when expanded.
+        let width = 12.0;
+        let height = 12.0 - 8.0 * self.expand_value();
+        UILayout {
+            left: header_layout.left + 4.0,
+            top: header_layout.top + (header_layout.height - height) / 2.0,
+            width,
+            height,
+        }
+    }
+}
+
+pub enum UICollapsibleEvent {
+    Toggled(bool),
+}
+
+impl UIWidget for UICollapsible {
+    type Event = UICollapsibleEvent;
+
+    fn measure(&self, children: &[UISize]) -> UISize {
+        let body_height = children.len() as f32 * (self.item_height + self.vpadding);
+        UISize {
+            width: 0.0,
+            height: self.header_height + body_height * self.expand_value(),
+        }
+    }
+
+    fn layout(&self, layout: UILayout, children: &mut [UILayout]) {
+        let expand_value = self.expand_value();
+        let height = self.item_height * expand_value;
+        let mut top = self.calc_header_layout(layout).top;
+
+        for child in children.iter_mut() {
+            top -= self.vpadding * expand_value + height;
+            *child = UILayout {
+                left: layout.left,
+                top,
+                width: layout.width,
+                height,
+            };
+        }
+    }
+
+    fn children_visible(&self) -> bool {
+        self.expand_value() > 0.0
+    }
+
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let header_layout = self.calc_header_layout(layout);
+        self.chevron
+            .render(frame, self.calc_chevron_layout(header_layout), screen)?;
+        self.label.render(frame, header_layout, screen)
+    }
+
+    fn update_input(
+        &mut self,
+        layout: UILayout,
+        frame_input: UIFrameInput,
+        events: &mut Vec<UICollapsibleEvent>,
+    ) {
+        let hover = self
+            .calc_header_layout(layout)
+            .is_inside(frame_input.mouse_pos);
+        let pressed = frame_input.left_mouse_button_pressed;
+
+        if !self.pressed && pressed && hover {
+            self.active = true;
+        }
+
+        if self.active && !pressed {
+            if hover {
+                let expanded = !self.expanded;
+                self.set_expanded(expanded);
+                events.push(UICollapsibleEvent::Toggled(expanded));
+            }
+            self.active = false;
+        }
+
+        self.pressed = pressed;
+    }
+}
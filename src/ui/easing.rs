@@ -0,0 +1,380 @@
+use crate::ui::block::{UIBlock, UIBlockContext, UIBlockStyle};
+use crate::ui::widget::{UIFrameInput, UILayout, UIPoint, UISize, UIWidget, DISABLED_OPACITY};
+use cgmath::Point2;
+use glium::{DrawError, Surface};
+use mcsdf::geometry::Curve;
+use std::rc::Rc;
+
+/// Easing function from `(0, 0)` to `(1, 1)` shaped by two control points.
+/// It's drawn as two quadratic curves joined halfway between the control
+/// points, which closely follows a cubic bezier with the same control polygon.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UIEasing {
+    pub c1: [f32; 2],
+    pub c2: [f32; 2],
+}
+
+impl Default for UIEasing {
+    fn default() -> Self {
+        UIEasing {
+            c1: [0.25, 0.1],
+            c2: [0.25, 1.0],
+        }
+    }
+}
+
+impl UIEasing {
+    pub fn linear() -> Self {
+        UIEasing {
+            c1: [1.0 / 3.0, 1.0 / 3.0],
+            c2: [2.0 / 3.0, 2.0 / 3.0],
+        }
+    }
+
+    pub fn curves(&self) -> [Curve; 2] {
+        let c1 = Point2::new(self.c1[0], self.c1[1]);
+        let c2 = Point2::new(self.c2[0], self.c2[1]);
+        let middle = Point2::new((c1.x + c2.x) / 2.0, (c1.y + c2.y) / 2.0);
+        [
+            Curve::new(Point2::new(0.0, 0.0), c1, middle),
+            Curve::new(middle, c2, Point2::new(1.0, 1.0)),
+        ]
+    }
+
+    /// Eased progress for `x` in `0..=1`. When the curve turns back
+    /// horizontally, any of its points at `x` may be used.
+    pub fn value(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        let curves = self.curves();
+        let curve = if x <= curves[0].p2.x {
+            curves[0]
+        } else {
+            curves[1]
+        };
+
+        let mut min_t = 0.0;
+        let mut max_t = 1.0;
+        for _ in 0..24 {
+            let t = (min_t + max_t) / 2.0;
+            if (curve.point(t).x < x) == (curve.p0.x <= curve.p2.x) {
+                min_t = t;
+            } else {
+                max_t = t;
+            }
+        }
+        curve.point((min_t + max_t) / 2.0).y
+    }
+}
+
+pub enum UIEasingEditorEvent {
+    Changed(UIEasing),
+    Finished(UIEasing),
+}
+
+// Vertical range shown by the editor, leaving room for overshooting curves.
+const MIN_Y: f32 = -0.25;
+const MAX_Y: f32 = 1.25;
+const HANDLE_RADIUS: f32 = 6.0;
+const CURVE_SAMPLES: usize = 24;
+const POLYGON_SAMPLES: usize = 10;
+
+/// Unit square with a draggable control polygon which edits a `UIEasing`.
+/// The curve is visualized by sampling it.
+pub struct UIEasingEditor {
+    frame: UIBlock,
+    dot: UIBlock,
+    handle: UIBlock,
+    easing: UIEasing,
+    hover: Option<usize>,
+    drag: Option<usize>,
+    pressed: bool,
+    enabled: bool,
+}
+
+impl UIEasingEditor {
+    pub fn new(context: Rc<UIBlockContext>, easing: UIEasing) -> Self {
+        let frame = UIBlock::new(
+            context.clone(),
+            UIBlockStyle {
+                alpha: 0.95,
+                sharpness: 1.0,
+                radius: 4.0,
+                left_offset: 0.0,
+                left_color: [0.05, 0.05, 0.05],
+                right_offset: 0.0,
+                right_color: [0.05, 0.05, 0.05],
+                inner_shadow: 2.0,
+                shade_color: [0.02, 0.02, 0.02],
+                border_width: 1.0,
+                border_color: [0.2, 0.2, 0.2],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
+            },
+        );
+
+        let mut dot = UIBlock::new(
+            context.clone(),
+            UIBlockStyle {
+                alpha: 0.9,
+                sharpness: 1.0,
+                radius: 1.5,
+                left_offset: 0.0,
+                left_color: [0.7, 0.7, 0.7],
+                right_offset: 0.0,
+                right_color: [0.7, 0.7, 0.7],
+                inner_shadow: 1.0,
+                shade_color: [0.7, 0.7, 0.7],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
+            },
+        );
+        // Samples lie between pixels, snapping would make the curve jagged.
+        dot.set_snap(false);
+
+        let mut handle = UIBlock::new(
+            context,
+            UIBlockStyle {
+                alpha: 0.95,
+                sharpness: 1.0,
+                radius: HANDLE_RADIUS,
+                left_offset: -HANDLE_RADIUS,
+                left_color: [0.016, 0.404, 0.557],
+                right_offset: HANDLE_RADIUS,
+                right_color: [0.6, 0.1, 0.9],
+                inner_shadow: 8.0,
+                shade_color: [0.0, 0.0, 0.0],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
+            },
+        );
+        handle.set_snap(false);
+
+        Self {
+            frame,
+            dot,
+            handle,
+            easing,
+            hover: None,
+            drag: None,
+            pressed: false,
+            enabled: true,
+        }
+    }
+
+    pub fn get_easing(&self) -> UIEasing {
+        self.easing
+    }
+
+    pub fn set_easing(&mut self, easing: UIEasing) {
+        self.easing = easing;
+    }
+
+    fn opacity(&self) -> f32 {
+        if self.enabled {
+            1.0
+        } else {
+            DISABLED_OPACITY
+        }
+    }
+
+    fn dimmed(&self, style: UIBlockStyle) -> UIBlockStyle {
+        UIBlockStyle {
+            alpha: style.alpha * self.opacity(),
+            ..style
+        }
+    }
+
+    // Size of a unit in pixels and the screen position of (0, 0). The plot
+    // is the largest centered square which fits with the overshoot range.
+    fn calc_plot(&self, layout: UILayout) -> (f32, UIPoint) {
+        let inner = layout.extend(-HANDLE_RADIUS);
+        let unit = inner.width.min(inner.height / (MAX_Y - MIN_Y)).max(0.0);
+        let origin = UIPoint {
+            left: inner.left + (inner.width - unit) / 2.0,
+            top: inner.top + (inner.height + (MAX_Y - MIN_Y) * unit) / 2.0 + MIN_Y * unit,
+        };
+        (unit, origin)
+    }
+
+    fn point_to_screen(&self, layout: UILayout, point: Point2<f32>) -> UIPoint {
+        let (unit, origin) = self.calc_plot(layout);
+        UIPoint {
+            left: origin.left + point.x * unit,
+            top: origin.top - point.y * unit,
+        }
+    }
+
+    fn screen_to_point(&self, layout: UILayout, point: UIPoint) -> [f32; 2] {
+        let (unit, origin) = self.calc_plot(layout);
+        let unit = unit.max(1.0);
+        let x = (point.left - origin.left) / unit;
+        let y = (origin.top - point.top) / unit;
+        [x.clamp(0.0, 1.0), y.clamp(MIN_Y, MAX_Y)]
+    }
+
+    fn handles(&self) -> [Point2<f32>; 2] {
+        [
+            Point2::new(self.easing.c1[0], self.easing.c1[1]),
+            Point2::new(self.easing.c2[0], self.easing.c2[1]),
+        ]
+    }
+
+    fn render_dot<S: Surface>(
+        &self,
+        frame: &mut S,
+        block: &UIBlock,
+        center: UIPoint,
+        style: UIBlockStyle,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let layout = UILayout {
+            left: center.left - style.radius,
+            top: center.top - style.radius,
+            width: 2.0 * style.radius,
+            height: 2.0 * style.radius,
+        };
+        block.render_styled(frame, layout, self.dimmed(style), screen)
+    }
+}
+
+impl UIWidget for UIEasingEditor {
+    type Event = UIEasingEditorEvent;
+
+    fn measure(&self, _children: &[UISize]) -> UISize {
+        UISize {
+            width: 0.0,
+            height: 160.0,
+        }
+    }
+
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        // Unit square
+        let (unit, origin) = self.calc_plot(layout);
+        let frame_layout = UILayout {
+            left: origin.left,
+            top: origin.top - unit,
+            width: unit,
+            height: unit,
+        };
+        self.frame.render_styled(
+            frame,
+            frame_layout,
+            self.dimmed(self.frame.get_style()),
+            screen,
+        )?;
+
+        // Control polygon
+        let handles = self.handles();
+        let polygon_style = UIBlockStyle {
+            alpha: 0.4,
+            radius: 1.0,
+            ..self.dot.get_style()
+        };
+        let lines = [
+            (Point2::new(0.0, 0.0), handles[0]),
+            (handles[1], Point2::new(1.0, 1.0)),
+        ];
+        for (from, to) in lines.iter() {
+            for index in 0..=POLYGON_SAMPLES {
+                let t = index as f32 / POLYGON_SAMPLES as f32;
+                let point = Point2::new(from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t);
+                let center = self.point_to_screen(layout, point);
+                self.render_dot(frame, &self.dot, center, polygon_style, screen)?;
+            }
+        }
+
+        // Curve
+        for curve in self.easing.curves().iter() {
+            for index in 0..=CURVE_SAMPLES {
+                let t = index as f32 / CURVE_SAMPLES as f32;
+                let center = self.point_to_screen(layout, curve.point(t));
+                self.render_dot(frame, &self.dot, center, self.dot.get_style(), screen)?;
+            }
+        }
+
+        // Handles
+        for (index, handle) in handles.iter().enumerate() {
+            let active = self.drag.or(self.hover) == Some(index);
+            let value = if self.drag == Some(index) { 1.0 } else { 0.0 };
+            let style = UIBlockStyle {
+                radius: if active {
+                    HANDLE_RADIUS * 1.3
+                } else {
+                    HANDLE_RADIUS
+                },
+                shade_color: [value, value, value],
+                ..self.handle.get_style()
+            };
+            let center = self.point_to_screen(layout, *handle);
+            self.render_dot(frame, &self.handle, center, style, screen)?;
+        }
+
+        Ok(())
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.hover = None;
+            self.drag = None;
+        }
+    }
+
+    fn update_input(
+        &mut self,
+        layout: UILayout,
+        frame_input: UIFrameInput,
+        events: &mut Vec<UIEasingEditorEvent>,
+    ) {
+        let pressed = frame_input.left_mouse_button_pressed;
+        let mouse_pos = frame_input.mouse_pos;
+
+        // The second handle wins when they overlap, as it's drawn on top.
+        let handles = self.handles();
+        self.hover = (0..handles.len()).rev().find(|index| {
+            let center = self.point_to_screen(layout, handles[*index]);
+            let distance = (mouse_pos.left - center.left).hypot(mouse_pos.top - center.top);
+            distance <= HANDLE_RADIUS * 1.5
+        });
+
+        if !self.pressed && pressed {
+            self.drag = self.hover;
+        }
+
+        if let Some(index) = self.drag {
+            let point = self.screen_to_point(layout, mouse_pos);
+            let mut easing = self.easing;
+            if index == 0 {
+                easing.c1 = point;
+            } else {
+                easing.c2 = point;
+            }
+            if easing != self.easing {
+                self.easing = easing;
+                events.push(UIEasingEditorEvent::Changed(easing));
+            }
+            if !pressed {
+                self.drag = None;
+                events.push(UIEasingEditorEvent::Finished(easing));
+            }
+        }
+
+        self.pressed = pressed;
+    }
+
+    fn captures_input(&self) -> bool {
+        self.drag.is_some()
+    }
+}
@@ -0,0 +1,487 @@
+use crate::ui::widget::{ui_draw_parameters, UIColorSpace, UILayout, UISize, UIWidget};
+use glium::backend::Facade;
+use glium::index::PrimitiveType;
+use glium::texture::Texture2d;
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+use glium::{
+    implement_vertex, program, uniform, DrawError, IndexBuffer, Program, Surface, VertexBuffer,
+};
+use std::cell::Cell;
+use std::rc::Rc;
+
+#[derive(Copy, Clone)]
+struct UIImageVertex {
+    pos: [f32; 2],
+}
+
+impl UIImageVertex {
+    fn new(x: f32, y: f32) -> Self {
+        UIImageVertex { pos: [x, y] }
+    }
+}
+
+implement_vertex!(UIImageVertex, pos);
+
+pub struct UIImageContext {
+    program: Program,
+    vertex_buffer: VertexBuffer<UIImageVertex>,
+    index_buffer: IndexBuffer<u16>,
+    write_alpha: Cell<bool>,
+    color_space: Cell<UIColorSpace>,
+}
+
+impl UIImageContext {
+    #[allow(clippy::redundant_closure)]
+    pub fn new<F: ?Sized + Facade>(facade: &F) -> Self {
+        let program = program!(facade, 140 => {
+        vertex: r#"
+            #version 140
+
+            in vec2 pos;
+            out vec2 vCoord;
+
+            uniform vec2 uScreen;
+            uniform vec2 uPosition;
+            uniform vec2 uSize;
+            uniform vec2 uCoordOffset;
+            uniform vec2 uCoordScale;
+
+            void main() {
+                vec2 position = (uPosition + pos * uSize) / uScreen;
+                gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+                vCoord = uCoordOffset + pos * uCoordScale;
+            }
+        "#,
+        fragment: r#"
+            #version 140
+
+            in vec2 vCoord;
+            out vec4 color;
+
+            uniform sampler2D uTexture;
+            uniform vec4 uTint;
+            uniform bool uLinear;
+            uniform bool uEncodeSrgb;
+
+            vec3 toLinear(vec3 c) {
+                if (!uLinear) {
+                    return c;
+                }
+                return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+            }
+
+            vec3 toOutput(vec3 c) {
+                if (!uEncodeSrgb) {
+                    return c;
+                }
+                return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+            }
+
+            void main() {
+                vec4 t = texture(uTexture, vCoord);
+                color = vec4(toOutput(toLinear(t.rgb) * toLinear(uTint.rgb)), t.a * uTint.a);
+            }
+        "#,
+        })
+        .expect("Cannot create program for UIImage");
+
+        let vertex_buffer = VertexBuffer::immutable(
+            facade,
+            &[
+                UIImageVertex::new(0.0, 0.0),
+                UIImageVertex::new(0.0, 1.0),
+                UIImageVertex::new(1.0, 1.0),
+                UIImageVertex::new(1.0, 0.0),
+            ],
+        )
+        .expect("Cannot create vertex buffer for UIImage");
+
+        let index_buffer =
+            IndexBuffer::immutable(facade, PrimitiveType::TrianglesList, &[0, 1, 2, 0, 2, 3])
+                .expect("Cannot create index buffer for UIImage");
+
+        Self {
+            program,
+            vertex_buffer,
+            index_buffer,
+            write_alpha: Cell::new(false),
+            color_space: Cell::new(UIColorSpace::default()),
+        }
+    }
+
+    /// Makes widgets drawn with this context write their coverage into the
+    /// target alpha channel, leaving premultiplied output for compositing.
+    pub fn set_write_alpha(&self, write_alpha: bool) {
+        self.write_alpha.set(write_alpha);
+    }
+
+    pub fn get_write_alpha(&self) -> bool {
+        self.write_alpha.get()
+    }
+
+    /// Color space the shaders of this context blend in. See `UIColorSpace`.
+    pub fn set_color_space(&self, color_space: UIColorSpace) {
+        self.color_space.set(color_space);
+    }
+
+    pub fn get_color_space(&self) -> UIColorSpace {
+        self.color_space.get()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UIImageFit {
+    /// Fills the layout ignoring the image aspect ratio.
+    Stretch,
+    /// Largest size which shows the whole image, centered.
+    Fit,
+    /// Smallest size which covers the layout, cropping the image evenly.
+    Fill,
+}
+
+pub struct UIImage {
+    context: Rc<UIImageContext>,
+    texture: Option<Rc<Texture2d>>,
+    fit: UIImageFit,
+    tint: [f32; 4],
+}
+
+impl UIImage {
+    pub fn new(
+        context: Rc<UIImageContext>,
+        texture: Option<Rc<Texture2d>>,
+        fit: UIImageFit,
+    ) -> Self {
+        Self {
+            context,
+            texture,
+            fit,
+            tint: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn set_texture(&mut self, texture: Option<Rc<Texture2d>>) {
+        self.texture = texture;
+    }
+
+    pub fn set_fit(&mut self, fit: UIImageFit) {
+        self.fit = fit;
+    }
+
+    pub fn set_tint(&mut self, tint: [f32; 4]) {
+        self.tint = tint;
+    }
+
+    // Returns the drawn rectangle and the texture coordinates offset and scale.
+    fn calc_image_layout(&self, layout: UILayout, texture: &Texture2d) -> (UILayout, [f32; 4]) {
+        let image_width = texture.get_width() as f32;
+        let image_height = texture.get_height().unwrap_or(1) as f32;
+        let image_aspect = image_width / image_height;
+        let layout_aspect = layout.width / layout.height;
+
+        match self.fit {
+            UIImageFit::Stretch => (layout, [0.0, 0.0, 1.0, 1.0]),
+            UIImageFit::Fit => {
+                let image_layout = if image_aspect > layout_aspect {
+                    let height = layout.width / image_aspect;
+                    UILayout {
+                        top: layout.top + (layout.height - height) / 2.0,
+                        height,
+                        ..layout
+                    }
+                } else {
+                    let width = layout.height * image_aspect;
+                    UILayout {
+                        left: layout.left + (layout.width - width) / 2.0,
+                        width,
+                        ..layout
+                    }
+                };
+                (image_layout, [0.0, 0.0, 1.0, 1.0])
+            }
+            UIImageFit::Fill => {
+                let coords = if image_aspect > layout_aspect {
+                    let scale = layout_aspect / image_aspect;
+                    [(1.0 - scale) / 2.0, 0.0, scale, 1.0]
+                } else {
+                    let scale = image_aspect / layout_aspect;
+                    [0.0, (1.0 - scale) / 2.0, 1.0, scale]
+                };
+                (layout, coords)
+            }
+        }
+    }
+}
+
+impl UIWidget for UIImage {
+    type Event = ();
+
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let texture = match &self.texture {
+            Some(texture) => texture,
+            None => return Ok(()),
+        };
+        if layout.width <= 0.0 || layout.height <= 0.0 {
+            return Ok(());
+        }
+
+        let (image_layout, coords) = self.calc_image_layout(layout, texture);
+        let (linear, encode_srgb) = self.context.color_space.get().uniforms();
+
+        frame.draw(
+            &self.context.vertex_buffer,
+            &self.context.index_buffer,
+            &self.context.program,
+            &uniform! {
+                uTexture: &**texture,
+                uTint: self.tint,
+                uScreen: [screen.width, screen.height],
+                uPosition: [image_layout.left, image_layout.top],
+                uSize: [image_layout.width, image_layout.height],
+                uCoordOffset: [coords[0], coords[1]],
+                uCoordScale: [coords[2], coords[3]],
+                uLinear: linear,
+                uEncodeSrgb: encode_srgb,
+            },
+            &ui_draw_parameters(self.context.write_alpha.get()),
+        )
+    }
+}
+
+// ============ Nine-slice ==========================================================
+
+/// Border widths in texture pixels, kept at their pixel size on screen.
+#[derive(Copy, Clone, Debug)]
+pub struct UINineSliceInsets {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+/// Draws a texture as nine regions: corners keep their size, edges stretch
+/// along one axis and the center stretches along both.
+pub struct UINineSlice {
+    context: Rc<UIImageContext>,
+    texture: Rc<Texture2d>,
+    insets: UINineSliceInsets,
+    tint: [f32; 4],
+}
+
+impl UINineSlice {
+    pub fn new(
+        context: Rc<UIImageContext>,
+        texture: Rc<Texture2d>,
+        insets: UINineSliceInsets,
+    ) -> Self {
+        Self {
+            context,
+            texture,
+            insets,
+            tint: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn set_tint(&mut self, tint: [f32; 4]) {
+        self.tint = tint;
+    }
+
+    /// Screen rectangles and texture coordinates (offset and scale) of the nine
+    /// regions, from the bottom left one row by row. Insets are shrunk evenly
+    /// when the layout is smaller than the borders.
+    pub fn calc_regions(
+        layout: UILayout,
+        insets: UINineSliceInsets,
+        texture_size: UISize,
+    ) -> [(UILayout, [f32; 4]); 9] {
+        let scale_x = (layout.width / (insets.left + insets.right)).min(1.0);
+        let scale_y = (layout.height / (insets.top + insets.bottom)).min(1.0);
+
+        let xs = [
+            layout.left,
+            layout.left + insets.left * scale_x,
+            layout.left + layout.width - insets.right * scale_x,
+            layout.left + layout.width,
+        ];
+        let ys = [
+            layout.top,
+            layout.top + insets.bottom * scale_y,
+            layout.top + layout.height - insets.top * scale_y,
+            layout.top + layout.height,
+        ];
+        let us = [
+            0.0,
+            insets.left / texture_size.width,
+            1.0 - insets.right / texture_size.width,
+            1.0,
+        ];
+        let vs = [
+            0.0,
+            insets.bottom / texture_size.height,
+            1.0 - insets.top / texture_size.height,
+            1.0,
+        ];
+
+        let mut regions = [(UILayout::zero(), [0.0; 4]); 9];
+        for row in 0..3 {
+            for column in 0..3 {
+                regions[row * 3 + column] = (
+                    UILayout {
+                        left: xs[column],
+                        top: ys[row],
+                        width: xs[column + 1] - xs[column],
+                        height: ys[row + 1] - ys[row],
+                    },
+                    [
+                        us[column],
+                        vs[row],
+                        us[column + 1] - us[column],
+                        vs[row + 1] - vs[row],
+                    ],
+                );
+            }
+        }
+        regions
+    }
+}
+
+impl UIWidget for UINineSlice {
+    type Event = ();
+
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let texture_size = UISize {
+            width: self.texture.get_width() as f32,
+            height: self.texture.get_height().unwrap_or(1) as f32,
+        };
+
+        // Nearest filtering keeps thin borders from bleeding into the stretched regions.
+        let sampler = self
+            .texture
+            .sampled()
+            .magnify_filter(MagnifySamplerFilter::Nearest)
+            .minify_filter(MinifySamplerFilter::Nearest);
+
+        let (linear, encode_srgb) = self.context.color_space.get().uniforms();
+        for (region, coords) in Self::calc_regions(layout, self.insets, texture_size).iter() {
+            if region.width <= 0.0 || region.height <= 0.0 {
+                continue;
+            }
+
+            frame.draw(
+                &self.context.vertex_buffer,
+                &self.context.index_buffer,
+                &self.context.program,
+                &uniform! {
+                    uTexture: sampler,
+                    uTint: self.tint,
+                    uScreen: [screen.width, screen.height],
+                    uPosition: [region.left, region.top],
+                    uSize: [region.width, region.height],
+                    uCoordOffset: [coords[0], coords[1]],
+                    uCoordScale: [coords[2], coords[3]],
+                    uLinear: linear,
+                    uEncodeSrgb: encode_srgb,
+                },
+                &ui_draw_parameters(self.context.write_alpha.get()),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXTURE: UISize = UISize {
+        width: 32.0,
+        height: 32.0,
+    };
+
+    fn layout(width: f32, height: f32) -> UILayout {
+        UILayout {
+            left: 10.0,
+            top: 20.0,
+            width,
+            height,
+        }
+    }
+
+    fn insets(left: f32, right: f32, top: f32, bottom: f32) -> UINineSliceInsets {
+        UINineSliceInsets {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+
+    // Region edges along x of the bottom row and along y of the left column.
+    fn edges(regions: &[(UILayout, [f32; 4]); 9]) -> (Vec<f32>, Vec<f32>) {
+        let mut xs: Vec<f32> = regions[..3].iter().map(|(r, _)| r.left).collect();
+        xs.push(regions[2].0.left + regions[2].0.width);
+        let mut ys: Vec<f32> = [0, 3, 6].iter().map(|&i| regions[i].0.top).collect();
+        ys.push(regions[6].0.top + regions[6].0.height);
+        (xs, ys)
+    }
+
+    #[test]
+    fn regions_keep_insets() {
+        let regions =
+            UINineSlice::calc_regions(layout(100.0, 50.0), insets(4.0, 6.0, 8.0, 2.0), TEXTURE);
+        let (xs, ys) = edges(&regions);
+        assert_eq!(xs, vec![10.0, 14.0, 104.0, 110.0]);
+        assert_eq!(ys, vec![20.0, 22.0, 62.0, 70.0]);
+        // The bottom left corner samples its inset of the texture.
+        assert_eq!(regions[0].1, [0.0, 0.0, 4.0 / 32.0, 2.0 / 32.0]);
+    }
+
+    #[test]
+    fn insets_shrink_in_small_layouts() {
+        // Insets add up to 20 x 40, twice the layout.
+        let regions =
+            UINineSlice::calc_regions(layout(10.0, 20.0), insets(8.0, 12.0, 10.0, 30.0), TEXTURE);
+        let (xs, ys) = edges(&regions);
+        assert_eq!(xs, vec![10.0, 14.0, 14.0, 20.0]);
+        assert_eq!(ys, vec![20.0, 35.0, 35.0, 40.0]);
+        for (region, _) in regions.iter() {
+            assert!(region.width >= 0.0 && region.height >= 0.0);
+        }
+        // The center is empty, so it's skipped when rendering.
+        assert_eq!(regions[4].0.width, 0.0);
+        assert_eq!(regions[4].0.height, 0.0);
+        // Texture coordinates still cover the full insets.
+        assert_eq!(regions[0].1, [0.0, 0.0, 8.0 / 32.0, 30.0 / 32.0]);
+    }
+
+    #[test]
+    fn zero_insets_stretch_the_center() {
+        for &(width, height) in &[(100.0, 50.0), (0.0, 0.0)] {
+            let regions = UINineSlice::calc_regions(
+                layout(width, height),
+                insets(0.0, 0.0, 0.0, 0.0),
+                TEXTURE,
+            );
+            for (index, (region, coords)) in regions.iter().enumerate() {
+                assert!(region.left.is_finite() && region.top.is_finite());
+                if index == 4 {
+                    assert_eq!((region.width, region.height), (width, height));
+                    assert_eq!(*coords, [0.0, 0.0, 1.0, 1.0]);
+                } else {
+                    assert!(region.width == 0.0 || region.height == 0.0);
+                }
+            }
+        }
+    }
+}
@@ -1,18 +1,23 @@
-use crate::ui::widget::{UILayout, UISize, UIWidget};
+use crate::ui::text_area::Color;
+use crate::ui::widget::{
+    antialias_sharpness, ui_draw_parameters, GlyphRenderPass, UIColorSpace, UILayout, UISize,
+    UIWidget, DISABLED_OPACITY,
+};
 use glium::backend::{Context, Facade};
-use glium::draw_parameters::DrawParameters;
-use glium::index::PrimitiveType;
+use glium::index::{NoIndices, PrimitiveType};
 use glium::texture::{ClientFormat, MipmapsOption, RawImage2d, Texture2d, TextureCreationError};
+use glium::uniforms::EmptyUniforms;
 use glium::{
-    implement_vertex, program, uniform, Blend, Frame, IndexBuffer, Program, Rect as GLRect,
+    implement_vertex, program, uniform, CapabilitiesSource, DrawError, Program, Rect as GLRect,
     Surface, VertexBuffer,
 };
 use mcsdf::font::{Font, GlyphLayout, TextureRenderBatch};
 use mcsdf::geometry::Rect;
 use mcsdf::texture::Texture;
 use std::borrow::Cow;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::mem::replace;
 use std::rc::Rc;
 
 pub struct UILabelContext {
@@ -20,6 +25,10 @@ pub struct UILabelContext {
     program: Program,
     font: Font,
     texture_cache: HashMap<u32, Texture2d>,
+    glyph_runs: HashMap<String, Rc<UILabelGlyphRun>>,
+    glyph_run_limit: usize,
+    write_alpha: Cell<bool>,
+    color_space: Cell<UIColorSpace>,
 }
 
 impl UILabelContext {
@@ -57,6 +66,23 @@ impl UILabelContext {
             uniform vec4 uColor;
             uniform vec4 uShadowColor;
             uniform float uOpacity;
+            uniform float uEdge;
+            uniform bool uLinear;
+            uniform bool uEncodeSrgb;
+
+            vec3 toLinear(vec3 c) {
+                if (!uLinear) {
+                    return c;
+                }
+                return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+            }
+
+            vec3 toOutput(vec3 c) {
+                if (!uEncodeSrgb) {
+                    return c;
+                }
+                return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+            }
 
             float median(float a, float b, float c) {
                 return max(min(a,b), min(max(a,b),c));
@@ -65,9 +91,12 @@ impl UILabelContext {
             void main() {
                 vec4 t = texture(uTexture, vCoord);
                 float d = median(t.r, t.g, t.b);
-                float alpha = smoothstep(0.6, 0.3, d);
-                color = mix(uColor, uShadowColor, alpha);
-                color.a = color.a * smoothstep(0.45 - uSharpness, 0.45 + uSharpness, d) * uOpacity;
+                float alpha = smoothstep(uEdge + 0.15, uEdge - 0.15, d);
+                vec4 textColor = vec4(toLinear(uColor.rgb), uColor.a);
+                vec4 shadowColor = vec4(toLinear(uShadowColor.rgb), uShadowColor.a);
+                color = mix(textColor, shadowColor, alpha);
+                color.rgb = toOutput(color.rgb);
+                color.a = color.a * smoothstep(uEdge - uSharpness, uEdge + uSharpness, d) * uOpacity;
             }
         "#,
         })
@@ -78,7 +107,132 @@ impl UILabelContext {
             program,
             font,
             texture_cache,
+            glyph_runs: HashMap::new(),
+            glyph_run_limit: 256,
+            write_alpha: Cell::new(false),
+            color_space: Cell::new(UIColorSpace::default()),
+        }
+    }
+
+    /// Makes widgets drawn with this context write their coverage into the
+    /// target alpha channel, leaving premultiplied output for compositing.
+    pub fn set_write_alpha(&self, write_alpha: bool) {
+        self.write_alpha.set(write_alpha);
+    }
+
+    pub fn get_write_alpha(&self) -> bool {
+        self.write_alpha.get()
+    }
+
+    /// Color space the shaders of this context blend in. See `UIColorSpace`.
+    pub fn set_color_space(&self, color_space: UIColorSpace) {
+        self.color_space.set(color_space);
+    }
+
+    pub fn get_color_space(&self) -> UIColorSpace {
+        self.color_space.get()
+    }
+
+    /// Same as `UIBlockContext::warm_up`, for the glyph program.
+    pub fn warm_up<F: ?Sized + Facade>(&self, facade: &F) -> Result<(), DrawError> {
+        let texture =
+            Texture2d::empty(facade, 1, 1).expect("Cannot create warm up texture for label");
+        let vertex_buffer =
+            VertexBuffer::new(facade, &[UILabelGlyphVertex::new(0.0, 0.0, 0.0, 0.0); 3])
+                .expect("Cannot create warm up vertex buffer for label");
+        texture.as_surface().draw(
+            &vertex_buffer,
+            NoIndices(PrimitiveType::TrianglesList),
+            &self.program,
+            &EmptyUniforms,
+            &ui_draw_parameters(self.write_alpha.get()),
+        )
+    }
+
+    /// Limits how many distinct texts keep their buffers shared between
+    /// labels. Zero disables sharing.
+    pub fn set_glyph_run_limit(&mut self, limit: usize) {
+        self.glyph_run_limit = limit;
+        self.glyph_runs.clear();
+    }
+
+    // Glyph runs are stored in em units, so labels of any size share them.
+    fn glyph_run(&mut self, text: &str) -> Rc<UILabelGlyphRun> {
+        if let Some(run) = self.glyph_runs.get(text) {
+            return run.clone();
+        }
+
+        let run = Rc::new(self.create_glyph_run(text, None, false));
+
+        if self.glyph_runs.len() >= self.glyph_run_limit {
+            self.glyph_runs.retain(|_, run| Rc::strong_count(run) > 1);
+        }
+        if self.glyph_runs.len() < self.glyph_run_limit {
+            self.glyph_runs.insert(text.into(), run.clone());
+        }
+
+        run
+    }
+
+    // Builds the glyph run, reusing buffers of `previous` where they are big enough
+    // when `dynamic` is set.
+    fn create_glyph_run(
+        &mut self,
+        text: &str,
+        previous: Option<UILabelGlyphRun>,
+        dynamic: bool,
+    ) -> UILabelGlyphRun {
+        let text_layout = self.font.layout_text_block(text);
+        let gl_context = &self.context;
+
+        struct PassData {
+            vertices: Vec<UILabelGlyphVertex>,
+            indices: Vec<u16>,
+        }
+
+        fn update_pass_data(pass_data: &mut PassData, glyph_layout: &GlyphLayout) {
+            let (vertices, indices) = glyph_layout
+                .quad(pass_data.vertices.len() as u16, |pos, tex| {
+                    UILabelGlyphVertex::new(pos.x, pos.y, tex.x, tex.y)
+                });
+            pass_data.vertices.extend_from_slice(&vertices);
+            pass_data.indices.extend_from_slice(&indices);
+        }
+
+        let mut passes = HashMap::<u32, PassData>::new();
+        for glyph_layout in &text_layout.glyph_layouts {
+            let pass_data = passes.entry(glyph_layout.texture_id).or_insert(PassData {
+                vertices: Vec::new(),
+                indices: Vec::new(),
+            });
+            update_pass_data(pass_data, glyph_layout);
         }
+
+        let mut previous_passes = previous.map(|run| run.passes).unwrap_or_default();
+        let mut gl_passes = HashMap::<u32, GlyphRenderPass<UILabelGlyphVertex>>::new();
+        for (id, pass_data) in passes {
+            let vertices = pass_data.vertices.as_slice();
+            let indices = pass_data.indices.as_slice();
+
+            let pass = match previous_passes.remove(&id) {
+                Some(mut pass) if dynamic && pass.fits(vertices, indices) => {
+                    pass.write(vertices, indices);
+                    pass
+                }
+                _ => GlyphRenderPass::new(gl_context, vertices, indices, dynamic),
+            };
+
+            gl_passes.insert(id, pass);
+        }
+
+        UILabelGlyphRun {
+            bounding_box: text_layout.bounding_box,
+            passes: gl_passes,
+        }
+    }
+
+    pub fn get_max_texture_size(&self) -> u32 {
+        self.context.get_capabilities().max_texture_size as u32
     }
 
     pub fn update_texture_cache(
@@ -86,6 +240,11 @@ impl UILabelContext {
         id: u32,
         texture: &Texture,
     ) -> Result<(), TextureCreationError> {
+        let max_size = self.get_max_texture_size();
+        if texture.get_width() > max_size || texture.get_height() > max_size {
+            return Err(TextureCreationError::DimensionsNotSupported);
+        }
+
         let raw_texture = RawImage2d {
             data: Cow::Borrowed(texture.get_data()),
             width: texture.get_width(),
@@ -145,9 +304,9 @@ impl UILabelGlyphVertex {
     }
 }
 
-struct UILabelRenderPass {
-    vertex_buffer: VertexBuffer<UILabelGlyphVertex>,
-    index_buffer: IndexBuffer<u16>,
+struct UILabelGlyphRun {
+    bounding_box: Rect<f32>,
+    passes: HashMap<u32, GlyphRenderPass<UILabelGlyphVertex>>,
 }
 
 #[derive(Copy, Clone)]
@@ -164,28 +323,60 @@ pub struct UILabelStyle {
     pub color: [f32; 4],
     pub shadow_color: [f32; 4],
     pub opacity: f32,
+    /// Distance field value of the glyph edge. Smaller values make text bolder.
+    pub edge: f32,
+    /// Background the label is drawn over. When set, the color is replaced by
+    /// black or white, whichever is more readable on it. Alpha is kept.
+    pub auto_contrast: Option<[f32; 3]>,
+}
+
+impl UILabelStyle {
+    /// Color the text is filled with, taking `auto_contrast` into account.
+    pub fn fill_color(&self) -> [f32; 4] {
+        match self.auto_contrast {
+            Some([r, g, b]) => {
+                let color = Color::readable_on(Color::new(r, g, b));
+                [color.r, color.g, color.b, self.color[3]]
+            }
+            None => self.color,
+        }
+    }
+}
+
+impl Default for UILabelStyle {
+    fn default() -> Self {
+        UILabelStyle {
+            align: UILabelAlignment::Left,
+            size: 16.0,
+            color: [1.0, 1.0, 1.0, 1.0],
+            shadow_color: [0.0, 0.0, 0.0, 1.0],
+            opacity: 1.0,
+            edge: 0.45,
+            auto_contrast: None,
+        }
+    }
 }
 
 pub struct UILabel {
     style: UILabelStyle,
     text: String,
-    bounding_box: Rect<f32>,
-    passes: HashMap<u32, UILabelRenderPass>,
+    dynamic: bool,
+    enabled: bool,
+    run: Rc<UILabelGlyphRun>,
     context: Rc<RefCell<UILabelContext>>,
 }
 
 impl UILabel {
     pub fn new(context: Rc<RefCell<UILabelContext>>, text: &str, style: UILabelStyle) -> Self {
-        let mut label = Self {
+        let run = context.borrow_mut().glyph_run(text);
+        Self {
             context,
-            text: String::new(),
-            bounding_box: Rect::new(0.0, 0.0, 0.0, 0.0),
-            passes: HashMap::new(),
+            text: text.into(),
+            dynamic: false,
+            enabled: true,
+            run,
             style,
-        };
-
-        label.set_text(text);
-        label
+        }
     }
 
     pub fn get_style(&self) -> UILabelStyle {
@@ -197,7 +388,7 @@ impl UILabel {
     }
 
     pub fn get_bounding_box(&self, style: UILabelStyle) -> Rect<f32> {
-        let bb = self.bounding_box;
+        let bb = self.run.bounding_box;
         let size = style.size;
         Rect::new(
             bb.min.x * size,
@@ -226,88 +417,42 @@ impl UILabel {
         self.text = text.into();
 
         let mut context = self.context.borrow_mut();
-        let text_layout = context.font.layout_text_block(text);
-        let gl_context = &context.context;
-
-        struct PassData {
-            vertices: Vec<UILabelGlyphVertex>,
-            indices: Vec<u16>,
-        }
-
-        fn update_pass_data(pass_data: &mut PassData, glyph_layout: &GlyphLayout) {
-            let new_index = pass_data.vertices.len();
-            let scr = glyph_layout.screen_coord;
-            let tex = glyph_layout.texture_coord;
-
-            let tl = UILabelGlyphVertex::new(scr.min.x, scr.max.y, tex.min.x, tex.max.y);
-            let tr = UILabelGlyphVertex::new(scr.max.x, scr.max.y, tex.max.x, tex.max.y);
-            let bl = UILabelGlyphVertex::new(scr.min.x, scr.min.y, tex.min.x, tex.min.y);
-            let br = UILabelGlyphVertex::new(scr.max.x, scr.min.y, tex.max.x, tex.min.y);
-
-            pass_data.vertices.push(tl);
-            pass_data.vertices.push(tr);
-            pass_data.vertices.push(br);
-            pass_data.vertices.push(bl);
-
-            pass_data.indices.push(new_index as u16);
-            pass_data.indices.push((new_index + 1) as u16);
-            pass_data.indices.push((new_index + 2) as u16);
-            pass_data.indices.push((new_index + 2) as u16);
-            pass_data.indices.push((new_index + 3) as u16);
-            pass_data.indices.push(new_index as u16);
-        }
-
-        let mut passes = HashMap::<u32, PassData>::new();
-        for glyph_layout in &text_layout.glyph_layouts {
-            let pass_data = passes.entry(glyph_layout.texture_id).or_insert(PassData {
-                vertices: Vec::new(),
-                indices: Vec::new(),
-            });
-            update_pass_data(pass_data, glyph_layout);
-        }
-
-        let mut gl_passes = HashMap::<u32, UILabelRenderPass>::new();
-        for (id, pass_data) in passes {
-            let vertex_buffer = VertexBuffer::immutable(gl_context, pass_data.vertices.as_slice())
-                .expect("Cannot create vertex buffer for label");
-
-            let index_buffer = IndexBuffer::immutable(
-                gl_context,
-                PrimitiveType::TrianglesList,
-                pass_data.indices.as_slice(),
-            )
-            .expect("Cannot create index buffer for label");
-
-            gl_passes.insert(
-                id,
-                UILabelRenderPass {
-                    vertex_buffer,
-                    index_buffer,
-                },
-            );
-        }
+        self.run = if self.dynamic {
+            let run = replace(&mut self.run, context.glyph_run(""));
+            let previous = Rc::try_unwrap(run).ok();
+            Rc::new(context.create_glyph_run(text, previous, true))
+        } else {
+            context.glyph_run(text)
+        };
+    }
 
-        self.passes = gl_passes;
-        self.bounding_box = text_layout.bounding_box;
+    /// Dynamic labels keep their own buffers and rewrite them in place, which
+    /// suits text that changes every few frames.
+    pub fn set_dynamic(&mut self, dynamic: bool) {
+        self.dynamic = dynamic;
     }
 
-    pub fn render_styled(
+    pub fn render_styled<S: Surface>(
         &self,
-        frame: &mut Frame,
+        frame: &mut S,
         layout: UILayout,
         style: UILabelStyle,
         screen: UISize,
-    ) {
+    ) -> Result<(), DrawError> {
         let mut pos = [layout.left, layout.top];
         let size = [layout.width, layout.height];
         let screen = [screen.width, screen.height];
 
         let context = self.context.borrow_mut();
         let shadow_size = context.font.get_shadow_size();
-        let font_size = context.font.get_font_size();
+        let font_size = context.font.get_font_size_f32();
         let font_sharpness = 0.4;
-        let sharpness =
-            font_sharpness / f32::from(shadow_size) / (style.size / f32::from(font_size));
+        let sharpness = antialias_sharpness(
+            font_sharpness,
+            f32::from(shadow_size),
+            style.size,
+            font_size,
+        );
 
         let bb = self.get_bounding_box(style);
         pos[1] -= (bb.height() - size[1]) / 2.0;
@@ -321,39 +466,62 @@ impl UILabel {
             }
         };
 
-        for (texture_id, pass_data) in &self.passes {
+        let (linear, encode_srgb) = context.color_space.get().uniforms();
+        for (texture_id, pass_data) in &self.run.passes {
             if let Some(texture) = context.get_texture(*texture_id) {
-                frame
-                    .draw(
-                        &pass_data.vertex_buffer,
-                        &pass_data.index_buffer,
-                        &context.program,
-                        &uniform! {
-                            uTexture: texture,
-                            uSharpness: sharpness,
-                            uFontSize: style.size,
-                            uPosition: pos,
-                            uScreen: screen,
-                            uColor: style.color,
-                            uOpacity: style.opacity,
-                            uShadowColor: style.shadow_color
-                        },
-                        &DrawParameters {
-                            blend: Blend::alpha_blending(),
-                            color_mask: (true, true, true, false),
-                            ..Default::default()
-                        },
-                    )
-                    .expect("Cannot draw UILabel pass");
+                frame.draw(
+                    pass_data
+                        .vertex_buffer
+                        .slice(..pass_data.vertex_count)
+                        .expect("Cannot slice vertex buffer for label"),
+                    pass_data
+                        .index_buffer
+                        .slice(..pass_data.index_count)
+                        .expect("Cannot slice index buffer for label"),
+                    &context.program,
+                    &uniform! {
+                        uTexture: texture,
+                        uSharpness: sharpness,
+                        uFontSize: style.size,
+                        uPosition: pos,
+                        uScreen: screen,
+                        uColor: style.fill_color(),
+                        uOpacity: style.opacity,
+                        uEdge: style.edge,
+                        uShadowColor: style.shadow_color,
+                        uLinear: linear,
+                        uEncodeSrgb: encode_srgb
+                    },
+                    &ui_draw_parameters(context.write_alpha.get()),
+                )?;
             }
         }
+
+        Ok(())
     }
 }
 
 impl UIWidget for UILabel {
     type Event = ();
 
-    fn render(&self, frame: &mut Frame, layout: UILayout, screen: UISize) {
-        self.render_styled(frame, layout, self.style, screen)
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let style = if self.enabled {
+            self.style
+        } else {
+            UILabelStyle {
+                opacity: self.style.opacity * DISABLED_OPACITY,
+                ..self.style
+            }
+        };
+        self.render_styled(frame, layout, style, screen)
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
     }
 }
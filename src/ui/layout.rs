@@ -1,6 +1,9 @@
 // ============ Absolute Layout =========================================================
 
-use super::widget::{UILayout, UIPoint, UISize, UIWidget};
+use super::block::{UIBlock, UIBlockContext, UIBlockStyle};
+use super::widget::{UIFrameInput, UILayout, UIPoint, UISize, UIWidget};
+use glium::{DrawError, Surface};
+use std::rc::Rc;
 
 #[derive(Copy, Clone)]
 pub struct UIAbsoluteLayout {
@@ -40,6 +43,57 @@ impl UIWidget for UIRelativeLayout {
     }
 }
 
+// ============ Constraint Layout =========================================================
+
+/// Position of one child edge along an axis of the parent layout.
+#[derive(Copy, Clone, Debug)]
+pub enum UIConstraint {
+    /// Distance from the parent's `left` or `top` edge.
+    Absolute(f32),
+    /// Fraction of the parent's width or height, from its `left` or `top` edge.
+    Relative(f32),
+    /// Distance from the parent's opposite edge, `left + width` or `top + height`.
+    FromOpposite(f32),
+}
+
+impl UIConstraint {
+    fn resolve(self, start: f32, size: f32) -> f32 {
+        match self {
+            UIConstraint::Absolute(offset) => start + offset,
+            UIConstraint::Relative(fraction) => start + size * fraction,
+            UIConstraint::FromOpposite(offset) => start + size - offset,
+        }
+    }
+}
+
+/// Places children by constraining each of their edges, so units can be mixed,
+/// e.g. 100px from the right edge with half of the parent's height. `right` and
+/// `bottom` are the edges at `left + width` and `top + height` of the child,
+/// following `UILayout`. Edges which cross give an empty child.
+#[derive(Copy, Clone, Debug)]
+pub struct UIConstraintLayout {
+    pub left: UIConstraint,
+    pub right: UIConstraint,
+    pub top: UIConstraint,
+    pub bottom: UIConstraint,
+}
+
+impl UIWidget for UIConstraintLayout {
+    type Event = ();
+    fn layout(&self, layout: UILayout, children: &mut [UILayout]) {
+        let left = self.left.resolve(layout.left, layout.width);
+        let right = self.right.resolve(layout.left, layout.width);
+        let top = self.top.resolve(layout.top, layout.height);
+        let bottom = self.bottom.resolve(layout.top, layout.height);
+        for child in children {
+            child.left = left;
+            child.top = top;
+            child.width = (right - left).max(0.0);
+            child.height = (bottom - top).max(0.0);
+        }
+    }
+}
+
 // ============ Scale Layout =========================================================
 
 #[derive(Copy, Clone)]
@@ -48,47 +102,156 @@ pub struct UIScaleLayout {
     pub anchor: UIPoint,
 }
 
-impl UIWidget for UIScaleLayout {
-    type Event = ();
-    fn layout(&self, layout: UILayout, children: &mut [UILayout]) {
+impl UIScaleLayout {
+    /// Scales `layout` about the anchor, given as a fraction of its size. Widgets
+    /// use it to transform their own layout.
+    pub fn transform(&self, layout: UILayout) -> UILayout {
         let origin_left = self.anchor.left * layout.width + layout.left;
         let origin_top = self.anchor.top * layout.height + layout.top;
 
+        UILayout {
+            left: (layout.left - origin_left) * self.scale.width + origin_left,
+            top: (layout.top - origin_top) * self.scale.height + origin_top,
+            width: layout.width * self.scale.width,
+            height: layout.height * self.scale.height,
+        }
+    }
+}
+
+impl UIWidget for UIScaleLayout {
+    type Event = ();
+    fn layout(&self, layout: UILayout, children: &mut [UILayout]) {
         for child in children {
-            child.left = (layout.left - origin_left) * self.scale.width + origin_left;
-            child.top = (layout.top - origin_top) * self.scale.height + origin_top;
-            child.width = layout.width * self.scale.width;
-            child.height = layout.height * self.scale.height;
+            *child = self.transform(layout);
         }
     }
 }
 
 // ============ Main Layout =========================================================
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
+enum UIMainLayoutSplitter {
+    Left,
+    Right,
+}
+
+pub enum UIMainLayoutEvent {
+    /// Drawers collapsed (`true`) or expanded (`false`) with the window width.
+    Collapsed(bool),
+}
+
+#[derive(Clone)]
 pub struct UIMainLayout {
     pub min_width: f32,
     pub max_width: f32,
     pub ratio: f32,
     pub padding: f32,
+    /// Layouts narrower than this collapse the drawers to `rail_width`. Zero
+    /// by default, which never collapses them.
+    pub collapse_width: f32,
+    /// Width of collapsed drawers, e.g. for a rail of icons. Zero hides them.
+    pub rail_width: f32,
+    handle: UIBlock,
+    hover: Option<UIMainLayoutSplitter>,
+    drag: Option<UIMainLayoutSplitter>,
+    pressed: bool,
+    collapsed: bool,
+}
+
+impl UIMainLayout {
+    pub fn new(
+        block_context: Rc<UIBlockContext>,
+        min_width: f32,
+        max_width: f32,
+        ratio: f32,
+        padding: f32,
+    ) -> Self {
+        let handle = UIBlock::new(
+            block_context,
+            UIBlockStyle {
+                alpha: 0.5,
+                radius: 2.0,
+                sharpness: 1.0,
+                left_offset: 0.0,
+                left_color: [0.3, 0.3, 0.3],
+                right_offset: 0.0,
+                right_color: [0.3, 0.3, 0.3],
+                inner_shadow: 1.0,
+                shade_color: [0.3, 0.3, 0.3],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
+            },
+        );
+
+        Self {
+            min_width,
+            max_width,
+            ratio,
+            padding,
+            collapse_width: 0.0,
+            rail_width: 0.0,
+            handle,
+            hover: None,
+            drag: None,
+            pressed: false,
+            collapsed: false,
+        }
+    }
+
+    pub fn is_collapsed(&self) -> bool {
+        self.collapsed
+    }
+
+    fn collapses(&self, layout: UILayout) -> bool {
+        layout.width < self.collapse_width
+    }
+
+    fn drawer_width(&self, layout: UILayout) -> f32 {
+        if self.collapses(layout) {
+            return self.rail_width;
+        }
+        (layout.width * self.ratio)
+            .max(self.min_width)
+            .min(self.max_width)
+    }
+
+    // Thin handle centered in the gap between a drawer and the content.
+    fn handle_layout(&self, layout: UILayout, splitter: UIMainLayoutSplitter) -> UILayout {
+        let drawer_width = self.drawer_width(layout);
+        let width = 4.0;
+        let center = match splitter {
+            UIMainLayoutSplitter::Left => drawer_width - self.padding / 2.0,
+            UIMainLayoutSplitter::Right => layout.width - drawer_width + self.padding / 2.0,
+        };
+
+        UILayout {
+            left: center - width / 2.0,
+            top: layout.height / 2.0 - 20.0,
+            width,
+            height: 40.0,
+        }
+    }
 }
 
 impl UIWidget for UIMainLayout {
-    type Event = ();
+    type Event = UIMainLayoutEvent;
     fn layout(&self, layout: UILayout, children: &mut [UILayout]) {
         if children.len() != 3 {
             panic!("Expected 3 children in main layout!");
         }
 
-        let drawer_width = (layout.width * self.ratio)
-            .max(self.min_width)
-            .min(self.max_width);
+        let drawer_width = self.drawer_width(layout);
+
+        let drawer_content_width = (drawer_width - 2.0 * self.padding).max(0.0);
 
         children[0] = UILayout {
             left: self.padding,
             top: self.padding,
             height: layout.height - 2.0 * self.padding,
-            width: drawer_width - 2.0 * self.padding,
+            width: drawer_content_width,
         };
 
         children[1] = UILayout {
@@ -102,9 +265,87 @@ impl UIWidget for UIMainLayout {
             left: layout.width - drawer_width + self.padding,
             top: self.padding,
             height: layout.height - 2.0 * self.padding,
-            width: drawer_width - 2.0 * self.padding,
+            width: drawer_content_width,
         };
     }
+
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        // Collapsed drawers can't be resized.
+        if self.collapses(layout) {
+            return Ok(());
+        }
+
+        for splitter in &[UIMainLayoutSplitter::Left, UIMainLayoutSplitter::Right] {
+            let active = self.drag.or(self.hover) == Some(*splitter);
+            let style = UIBlockStyle {
+                alpha: if active { 1.0 } else { 0.5 },
+                ..self.handle.get_style()
+            };
+            let handle_layout = self.handle_layout(layout, *splitter);
+            self.handle
+                .render_styled(frame, handle_layout, style, screen)?;
+        }
+
+        Ok(())
+    }
+
+    fn update_input(
+        &mut self,
+        layout: UILayout,
+        frame_input: UIFrameInput,
+        events: &mut Vec<Self::Event>,
+    ) {
+        let pressed = frame_input.left_mouse_button_pressed;
+        let mouse_pos = frame_input.mouse_pos;
+
+        let collapsed = self.collapses(layout);
+        if collapsed != self.collapsed {
+            self.collapsed = collapsed;
+            events.push(UIMainLayoutEvent::Collapsed(collapsed));
+        }
+        if collapsed {
+            self.hover = None;
+            self.drag = None;
+            self.pressed = pressed;
+            return;
+        }
+
+        self.hover = [UIMainLayoutSplitter::Left, UIMainLayoutSplitter::Right]
+            .iter()
+            .copied()
+            .find(|splitter| {
+                self.handle_layout(layout, *splitter)
+                    .extend(self.padding / 4.0)
+                    .is_inside(mouse_pos)
+            });
+
+        if !self.pressed && pressed {
+            self.drag = self.hover;
+        } else if !pressed {
+            self.drag = None;
+        }
+
+        if let Some(splitter) = self.drag {
+            let drawer_width = match splitter {
+                UIMainLayoutSplitter::Left => mouse_pos.left - layout.left + self.padding / 2.0,
+                UIMainLayoutSplitter::Right => {
+                    layout.left + layout.width - mouse_pos.left + self.padding / 2.0
+                }
+            };
+            self.ratio = drawer_width.max(self.min_width).min(self.max_width) / layout.width;
+        }
+
+        self.pressed = pressed;
+    }
+
+    fn captures_input(&self) -> bool {
+        self.drag.is_some()
+    }
 }
 
 // ============ VBox Layout =========================================================
@@ -120,17 +361,27 @@ pub struct UIVBoxLayout {
 impl UIWidget for UIVBoxLayout {
     type Event = ();
     fn layout(&self, layout: UILayout, children: &mut [UILayout]) {
-        let height = ((layout.height - (children.len() + 1) as f32 * self.vpadding)
-            / children.len() as f32)
-            .min(self.max_height)
-            .max(self.min_height);
+        // Children which measure a height keep it, the rest share the remaining space.
+        let measured_height: f32 = children.iter().map(|child| child.height).sum();
+        let flexible_count = children.iter().filter(|child| child.height <= 0.0).count();
+        let height =
+            ((layout.height - measured_height - (children.len() + 1) as f32 * self.vpadding)
+                / flexible_count.max(1) as f32)
+                .min(self.max_height)
+                .max(self.min_height);
 
-        for (index, child) in children.iter_mut().enumerate() {
+        let mut top = layout.top + layout.height;
+        for child in children.iter_mut() {
+            let child_height = if child.height > 0.0 {
+                child.height
+            } else {
+                height
+            };
+            top -= self.vpadding + child_height;
             child.left = layout.left + self.hpadding;
             child.width = layout.width - self.hpadding * 2.0;
-            child.height = height;
-            child.top =
-                layout.top + layout.height - (index as f32 + 1.0) * (self.vpadding + height);
+            child.height = child_height;
+            child.top = top;
         }
     }
 }
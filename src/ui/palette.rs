@@ -0,0 +1,254 @@
+use crate::ui::block::{UIBlock, UIBlockContext, UIBlockStyle};
+use crate::ui::easing::UIEasing;
+use crate::ui::layout::UIScaleLayout;
+use crate::ui::text_area::Color;
+use crate::ui::widget::{UIFrameInput, UILayout, UIPoint, UISize, UIWidget, DISABLED_OPACITY};
+use glium::{DrawError, Surface};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const SWATCH_HEIGHT: f32 = 20.0;
+const SWATCH_SPACING: f32 = 4.0;
+// Growth of a hovered swatch relative to its size.
+const HOVER_SCALE: f32 = 0.2;
+const HOVER_DURATION: Duration = Duration::from_millis(150);
+
+struct UIPaletteSwatch {
+    color: Color,
+    hover: bool,
+    hover_from: f32,
+    hover_to: f32,
+    hover_time: Instant,
+}
+
+impl UIPaletteSwatch {
+    fn hover_value(&self, easing: UIEasing) -> f32 {
+        let t = self.hover_time.elapsed().as_secs_f32() / HOVER_DURATION.as_secs_f32();
+        self.hover_from + (self.hover_to - self.hover_from) * easing.value(t.min(1.0))
+    }
+
+    fn set_hover(&mut self, hover: bool, easing: UIEasing) {
+        if hover != self.hover {
+            self.hover_from = self.hover_value(easing);
+            self.hover_to = if hover { 1.0 } else { 0.0 };
+            self.hover_time = Instant::now();
+            self.hover = hover;
+        }
+    }
+}
+
+/// Grid of clickable color swatches, filled row by row from the top. The
+/// selected swatch is outlined and hovered ones grow.
+pub struct UIPalette {
+    block: UIBlock,
+    swatches: Vec<UIPaletteSwatch>,
+    columns: usize,
+    selected: Option<usize>,
+    easing: UIEasing,
+    active: Option<usize>,
+    pressed: bool,
+    enabled: bool,
+}
+
+impl UIPalette {
+    pub fn new(context: Rc<UIBlockContext>, colors: Vec<Color>, columns: usize) -> Self {
+        assert!(columns > 0, "Palette needs at least one column");
+
+        let mut block = UIBlock::new(
+            context,
+            UIBlockStyle {
+                alpha: 1.0,
+                sharpness: 1.0,
+                radius: 3.0,
+                left_offset: 0.0,
+                left_color: [0.0, 0.0, 0.0],
+                right_offset: 0.0,
+                right_color: [0.0, 0.0, 0.0],
+                inner_shadow: 4.0,
+                shade_color: [0.0, 0.0, 0.0],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.5],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, -1.0],
+            },
+        );
+        // Scaled on hover.
+        block.set_snap(false);
+
+        let swatches = colors
+            .into_iter()
+            .map(|color| UIPaletteSwatch {
+                color,
+                hover: false,
+                hover_from: 0.0,
+                hover_to: 0.0,
+                hover_time: Instant::now(),
+            })
+            .collect();
+
+        Self {
+            block,
+            swatches,
+            columns,
+            selected: None,
+            easing: UIEasing::default(),
+            active: None,
+            pressed: false,
+            enabled: true,
+        }
+    }
+
+    pub fn selected(&self) -> Option<Color> {
+        self.selected.map(|index| self.swatches[index].color)
+    }
+
+    /// Selects the first swatch of exactly `color` without emitting events.
+    /// Other colors, e.g. ones picked elsewhere, clear the selection.
+    pub fn set_selected(&mut self, color: Color) {
+        self.selected = self.swatches.iter().position(|s| s.color == color);
+    }
+
+    /// Curve of the hover animation.
+    pub fn set_easing(&mut self, easing: UIEasing) {
+        self.easing = easing;
+    }
+
+    fn rows(&self) -> usize {
+        self.swatches.len().div_ceil(self.columns)
+    }
+
+    fn calc_swatch_layout(&self, layout: UILayout, index: usize) -> UILayout {
+        let columns = self.columns as f32;
+        let width = (layout.width - (columns - 1.0) * SWATCH_SPACING) / columns;
+        let column = (index % self.columns) as f32;
+        let row = (index / self.columns) as f32;
+        UILayout {
+            left: layout.left + column * (width + SWATCH_SPACING),
+            top: layout.top + layout.height - (row + 1.0) * SWATCH_HEIGHT - row * SWATCH_SPACING,
+            width,
+            height: SWATCH_HEIGHT,
+        }
+    }
+
+    fn render_swatch<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        index: usize,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let swatch = &self.swatches[index];
+        let hover_value = swatch.hover_value(self.easing);
+        let scale = 1.0 + HOVER_SCALE * hover_value;
+        let scale_layout = UIScaleLayout {
+            scale: UISize {
+                width: scale,
+                height: scale,
+            },
+            anchor: UIPoint {
+                left: 0.5,
+                top: 0.5,
+            },
+        };
+
+        let color = [swatch.color.r, swatch.color.g, swatch.color.b];
+        let selected = self.selected == Some(index);
+        let outline = Color::readable_on(swatch.color);
+        let opacity = if self.enabled { 1.0 } else { DISABLED_OPACITY };
+        let style = UIBlockStyle {
+            alpha: opacity,
+            left_color: color,
+            right_color: color,
+            shade_color: [color[0] * 0.8, color[1] * 0.8, color[2] * 0.8],
+            border_width: if selected { 2.0 } else { 0.0 },
+            border_color: [outline.r, outline.g, outline.b],
+            shadow_blur: 4.0 * hover_value,
+            ..self.block.get_style()
+        };
+        self.block.render_styled(
+            frame,
+            scale_layout.transform(self.calc_swatch_layout(layout, index)),
+            style,
+            screen,
+        )
+    }
+}
+
+pub enum UIPaletteEvent {
+    /// Emitted on every click, also when the swatch was already selected.
+    Selected(Color),
+}
+
+impl UIWidget for UIPalette {
+    type Event = UIPaletteEvent;
+
+    fn measure(&self, _children: &[UISize]) -> UISize {
+        let rows = self.rows() as f32;
+        UISize {
+            width: 0.0,
+            height: (rows * (SWATCH_HEIGHT + SWATCH_SPACING) - SWATCH_SPACING).max(0.0),
+        }
+    }
+
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        // Hovered swatches go last, so they grow over their neighbours.
+        let (hovered, rest): (Vec<usize>, Vec<usize>) = (0..self.swatches.len())
+            .partition(|&index| self.swatches[index].hover_value(self.easing) > 0.0);
+        for index in rest.into_iter().chain(hovered) {
+            self.render_swatch(frame, layout, index, screen)?;
+        }
+        Ok(())
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.active = None;
+            for swatch in &mut self.swatches {
+                swatch.set_hover(false, self.easing);
+            }
+        }
+    }
+
+    fn update_input(
+        &mut self,
+        layout: UILayout,
+        frame_input: UIFrameInput,
+        events: &mut Vec<UIPaletteEvent>,
+    ) {
+        let pressed = frame_input.left_mouse_button_pressed;
+        // Hit testing uses unscaled swatches, so a grown one doesn't steal the
+        // neighbour under the cursor.
+        let hovered = (0..self.swatches.len()).find(|&index| {
+            self.calc_swatch_layout(layout, index)
+                .is_inside(frame_input.mouse_pos)
+        });
+
+        let easing = self.easing;
+        for (index, swatch) in self.swatches.iter_mut().enumerate() {
+            swatch.set_hover(hovered == Some(index), easing);
+        }
+
+        if self.active.is_none() && !self.pressed && pressed {
+            self.active = hovered;
+        }
+
+        if let Some(index) = self.active {
+            if self.pressed && !pressed && hovered == Some(index) {
+                self.selected = Some(index);
+                events.push(UIPaletteEvent::Selected(self.swatches[index].color));
+            }
+            if hovered != Some(index) || !pressed {
+                self.active = None;
+            }
+        }
+
+        self.pressed = pressed;
+    }
+}
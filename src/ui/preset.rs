@@ -0,0 +1,329 @@
+use crate::ui::block::UIBlockStyle;
+use crate::ui::text_area::{Color, UITextAreaAlignment, UITextAreaStyle};
+use serde::de::{DeserializeOwned, Error as _};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use toml::value::{Table, Value};
+
+// Serde derives aren't available, so styles are (de)serialized by hand. Reading
+// goes through a `toml::Value`, which works with any deserializer.
+
+fn table<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Table, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Table(table) => Ok(table),
+        value => Err(D::Error::custom(format!(
+            "expected a table, found {}",
+            value.type_str()
+        ))),
+    }
+}
+
+fn required<T: DeserializeOwned>(table: &Table, key: &str) -> Result<T, toml::de::Error> {
+    match table.get(key) {
+        Some(value) => value
+            .clone()
+            .try_into()
+            .map_err(|error| toml::de::Error::custom(format!("{}: {}", key, error))),
+        None => Err(toml::de::Error::custom(format!("missing field {}", key))),
+    }
+}
+
+// Missing fields keep `default`, so presets saved by older versions still load.
+fn optional<T: DeserializeOwned>(
+    table: &Table,
+    key: &str,
+    default: T,
+) -> Result<T, toml::de::Error> {
+    if table.contains_key(key) {
+        required(table, key)
+    } else {
+        Ok(default)
+    }
+}
+
+fn to_value<T: Serialize, E: serde::ser::Error>(value: &T) -> Result<Value, E> {
+    Value::try_from(value).map_err(E::custom)
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Color", 3)?;
+        state.serialize_field("r", &self.r)?;
+        state.serialize_field("g", &self.g)?;
+        state.serialize_field("b", &self.b)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = table(deserializer)?;
+        let color = || -> Result<Color, toml::de::Error> {
+            Ok(Color {
+                r: required(&table, "r")?,
+                g: required(&table, "g")?,
+                b: required(&table, "b")?,
+            })
+        };
+        color().map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for UIBlockStyle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("UIBlockStyle", 14)?;
+        state.serialize_field("alpha", &self.alpha)?;
+        state.serialize_field("radius", &self.radius)?;
+        state.serialize_field("sharpness", &self.sharpness)?;
+        state.serialize_field("left_offset", &self.left_offset)?;
+        state.serialize_field("left_color", &self.left_color)?;
+        state.serialize_field("right_offset", &self.right_offset)?;
+        state.serialize_field("right_color", &self.right_color)?;
+        state.serialize_field("inner_shadow", &self.inner_shadow)?;
+        state.serialize_field("shade_color", &self.shade_color)?;
+        state.serialize_field("border_width", &self.border_width)?;
+        state.serialize_field("border_color", &self.border_color)?;
+        state.serialize_field("shadow_color", &self.shadow_color)?;
+        state.serialize_field("shadow_blur", &self.shadow_blur)?;
+        state.serialize_field("shadow_offset", &self.shadow_offset)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for UIBlockStyle {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = table(deserializer)?;
+        let style = || -> Result<UIBlockStyle, toml::de::Error> {
+            Ok(UIBlockStyle {
+                alpha: required(&table, "alpha")?,
+                radius: required(&table, "radius")?,
+                sharpness: required(&table, "sharpness")?,
+                left_offset: required(&table, "left_offset")?,
+                left_color: required(&table, "left_color")?,
+                right_offset: required(&table, "right_offset")?,
+                right_color: required(&table, "right_color")?,
+                inner_shadow: required(&table, "inner_shadow")?,
+                shade_color: required(&table, "shade_color")?,
+                border_width: optional(&table, "border_width", 0.0)?,
+                border_color: optional(&table, "border_color", [0.0, 0.0, 0.0])?,
+                shadow_color: optional(&table, "shadow_color", [0.0, 0.0, 0.0, 0.0])?,
+                shadow_blur: optional(&table, "shadow_blur", 0.0)?,
+                shadow_offset: optional(&table, "shadow_offset", [0.0, 0.0])?,
+            })
+        };
+        style().map_err(D::Error::custom)
+    }
+}
+
+const ALIGNMENTS: [(UITextAreaAlignment, &str); 9] = [
+    (UITextAreaAlignment::TopLeft, "top_left"),
+    (UITextAreaAlignment::Top, "top"),
+    (UITextAreaAlignment::TopRight, "top_right"),
+    (UITextAreaAlignment::Left, "left"),
+    (UITextAreaAlignment::Center, "center"),
+    (UITextAreaAlignment::Right, "right"),
+    (UITextAreaAlignment::BottomLeft, "bottom_left"),
+    (UITextAreaAlignment::Bottom, "bottom"),
+    (UITextAreaAlignment::BottomRight, "bottom_right"),
+];
+
+impl Serialize for UITextAreaAlignment {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (_, name) = ALIGNMENTS
+            .iter()
+            .find(|(alignment, _)| alignment == self)
+            .expect("Cannot find alignment name");
+        serializer.serialize_str(name)
+    }
+}
+
+impl<'de> Deserialize<'de> for UITextAreaAlignment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        ALIGNMENTS
+            .iter()
+            .find(|(_, alignment_name)| *alignment_name == name)
+            .map(|(alignment, _)| *alignment)
+            .ok_or_else(|| D::Error::custom(format!("unknown alignment {}", name)))
+    }
+}
+
+impl Serialize for UITextAreaStyle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("UITextAreaStyle", 18)?;
+        state.serialize_field("text_size", &self.text_size)?;
+        state.serialize_field("inner_dist", &self.inner_dist)?;
+        state.serialize_field("outer_dist", &self.outer_dist)?;
+        state.serialize_field("sharpness", &self.sharpness)?;
+        state.serialize_field("shadow_dist", &self.shadow_dist)?;
+        state.serialize_field("text_color", &self.text_color)?;
+        state.serialize_field("shadow_color", &self.shadow_color)?;
+        state.serialize_field("shadow_pos", &self.shadow_pos)?;
+        state.serialize_field("shadow_size", &self.shadow_size)?;
+        state.serialize_field("shadow_alpha", &self.shadow_alpha)?;
+        state.serialize_field("texture_visibility", &self.texture_visibility)?;
+        state.serialize_field("animation", &self.animation)?;
+        state.serialize_field("shadow_offset", &self.shadow_offset)?;
+        match self.stroke {
+            Some((width, color)) => {
+                let mut stroke = Table::new();
+                stroke.insert("width".into(), to_value(&width)?);
+                stroke.insert("color".into(), to_value(&color)?);
+                state.serialize_field("stroke", &stroke)?;
+            }
+            None => state.skip_field("stroke")?,
+        }
+        match self.glow {
+            Some((radius, intensity, color)) => {
+                let mut glow = Table::new();
+                glow.insert("radius".into(), to_value(&radius)?);
+                glow.insert("intensity".into(), to_value(&intensity)?);
+                glow.insert("color".into(), to_value(&color)?);
+                state.serialize_field("glow", &glow)?;
+            }
+            None => state.skip_field("glow")?,
+        }
+        match &self.background {
+            Some(background) => state.serialize_field("background", background)?,
+            None => state.skip_field("background")?,
+        }
+        state.serialize_field("content_align", &self.content_align)?;
+        match &self.auto_contrast {
+            Some(background) => state.serialize_field("auto_contrast", background)?,
+            None => state.skip_field("auto_contrast")?,
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for UITextAreaStyle {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = table(deserializer)?;
+        let style = || -> Result<UITextAreaStyle, toml::de::Error> {
+            let default = UITextAreaStyle::default();
+            let stroke = match optional::<Option<Table>>(&table, "stroke", None)? {
+                Some(stroke) => Some((required(&stroke, "width")?, required(&stroke, "color")?)),
+                None => None,
+            };
+            let glow = match optional::<Option<Table>>(&table, "glow", None)? {
+                Some(glow) => Some((
+                    required(&glow, "radius")?,
+                    required(&glow, "intensity")?,
+                    required(&glow, "color")?,
+                )),
+                None => None,
+            };
+
+            Ok(UITextAreaStyle {
+                text_size: optional(&table, "text_size", default.text_size)?,
+                inner_dist: optional(&table, "inner_dist", default.inner_dist)?,
+                outer_dist: optional(&table, "outer_dist", default.outer_dist)?,
+                sharpness: optional(&table, "sharpness", default.sharpness)?,
+                shadow_dist: optional(&table, "shadow_dist", default.shadow_dist)?,
+                text_color: optional(&table, "text_color", default.text_color)?,
+                shadow_color: optional(&table, "shadow_color", default.shadow_color)?,
+                shadow_pos: optional(&table, "shadow_pos", default.shadow_pos)?,
+                shadow_size: optional(&table, "shadow_size", default.shadow_size)?,
+                shadow_alpha: optional(&table, "shadow_alpha", default.shadow_alpha)?,
+                texture_visibility: optional(
+                    &table,
+                    "texture_visibility",
+                    default.texture_visibility,
+                )?,
+                animation: optional(&table, "animation", default.animation)?,
+                shadow_offset: optional(&table, "shadow_offset", default.shadow_offset)?,
+                stroke,
+                glow,
+                background: optional(&table, "background", default.background)?,
+                content_align: optional(&table, "content_align", default.content_align)?,
+                auto_contrast: optional(&table, "auto_contrast", default.auto_contrast)?,
+            })
+        };
+        style().map_err(D::Error::custom)
+    }
+}
+
+// Styles hold `f32`s, written with the shortest `f32` representation so that
+// 0.4 doesn't turn into 0.4000000059604645.
+fn shorten_floats(value: &mut Value) {
+    match value {
+        Value::Float(float) => {
+            *float = (*float as f32)
+                .to_string()
+                .parse()
+                .expect("Cannot parse formatted float");
+        }
+        Value::Array(array) => array.iter_mut().for_each(shorten_floats),
+        Value::Table(table) => table
+            .iter_mut()
+            .for_each(|(_, value)| shorten_floats(value)),
+        _ => {}
+    }
+}
+
+impl UITextAreaStyle {
+    pub fn to_toml(&self) -> String {
+        let mut value = Value::try_from(self).expect("Cannot serialize text area style");
+        shorten_floats(&mut value);
+        toml::to_string(&value).expect("Cannot format text area style")
+    }
+
+    /// Parses a style written by `to_toml`. Missing fields keep their default
+    /// values.
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+}
+
+/// Named text area styles, saved as TOML files in a directory.
+pub struct UITextAreaPresets {
+    dir: PathBuf,
+}
+
+impl UITextAreaPresets {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Names of the saved presets in alphabetical order. A missing directory
+    /// has none.
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) == Some("toml") {
+                if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Saves `style` as `name`, replacing a preset with the same name.
+    pub fn save(&self, name: &str, style: &UITextAreaStyle) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(name), style.to_toml())
+    }
+
+    /// Loads the preset `name`. Invalid files fail with `InvalidData`.
+    pub fn load(&self, name: &str) -> io::Result<UITextAreaStyle> {
+        let text = fs::read_to_string(self.path(name))?;
+        UITextAreaStyle::from_toml(&text)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.toml", name))
+    }
+}
@@ -0,0 +1,110 @@
+use crate::ui::button::{UIButton, UIButtonContext, UIButtonEvent};
+use crate::ui::widget::{UIFrameInput, UILayout, UISize, UIWidget};
+use glium::{DrawError, Surface};
+use std::rc::Rc;
+
+/// Row of mutually exclusive buttons of equal width. Exactly one option is
+/// toggled at any time.
+pub struct UIRadioGroup {
+    buttons: Vec<UIButton>,
+    selected: usize,
+    spacing: f32,
+}
+
+impl UIRadioGroup {
+    pub fn new(context: &Rc<UIButtonContext>, titles: &[&str], selected: usize) -> Self {
+        assert!(selected < titles.len(), "Selected option is out of range");
+
+        let buttons = titles
+            .iter()
+            .enumerate()
+            .map(|(index, title)| {
+                let mut button = UIButton::new(context, title);
+                button.set_toggled(index == selected);
+                button
+            })
+            .collect();
+
+        Self {
+            buttons,
+            selected,
+            spacing: 4.0,
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn set_selected(&mut self, selected: usize) {
+        self.selected = selected;
+        for (index, button) in self.buttons.iter_mut().enumerate() {
+            button.set_toggled(index == selected);
+        }
+    }
+
+    fn calc_option_layout(&self, layout: UILayout, index: usize) -> UILayout {
+        let count = self.buttons.len() as f32;
+        let width = (layout.width - (count - 1.0) * self.spacing) / count;
+        UILayout {
+            left: layout.left + index as f32 * (width + self.spacing),
+            width,
+            ..layout
+        }
+    }
+}
+
+pub enum UIRadioEvent {
+    Selected(usize),
+}
+
+impl UIWidget for UIRadioGroup {
+    type Event = UIRadioEvent;
+
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        for (index, button) in self.buttons.iter().enumerate() {
+            button.render(frame, self.calc_option_layout(layout, index), screen)?;
+        }
+        Ok(())
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        for button in &mut self.buttons {
+            button.set_enabled(enabled);
+        }
+    }
+
+    fn update_input(
+        &mut self,
+        layout: UILayout,
+        frame_input: UIFrameInput,
+        events: &mut Vec<UIRadioEvent>,
+    ) {
+        let mut clicked = None;
+        let mut button_events = Vec::new();
+        for index in 0..self.buttons.len() {
+            let option_layout = self.calc_option_layout(layout, index);
+            self.buttons[index].update_input(option_layout, frame_input, &mut button_events);
+            for event in button_events.drain(..) {
+                match event {
+                    UIButtonEvent::Toggled(_) => clicked = Some(index),
+                }
+            }
+        }
+
+        // Buttons flip their own state on click, so restore exclusivity even
+        // when the selected option is clicked again.
+        if let Some(index) = clicked {
+            let changed = index != self.selected;
+            self.set_selected(index);
+            if changed {
+                events.push(UIRadioEvent::Selected(index));
+            }
+        }
+    }
+}
@@ -1,9 +1,11 @@
 use crate::ui::block::{UIBlock, UIBlockContext, UIBlockStyle};
 use crate::ui::label::{UILabel, UILabelAlignment, UILabelContext, UILabelStyle};
 use crate::ui::layout::{UIAbsoluteLayout, UIScaleLayout};
-use crate::ui::widget::{UIFrameInput, UILayout, UIPoint, UISize, UIWidget};
+use crate::ui::widget::{
+    UIFrameInput, UIKey, UILayout, UIPoint, UISize, UIWidget, DISABLED_OPACITY,
+};
 use crate::utils::*;
-use glium::Frame;
+use glium::{DrawError, Surface};
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::time::Instant;
@@ -25,9 +27,39 @@ impl UISliderContext {
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum UISliderScale {
+    Linear,
+    /// Positions map to the logarithm of the value. Requires a positive range.
+    Logarithmic,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum UISliderTrackDecoration {
+    None,
+    /// Small marks at every `step_value`. Marks closer than a few pixels are skipped.
+    Ticks,
+    /// Fixed left and right track colors instead of the value fill.
+    Gradient([f32; 3], [f32; 3]),
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum UISliderLabelMode {
+    /// Always visible above the dot.
+    Above,
+    /// Always visible right of the track, which is shortened to make room.
+    Right,
+    /// Above the dot, fading in while the slider is hovered.
+    OnHover,
+    Hidden,
+}
+
+const RIGHT_LABEL_WIDTH: f32 = 48.0;
+
 pub struct UISlider {
     block: UIBlock,
     dot: UIBlock,
+    tick: UIBlock,
     label: UILabel,
     pressed: bool,
     hover: bool,
@@ -43,7 +75,13 @@ pub struct UISlider {
     step_value: f32,
     value: f32,
     precision: usize,
+    scale: UISliderScale,
     drag_value: Option<f32>,
+    snap_points: Vec<f32>,
+    snap_tolerance: f32,
+    track_decoration: UISliderTrackDecoration,
+    label_mode: UISliderLabelMode,
+    enabled: bool,
 }
 
 impl UISlider {
@@ -54,6 +92,7 @@ impl UISlider {
         step_value: f32,
         value: f32,
         precision: usize,
+        scale: UISliderScale,
     ) -> Self {
         let block = UIBlock::new(
             context.block_context.clone(),
@@ -67,10 +106,15 @@ impl UISlider {
                 right_color: [0.05, 0.05, 0.05],
                 inner_shadow: 2.0,
                 shade_color: [0.02, 0.02, 0.02],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
             },
         );
 
-        let dot = UIBlock::new(
+        let mut dot = UIBlock::new(
             context.block_context.clone(),
             UIBlockStyle {
                 alpha: 0.95,
@@ -82,6 +126,33 @@ impl UISlider {
                 right_color: [0.6, 0.1, 0.9],
                 inner_shadow: 20.0,
                 shade_color: [0.0, 0.0, 0.0],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
+            },
+        );
+        // Follows the value smoothly and scales on hover.
+        dot.set_snap(false);
+
+        let tick = UIBlock::new(
+            context.block_context.clone(),
+            UIBlockStyle {
+                alpha: 0.6,
+                sharpness: 1.0,
+                radius: 1.0,
+                left_offset: 0.0,
+                left_color: [0.5, 0.5, 0.5],
+                right_offset: 0.0,
+                right_color: [0.5, 0.5, 0.5],
+                inner_shadow: 1.0,
+                shade_color: [0.5, 0.5, 0.5],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
             },
         );
 
@@ -92,14 +163,15 @@ impl UISlider {
                 size: 15.0,
                 align: UILabelAlignment::Center,
                 color: [0.7, 0.7, 0.7, 1.0],
-                shadow_color: [0.0, 0.0, 0.0, 1.0],
                 opacity: 0.0,
+                ..Default::default()
             },
         );
 
         Self {
             block,
             dot,
+            tick,
             label,
             pressed: false,
             hover: false,
@@ -115,10 +187,38 @@ impl UISlider {
             step_value,
             value,
             precision,
+            scale,
             drag_value: None,
+            snap_points: Vec::new(),
+            snap_tolerance: 0.0,
+            track_decoration: UISliderTrackDecoration::None,
+            label_mode: UISliderLabelMode::OnHover,
+            enabled: true,
         }
     }
 
+    pub fn set_label_mode(&mut self, label_mode: UISliderLabelMode) {
+        self.label_mode = label_mode;
+    }
+
+    pub fn set_track_decoration(&mut self, track_decoration: UISliderTrackDecoration) {
+        self.track_decoration = track_decoration;
+    }
+
+    /// Values which attract the dot while dragging when it is closer than
+    /// `tolerance` pixels.
+    pub fn set_snap_points(&mut self, snap_points: &[f32], tolerance: f32) {
+        self.snap_points = snap_points.to_vec();
+        self.snap_tolerance = tolerance;
+    }
+
+    /// Moves the dot to `value` without emitting events.
+    pub fn set_value(&mut self, value: f32) {
+        self.value = value;
+        self.label
+            .set_text(&format!("{:.*}", self.precision, self.value));
+    }
+
     fn hover_value(&self) -> f32 {
         let animation = (self.hover_time.elapsed_seconds() * 8.0).min(1.0) as f32;
         let t = (self.hover_to - self.hover_from) * animation + self.hover_from;
@@ -132,16 +232,67 @@ impl UISlider {
         1.0 - (t - 1.0).powf(2.0)
     }
 
+    fn value_to_fraction(&self, value: f32) -> f32 {
+        match self.scale {
+            UISliderScale::Linear => (value - self.min_value) / (self.max_value - self.min_value),
+            UISliderScale::Logarithmic => {
+                (value.ln() - self.min_value.ln()) / (self.max_value.ln() - self.min_value.ln())
+            }
+        }
+    }
+
+    fn fraction_to_value(&self, fraction: f32) -> f32 {
+        match self.scale {
+            UISliderScale::Linear => fraction * (self.max_value - self.min_value) + self.min_value,
+            UISliderScale::Logarithmic => {
+                (fraction * (self.max_value.ln() - self.min_value.ln()) + self.min_value.ln()).exp()
+            }
+        }
+    }
+
     fn value_from_pos(&self, pos: f32, layout: UILayout) -> f32 {
-        let value = ((pos - layout.left) / layout.width).max(0.0).min(1.0);
-        (value * (self.max_value - self.min_value) / self.step_value + 0.5).floor()
-            * self.step_value
-            + self.min_value
+        for snap_point in &self.snap_points {
+            let snap_pos = self.value_to_fraction(*snap_point) * layout.width + layout.left;
+            if (snap_pos - pos).abs() <= self.snap_tolerance {
+                return *snap_point;
+            }
+        }
+
+        // Steps are applied in value space, whatever the scale.
+        let fraction = ((pos - layout.left) / layout.width).max(0.0).min(1.0);
+        let value = self.fraction_to_value(fraction) - self.min_value;
+        (value / self.step_value + 0.5).floor() * self.step_value + self.min_value
     }
 
     fn value_to_pos(&self, value: f32, layout: UILayout) -> f32 {
         let value = (value / self.step_value + 0.5).floor() * self.step_value;
-        (value - self.min_value) / (self.max_value - self.min_value) * layout.width
+        self.value_to_fraction(value) * layout.width
+    }
+
+    fn opacity(&self) -> f32 {
+        if self.enabled {
+            1.0
+        } else {
+            DISABLED_OPACITY
+        }
+    }
+
+    fn dimmed(&self, style: UIBlockStyle) -> UIBlockStyle {
+        UIBlockStyle {
+            alpha: style.alpha * self.opacity(),
+            ..style
+        }
+    }
+
+    fn calc_track_layout(&self, layout: UILayout) -> UILayout {
+        if self.label_mode == UISliderLabelMode::Right {
+            UILayout {
+                width: (layout.width - RIGHT_LABEL_WIDTH).max(0.0),
+                ..layout
+            }
+        } else {
+            layout
+        }
     }
 
     fn calc_slider_layout(&self, layout: UILayout) -> UILayout {
@@ -195,8 +346,7 @@ impl UISlider {
 
         let mut result = [UILayout::zero()];
         dot_layout.layout(layout, &mut result);
-        scale_layout.layout(result[0], &mut result);
-        result[0]
+        scale_layout.transform(result[0])
     }
 }
 
@@ -208,20 +358,68 @@ pub enum UISliderEvent {
 impl UIWidget for UISlider {
     type Event = UISliderEvent;
 
-    fn render(&self, frame: &mut Frame, layout: UILayout, screen: UISize) {
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let widget_layout = layout;
+        let layout = self.calc_track_layout(layout);
+
         // Dot layout
         let dot_layout = self.calc_dot_layout(layout);
         let center = dot_layout.left + dot_layout.width / 2.0 - layout.left;
 
         // Background
-        let background_style = UIBlockStyle {
-            left_offset: center - 2.0,
-            right_offset: center + 2.0,
-            ..self.block.get_style()
-        };
         let background_layout = self.calc_slider_layout(layout);
-        self.block
-            .render_styled(frame, background_layout, background_style, screen);
+        let background_style = match self.track_decoration {
+            UISliderTrackDecoration::Gradient(left_color, right_color) => {
+                let radius = self.block.get_style().radius;
+                UIBlockStyle {
+                    left_offset: -radius,
+                    left_color,
+                    right_offset: background_layout.width - radius,
+                    right_color,
+                    ..self.block.get_style()
+                }
+            }
+            _ => UIBlockStyle {
+                left_offset: center - 2.0,
+                right_offset: center + 2.0,
+                ..self.block.get_style()
+            },
+        };
+        self.block.render_styled(
+            frame,
+            background_layout,
+            self.dimmed(background_style),
+            screen,
+        )?;
+
+        // Ticks
+        if self.track_decoration == UISliderTrackDecoration::Ticks {
+            let tick_width = 2.0;
+            let tick_height = background_layout.height + 4.0;
+            let mut last_pos = f32::NEG_INFINITY;
+            let mut value = self.min_value;
+            while value <= self.max_value {
+                let pos = self.value_to_fraction(value) * layout.width;
+                if pos - last_pos >= 4.0 {
+                    let tick_layout = UILayout {
+                        left: layout.left + pos - tick_width / 2.0,
+                        top: background_layout.top - 2.0,
+                        width: tick_width,
+                        height: tick_height,
+                    };
+                    let tick_style = self.dimmed(self.tick.get_style());
+                    self.tick
+                        .render_styled(frame, tick_layout, tick_style, screen)?;
+                    last_pos = pos;
+                }
+                value += self.step_value;
+            }
+        }
 
         // Dot
         let pressed_value = if self.drag_value.is_some() { 1.0 } else { 0.0 };
@@ -230,29 +428,63 @@ impl UIWidget for UISlider {
             radius: 8.0 * (1.0 + 0.3 * self.hover_value()),
             ..self.dot.get_style()
         };
-        self.dot.render_styled(frame, dot_layout, dot_style, screen);
+        self.dot
+            .render_styled(frame, dot_layout, self.dimmed(dot_style), screen)?;
 
         // Label
-        let label_layout = UIAbsoluteLayout {
-            pos: UIPoint {
-                left: 0.0,
-                top: 20.0,
-            },
-            size: UISize {
-                width: dot_layout.width,
-                height: dot_layout.height,
-            },
-        };
+        let (label_layout, mut label_style) = match self.label_mode {
+            UISliderLabelMode::Hidden => return Ok(()),
+            UISliderLabelMode::Right => {
+                let label_layout = UILayout {
+                    left: layout.left + layout.width,
+                    width: widget_layout.width - layout.width,
+                    ..widget_layout
+                };
+                let label_style = UILabelStyle {
+                    align: UILabelAlignment::Right,
+                    opacity: 1.0,
+                    ..self.label.get_style()
+                };
+                (label_layout, label_style)
+            }
+            UISliderLabelMode::Above | UISliderLabelMode::OnHover => {
+                let label_layout = UIAbsoluteLayout {
+                    pos: UIPoint {
+                        left: 0.0,
+                        top: 20.0,
+                    },
+                    size: UISize {
+                        width: dot_layout.width,
+                        height: dot_layout.height,
+                    },
+                };
+                let mut label_layout_result = [UILayout::zero()];
+                label_layout.layout(dot_layout, &mut label_layout_result);
 
-        let label_style = UILabelStyle {
-            opacity: self.slider_hover_value(),
-            ..self.label.get_style()
+                let opacity = if self.label_mode == UISliderLabelMode::Above {
+                    1.0
+                } else {
+                    self.slider_hover_value()
+                };
+                let label_style = UILabelStyle {
+                    opacity,
+                    ..self.label.get_style()
+                };
+                (label_layout_result[0], label_style)
+            }
         };
 
-        let mut label_layout_result = [UILayout::zero()];
-        label_layout.layout(dot_layout, &mut label_layout_result);
+        label_style.opacity *= self.opacity();
         self.label
-            .render_styled(frame, label_layout_result[0], label_style, screen);
+            .render_styled(frame, label_layout, label_style, screen)
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled && self.drag_value.take().is_some() {
+            self.label
+                .set_text(&format!("{:.*}", self.precision, self.value));
+        }
     }
 
     #[allow(clippy::float_cmp)]
@@ -262,6 +494,7 @@ impl UIWidget for UISlider {
         frame_input: UIFrameInput,
         events: &mut Vec<UISliderEvent>,
     ) {
+        let layout = self.calc_track_layout(layout);
         let dot_layout = self.calc_dot_layout(layout);
         let hover = dot_layout.is_inside(frame_input.mouse_pos);
         let slider_layout = self
@@ -301,9 +534,7 @@ impl UIWidget for UISlider {
         if let Some(old_value) = self.drag_value {
             let new_value = self.value_from_pos(frame_input.mouse_pos.left, layout);
             if !pressed {
-                self.value = new_value;
-                self.label
-                    .set_text(&format!("{:.*}", self.precision, self.value));
+                self.set_value(new_value);
                 self.drag_value = None;
                 events.push(UISliderEvent::ValueFinished(new_value));
             } else {
@@ -320,4 +551,44 @@ impl UIWidget for UISlider {
         self.hover = hover;
         self.slider_hover = slider_hover;
     }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+
+    fn captures_input(&self) -> bool {
+        self.drag_value.is_some()
+    }
+
+    fn on_key(
+        &mut self,
+        _layout: UILayout,
+        frame_input: UIFrameInput,
+        key: UIKey,
+        pressed: bool,
+        events: &mut Vec<UISliderEvent>,
+    ) {
+        if self.drag_value.is_some() {
+            return;
+        }
+
+        if !pressed {
+            events.push(UISliderEvent::ValueFinished(self.value));
+            return;
+        }
+
+        let step = if frame_input.shift_pressed {
+            self.step_value * 10.0
+        } else {
+            self.step_value
+        };
+        let delta = match key {
+            UIKey::Left | UIKey::Down => -step,
+            UIKey::Right | UIKey::Up => step,
+        };
+
+        let value = (self.value + delta).max(self.min_value).min(self.max_value);
+        self.set_value(value);
+        events.push(UISliderEvent::ValueChanged(value));
+    }
 }
@@ -1,22 +1,36 @@
-use crate::ui::widget::{UIFrameInput, UILayout, UIPoint, UISize, UIWidget};
+use crate::ui::block::{UIBlock, UIBlockContext, UIBlockStyle};
+use crate::ui::easing::UIEasing;
+use crate::ui::widget::{
+    ui_draw_parameters, GlyphRenderPass, UIColorSpace, UIFrameInput, UILayout, UIPoint, UISize,
+    UIWidget,
+};
 use glium::backend::{Context, Facade};
 use glium::draw_parameters::DrawParameters;
-use glium::index::PrimitiveType;
-use glium::texture::{ClientFormat, MipmapsOption, RawImage2d, Texture2d, TextureCreationError};
-use glium::uniforms::{AsUniformValue, UniformValue};
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{
+    ClientFormat, MipmapsOption, RawImage2d, Texture2d, TextureCreationError,
+    UncompressedFloatFormat,
+};
+use glium::uniforms::{AsUniformValue, EmptyUniforms, SamplerWrapFunction, UniformValue};
 use glium::{
-    implement_vertex, program, uniform, Blend, Frame, IndexBuffer, Program, Rect as GLRect,
-    Surface, VertexBuffer,
+    implement_vertex, program, uniform, Blend, BlendingFunction, CapabilitiesSource, DrawError,
+    LinearBlendingFactor, Program, Rect as GLRect, Surface, VertexBuffer,
 };
 use mcsdf::font::{Font, TextBlockLayout, TextureRenderBatch};
+use mcsdf::geometry::Rect;
+use mcsdf::grapheme::{next_grapheme_boundary, previous_grapheme_boundary};
 use mcsdf::texture::Texture;
 use std::borrow::Cow;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 
 use std::collections::VecDeque;
+use std::mem::take;
+use std::ops::Range;
 use std::rc::Rc;
-#[derive(Clone, Copy, Debug)]
+use std::time::{Duration, Instant};
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -43,6 +57,44 @@ impl Color {
             b: 1.0,
         }
     }
+
+    /// Black or white, whichever contrasts more with `background`, using the
+    /// WCAG relative luminance and contrast ratio.
+    pub fn readable_on(background: Color) -> Self {
+        let luminance = background.relative_luminance();
+        let black_contrast = (luminance + 0.05) / 0.05;
+        let white_contrast = 1.05 / (luminance + 0.05);
+        if black_contrast >= white_contrast {
+            Color::black()
+        } else {
+            Color::white()
+        }
+    }
+
+    /// Relative luminance of the sRGB color, from 0 for black to 1 for white.
+    pub fn relative_luminance(self) -> f32 {
+        let linear = |channel: f32| {
+            if channel <= 0.04045 {
+                channel / 12.92
+            } else {
+                ((channel + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// Color `t` of the way towards `other`.
+    pub fn lerp(self, other: Color, t: f32) -> Self {
+        Color {
+            r: lerp(self.r, other.r, t),
+            g: lerp(self.g, other.g, t),
+            b: lerp(self.b, other.b, t),
+        }
+    }
+}
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
 }
 
 impl AsUniformValue for Color {
@@ -51,7 +103,37 @@ impl AsUniformValue for Color {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UITextAreaAlignment {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl UITextAreaAlignment {
+    // Fraction of the free space put before the content, horizontally and vertically.
+    fn factors(self) -> (f32, f32) {
+        match self {
+            UITextAreaAlignment::TopLeft => (0.0, 0.0),
+            UITextAreaAlignment::Top => (0.5, 0.0),
+            UITextAreaAlignment::TopRight => (1.0, 0.0),
+            UITextAreaAlignment::Left => (0.0, 0.5),
+            UITextAreaAlignment::Center => (0.5, 0.5),
+            UITextAreaAlignment::Right => (1.0, 0.5),
+            UITextAreaAlignment::BottomLeft => (0.0, 1.0),
+            UITextAreaAlignment::Bottom => (0.5, 1.0),
+            UITextAreaAlignment::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct UITextAreaStyle {
     pub text_size: f32,
     pub inner_dist: f32,
@@ -65,6 +147,23 @@ pub struct UITextAreaStyle {
     pub shadow_alpha: f32,
     pub texture_visibility: f32,
     pub animation: bool,
+    /// Shadow displacement in em units, y pointing up.
+    pub shadow_offset: [f32; 2],
+    /// Draws only a band of given width around the glyph edge, leaving the
+    /// interior transparent.
+    pub stroke: Option<(f32, Color)>,
+    /// Blurred copy of the text drawn underneath it: radius in pixels,
+    /// intensity and color. The blur takes at most 129 samples per direction,
+    /// so radii beyond 64 pixels are approximated with spread out samples.
+    pub glow: Option<(f32, f32, Color)>,
+    /// Panel drawn behind the text, covering the whole area.
+    pub background: Option<UIBlockStyle>,
+    /// Initial placement of the text inside the area, kept until the user
+    /// pans or zooms.
+    pub content_align: UITextAreaAlignment,
+    /// Background the text is drawn over. When set, `text_color` is replaced
+    /// by black or white, whichever is more readable on it.
+    pub auto_contrast: Option<Color>,
 }
 
 impl Default for UITextAreaStyle {
@@ -82,20 +181,118 @@ impl Default for UITextAreaStyle {
             shadow_alpha: 0.0,
             texture_visibility: 0.0,
             animation: false,
+            shadow_offset: [0.0, 0.0],
+            stroke: None,
+            glow: None,
+            background: None,
+            content_align: UITextAreaAlignment::TopLeft,
+            auto_contrast: None,
+        }
+    }
+}
+
+impl UITextAreaStyle {
+    /// Style `t` of the way towards `target`. Numbers and colors are
+    /// interpolated, a stroke or glow which appears or disappears grows from
+    /// or shrinks to nothing, and the remaining fields switch halfway.
+    pub fn lerp(&self, target: &UITextAreaStyle, t: f32) -> Self {
+        let stroke = match (self.stroke, target.stroke) {
+            (Some((from, from_color)), Some((to, to_color))) => {
+                Some((lerp(from, to, t), from_color.lerp(to_color, t)))
+            }
+            (None, Some((width, color))) => Some((width * t, color)),
+            (Some((width, color)), None) if t < 1.0 => Some((width * (1.0 - t), color)),
+            _ => None,
+        };
+        let glow = match (self.glow, target.glow) {
+            (Some((from, from_intensity, from_color)), Some((to, to_intensity, to_color))) => {
+                Some((
+                    lerp(from, to, t),
+                    lerp(from_intensity, to_intensity, t),
+                    from_color.lerp(to_color, t),
+                ))
+            }
+            (None, Some((radius, intensity, color))) => Some((radius, intensity * t, color)),
+            (Some((radius, intensity, color)), None) if t < 1.0 => {
+                Some((radius, intensity * (1.0 - t), color))
+            }
+            _ => None,
+        };
+        let discrete = if t < 0.5 { self } else { target };
+
+        UITextAreaStyle {
+            text_size: lerp(self.text_size, target.text_size, t),
+            inner_dist: lerp(self.inner_dist, target.inner_dist, t),
+            outer_dist: lerp(self.outer_dist, target.outer_dist, t),
+            sharpness: lerp(self.sharpness, target.sharpness, t),
+            shadow_dist: lerp(self.shadow_dist, target.shadow_dist, t),
+            text_color: self.text_color.lerp(target.text_color, t),
+            shadow_color: self.shadow_color.lerp(target.shadow_color, t),
+            shadow_pos: lerp(self.shadow_pos, target.shadow_pos, t),
+            shadow_size: lerp(self.shadow_size, target.shadow_size, t),
+            shadow_alpha: lerp(self.shadow_alpha, target.shadow_alpha, t),
+            texture_visibility: lerp(self.texture_visibility, target.texture_visibility, t),
+            animation: discrete.animation,
+            shadow_offset: [
+                lerp(self.shadow_offset[0], target.shadow_offset[0], t),
+                lerp(self.shadow_offset[1], target.shadow_offset[1], t),
+            ],
+            stroke,
+            glow,
+            background: discrete.background,
+            content_align: discrete.content_align,
+            auto_contrast: discrete.auto_contrast,
+        }
+    }
+
+    /// Color the text is filled with, taking `auto_contrast` into account.
+    pub fn fill_color(&self) -> Color {
+        match self.auto_contrast {
+            Some(background) => Color::readable_on(background),
+            None => self.text_color,
         }
     }
 }
 
+/// Overrides of the area's style for a run of rich text. `None` keeps the
+/// value of the style the area is rendered with.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UITextAreaRunStyle {
+    pub color: Option<Color>,
+    /// Text size in the same units as `UITextAreaStyle::text_size`. Lines keep
+    /// the spacing of the area's text size.
+    pub size: Option<f32>,
+    pub stroke: Option<(f32, Color)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UITextAreaStyledRun {
+    pub text: String,
+    pub style: UITextAreaRunStyle,
+}
+
 pub struct UITextAreaContext {
     context: Rc<Context>,
+    block_context: Rc<UIBlockContext>,
     program: Program,
+    glow_program: Program,
+    glow_quad: VertexBuffer<UITextAreaGlowVertex>,
+    glow_textures: Option<(Texture2d, Texture2d)>,
     font: Font,
-    texture_cache: HashMap<u32, Texture2d>,
+    texture_cache: HashMap<u32, Rc<Texture2d>>,
+    // Next row to upload for textures which are partially uploaded.
+    upload_cursors: HashMap<u32, u32>,
+    write_alpha: Cell<bool>,
+    color_space: Cell<UIColorSpace>,
 }
 
 impl UITextAreaContext {
     #[allow(clippy::redundant_closure)]
-    pub fn new<F: ?Sized + Facade>(facade: &F, font: Font) -> Self {
+    pub fn new<F: ?Sized + Facade>(
+        facade: &F,
+        block_context: Rc<UIBlockContext>,
+        font: Font,
+    ) -> Self {
         let context = facade.get_context().clone();
         let texture_cache = HashMap::new();
 
@@ -105,17 +302,25 @@ impl UITextAreaContext {
 
             in vec2 pos;
             in vec2 coord;
+            in vec4 bounds;
 
             out vec2 vCoord;
             out vec2 vPos;
+            out vec4 vBounds;
 
             uniform float uFontSize;
             uniform vec2 uScreen;
             uniform vec2 uPosition;
+            uniform vec2 uShadowOffset;
+            uniform vec2 uAtlasScale;
 
             void main() {
-                vPos = (uPosition + pos * uFontSize) * 2.0 / uScreen - 1.0;
-                vCoord = coord;
+                // Grow the quad so that an offset shadow is not cut off.
+                vec2 corner = sign(coord - (bounds.xy + bounds.zw) * 0.5);
+                vec2 extent = abs(uShadowOffset) * corner;
+                vPos = (uPosition + (pos + extent) * uFontSize) * 2.0 / uScreen - 1.0;
+                vCoord = coord + extent * uAtlasScale;
+                vBounds = bounds;
                 gl_Position = vec4(vPos, 0.0, 1.0);
             }
         "#,
@@ -124,6 +329,7 @@ impl UITextAreaContext {
 
             in vec2 vCoord;
             in vec2 vPos;
+            in vec4 vBounds;
 
             out vec4 color;
 
@@ -137,59 +343,233 @@ impl UITextAreaContext {
             uniform float uShadowSize;
             uniform float uShadowAlpha;
             uniform float uTextureVisibility;
+            uniform vec2 uShadowOffset;
+            uniform vec2 uAtlasScale;
             uniform vec2 uMouse;
             uniform bool uAnimation;
+            uniform bool uStroke;
+            uniform float uStrokeWidth;
+            uniform vec4 uStrokeColor;
             uniform vec2 uScreen;
             uniform float uFontSize;
+            uniform bool uLinear;
+            uniform bool uEncodeSrgb;
+
+            vec3 toLinear(vec3 c) {
+                if (!uLinear) {
+                    return c;
+                }
+                return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+            }
+
+            vec3 toOutput(vec3 c) {
+                if (!uEncodeSrgb) {
+                    return c;
+                }
+                return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+            }
 
             float median(float a, float b, float c) {
                 return max(min(a,b), min(max(a,b),c));
             }
 
             void main() {
-                vec4 t = texture(uTexture, vCoord);
+                // Clamp samples to the glyph view, so neighbours in the atlas are never read.
+                vec4 t = texture(uTexture, clamp(vCoord, vBounds.xy, vBounds.zw));
                 float d = median(t.r, t.g, t.b);
 
+                vec2 shadow_coord = clamp(vCoord - uShadowOffset * uAtlasScale, vBounds.xy, vBounds.zw);
+                vec4 s = texture(uTexture, shadow_coord);
+                float sd = median(s.r, s.g, s.b);
+
                 if (uAnimation) {
                     float mouse_dist = length(vPos - (uMouse / uScreen - vec2(0.5)) * 2.0);
-                    d = d * (1.0 + 1.0 * clamp(1.0 - mouse_dist * 2.0, 0.0, 1.0));
+                    float boost = 1.0 + 1.0 * clamp(1.0 - mouse_dist * 2.0, 0.0, 1.0);
+                    d = d * boost;
+                    sd = sd * boost;
                 }
 
-                vec4 outline_color = uColor;
-                float outer_alpha = smoothstep(uOuterDist - uSharpness, uOuterDist + uSharpness, d);
-                float inner_alpha = uInnerDist == 1.0 ? 1.0 : smoothstep(uInnerDist + uSharpness, uInnerDist - uSharpness, d);
+                // Distance change across one screen pixel, so edges stay the same width
+                // on screen at any zoom or transform.
+                vec2 texel_footprint = fwidth(vCoord) * vec2(textureSize(uTexture, 0));
+                float sharpness = uSharpness * 0.5 * (texel_footprint.x + texel_footprint.y);
+
+                vec4 outline_color = vec4(toLinear(uColor.rgb), uColor.a);
+                float outer_alpha = smoothstep(uOuterDist - sharpness, uOuterDist + sharpness, d);
+                float inner_alpha = uInnerDist == 1.0 ? 1.0 : smoothstep(uInnerDist + sharpness, uInnerDist - sharpness, d);
                 outline_color.a = inner_alpha * outer_alpha;
 
-                vec4 shadow_color = uShadowColor;
-                shadow_color.a = (1.0 - clamp(abs(d - uShadowPos) / uShadowSize, 0.0, 1.0)) * uShadowAlpha;
+                if (uStroke) {
+                    float edge_dist = abs(d - uOuterDist);
+                    outline_color = vec4(toLinear(uStrokeColor.rgb), uStrokeColor.a);
+                    outline_color.a = smoothstep(uStrokeWidth + sharpness, uStrokeWidth - sharpness, edge_dist);
+                }
+
+                vec4 shadow_color = vec4(toLinear(uShadowColor.rgb), uShadowColor.a);
+                shadow_color.a = (1.0 - clamp(abs(sd - uShadowPos) / uShadowSize, 0.0, 1.0)) * uShadowAlpha;
 
                 vec4 font_color = mix(outline_color, shadow_color, 1.0 - outline_color.a);
+                font_color.rgb = toOutput(font_color.rgb);
                 color = mix(font_color, t, uTextureVisibility);
             }
         "#,
         })
         .expect("Cannot create program for text area");
 
+        let glow_program = program!(facade, 140 => {
+        vertex: r#"
+            #version 140
+
+            in vec2 pos;
+
+            out vec2 vCoord;
+
+            void main() {
+                vCoord = pos * 0.5 + 0.5;
+                gl_Position = vec4(pos, 0.0, 1.0);
+            }
+        "#,
+        fragment: r#"
+            #version 140
+
+            in vec2 vCoord;
+
+            out vec4 color;
+
+            uniform sampler2D uTexture;
+            uniform vec2 uDirection;
+            uniform float uRadius;
+            uniform float uIntensity;
+
+            void main() {
+                float sigma = max(uRadius / 2.0, 0.001);
+                // Taps are spread out for radii beyond 64 pixels, relying on
+                // linear filtering between them.
+                float stride = max(uRadius / 64.0, 1.0);
+                vec4 sum = vec4(0.0);
+                float weight_sum = 0.0;
+
+                for (int i = -64; i <= 64; i++) {
+                    float x = float(i) * stride;
+                    if (abs(x) > uRadius) {
+                        continue;
+                    }
+
+                    float weight = exp(-x * x / (2.0 * sigma * sigma));
+                    sum += texture(uTexture, vCoord + uDirection * x) * weight;
+                    weight_sum += weight;
+                }
+
+                color = sum / weight_sum * uIntensity;
+            }
+        "#,
+        })
+        .expect("Cannot create glow program for text area");
+
+        let glow_quad = VertexBuffer::new(
+            facade,
+            &[
+                UITextAreaGlowVertex { pos: [-1.0, -1.0] },
+                UITextAreaGlowVertex { pos: [1.0, -1.0] },
+                UITextAreaGlowVertex { pos: [-1.0, 1.0] },
+                UITextAreaGlowVertex { pos: [1.0, 1.0] },
+            ],
+        )
+        .expect("Cannot create glow quad for text area");
+
         Self {
             context,
+            block_context,
             program,
+            glow_program,
+            glow_quad,
+            glow_textures: None,
             font,
             texture_cache,
+            upload_cursors: HashMap::new(),
+            write_alpha: Cell::new(false),
+            color_space: Cell::new(UIColorSpace::default()),
         }
     }
 
+    fn update_glow_textures(&mut self, width: u32, height: u32) {
+        let outdated = match &self.glow_textures {
+            Some((texture, _)) => {
+                texture.get_width() != width || texture.get_height() != Some(height)
+            }
+            None => true,
+        };
+
+        if outdated {
+            let create = || {
+                Texture2d::empty(&self.context, width, height)
+                    .expect("Cannot create glow texture for text area")
+            };
+            self.glow_textures = Some((create(), create()));
+        }
+    }
+
+    /// Makes widgets drawn with this context write their coverage into the
+    /// target alpha channel, leaving premultiplied output for compositing.
+    pub fn set_write_alpha(&self, write_alpha: bool) {
+        self.write_alpha.set(write_alpha);
+    }
+
+    pub fn get_write_alpha(&self) -> bool {
+        self.write_alpha.get()
+    }
+
+    /// Color space the shaders of this context blend in. See `UIColorSpace`.
+    pub fn set_color_space(&self, color_space: UIColorSpace) {
+        self.color_space.set(color_space);
+    }
+
+    pub fn get_color_space(&self) -> UIColorSpace {
+        self.color_space.get()
+    }
+
+    /// Same as `UIBlockContext::warm_up`, for the glyph and glow programs.
+    pub fn warm_up<F: ?Sized + Facade>(&self, facade: &F) -> Result<(), DrawError> {
+        let texture =
+            Texture2d::empty(facade, 1, 1).expect("Cannot create warm up texture for text area");
+        let bounds = Rect::new(0.0, 0.0, 0.0, 0.0);
+        let vertex_buffer = VertexBuffer::new(
+            facade,
+            &[UITextAreaGlyphVertex::new(0.0, 0.0, 0.0, 0.0, bounds); 3],
+        )
+        .expect("Cannot create warm up vertex buffer for text area");
+        let draw_parameters = ui_draw_parameters(self.write_alpha.get());
+
+        let mut surface = texture.as_surface();
+        surface.draw(
+            &vertex_buffer,
+            NoIndices(PrimitiveType::TrianglesList),
+            &self.program,
+            &EmptyUniforms,
+            &draw_parameters,
+        )?;
+        surface.draw(
+            &self.glow_quad,
+            NoIndices(PrimitiveType::TriangleStrip),
+            &self.glow_program,
+            &EmptyUniforms,
+            &draw_parameters,
+        )
+    }
+
     pub fn invalidate(&mut self) {
         self.texture_cache = HashMap::new();
+        self.upload_cursors = HashMap::new();
     }
 
     pub fn set_texture_size(&mut self, texture_size: f32) {
-        self.font
-            .set_texture_size(texture_size as u32, texture_size as u32);
+        let texture_size = (texture_size as u32).min(self.get_max_texture_size());
+        self.font.set_texture_size(texture_size, texture_size);
         self.invalidate();
     }
 
     pub fn set_font_size(&mut self, font_size: f32) {
-        self.font.set_font_size(font_size as u8);
+        self.font.set_font_size_f32(font_size);
         self.invalidate();
     }
 
@@ -198,46 +578,265 @@ impl UITextAreaContext {
         self.invalidate();
     }
 
+    pub fn set_font_weight(&mut self, weight: f32) {
+        self.font.set_variation(*b"wght", weight);
+        self.invalidate();
+    }
+
+    // Only affects layout, text areas have to be invalidated to pick it up.
+    pub fn set_fixed_advance(&mut self, advance: Option<f32>) {
+        self.font.set_fixed_advance(advance);
+    }
+
+    pub fn get_max_texture_size(&self) -> u32 {
+        self.context.get_capabilities().max_texture_size as u32
+    }
+
+    /// Largest font size at which glyphs still fit into an atlas texture.
+    /// Bigger glyphs fail to allocate and aren't drawn at all.
+    pub fn get_max_font_size(&self) -> f32 {
+        let font = &self.font;
+        let texture_size = font.get_texture_width().min(font.get_texture_height()) as f32;
+        let padding = 2.0 * (f32::from(font.get_shadow_size()) + 1.0);
+        // Glyphs may reach beyond the ascent and descent, e.g. stacked accents
+        // or emboldened outlines, so a quarter of an em is kept spare.
+        let em_height = (font.get_ascent() - font.get_descent()).max(1.0) + 0.25;
+        ((texture_size - padding) / em_height).max(1.0)
+    }
+
     pub fn update_texture_cache(
         &mut self,
         id: u32,
         texture: &Texture,
     ) -> Result<(), TextureCreationError> {
+        self.update_texture_caches(&[(id, texture)])
+    }
+
+    /// Uploads several atlas textures in one pass. Only the last texture of
+    /// an id which appears more than once is uploaded. Cached textures of the
+    /// same size are written in place, others are replaced. Nothing is
+    /// uploaded when any texture is larger than the maximum texture size.
+    pub fn update_texture_caches(
+        &mut self,
+        batches: &[(u32, &Texture)],
+    ) -> Result<(), TextureCreationError> {
+        let max_size = self.get_max_texture_size();
+        if batches
+            .iter()
+            .any(|(_, texture)| texture.get_width() > max_size || texture.get_height() > max_size)
+        {
+            return Err(TextureCreationError::DimensionsNotSupported);
+        }
+
+        let mut latest: HashMap<u32, &Texture> = HashMap::new();
+        for &(id, texture) in batches {
+            latest.insert(id, texture);
+        }
+
+        for (id, texture) in latest {
+            let width = texture.get_width();
+            let height = texture.get_height();
+            let raw_texture = RawImage2d {
+                data: Cow::Borrowed(texture.get_data()),
+                width,
+                height,
+                format: ClientFormat::U8U8U8,
+            };
+
+            match self.texture_cache.get(&id) {
+                Some(current_texture)
+                    if current_texture.width() == width && current_texture.height() == height =>
+                {
+                    current_texture.write(
+                        GLRect {
+                            left: 0,
+                            bottom: 0,
+                            width,
+                            height,
+                        },
+                        raw_texture,
+                    );
+                }
+                _ => {
+                    let new_texture = Texture2d::with_mipmaps(
+                        &self.context,
+                        raw_texture,
+                        MipmapsOption::NoMipmap,
+                    )?;
+                    self.texture_cache.insert(id, Rc::new(new_texture));
+                }
+            }
+            self.upload_cursors.remove(&id);
+        }
+
+        Ok(())
+    }
+
+    /// Uploads at most `max_bytes` worth of rows of `texture` (at least one
+    /// row), continuing where the previous call for `id` stopped. Returns
+    /// whether more rows remain. New textures start cleared, so glyphs in rows
+    /// which aren't uploaded yet stay invisible. A cached texture of another
+    /// size is replaced and uploaded from the first row.
+    pub fn update_texture_cache_budgeted(
+        &mut self,
+        id: u32,
+        texture: &Texture,
+        max_bytes: usize,
+    ) -> Result<bool, TextureCreationError> {
+        let max_size = self.get_max_texture_size();
+        let width = texture.get_width();
+        let height = texture.get_height();
+        if width > max_size || height > max_size {
+            return Err(TextureCreationError::DimensionsNotSupported);
+        }
+
+        let cached = self.texture_cache.get(&id);
+        if !matches!(cached, Some(t) if t.width() == width && t.height() == height) {
+            let new_texture = Texture2d::empty_with_format(
+                &self.context,
+                UncompressedFloatFormat::U8U8U8,
+                MipmapsOption::NoMipmap,
+                width,
+                height,
+            )?;
+            new_texture.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
+            self.texture_cache.insert(id, Rc::new(new_texture));
+            self.upload_cursors.insert(id, 0);
+        }
+
+        let start = match self.upload_cursors.get(&id) {
+            Some(start) => *start,
+            None => return Ok(false),
+        };
+        let row_bytes = (width * 3) as usize;
+        let rows = (max_bytes / row_bytes).max(1) as u32;
+        let end = (start + rows).min(height);
+
         let raw_texture = RawImage2d {
-            data: Cow::Borrowed(texture.get_data()),
-            width: texture.get_width(),
-            height: texture.get_height(),
+            data: Cow::Borrowed(
+                &texture.get_data()[start as usize * row_bytes..end as usize * row_bytes],
+            ),
+            width,
+            height: end - start,
             format: ClientFormat::U8U8U8,
         };
-
-        let new_texture = if let Some(current_texture) = self.texture_cache.get_mut(&id) {
-            current_texture.write(
-                GLRect {
-                    left: 0,
-                    bottom: 0,
-                    width: texture.get_width(),
-                    height: texture.get_height(),
-                },
-                raw_texture,
-            );
-            None
+        self.texture_cache[&id].write(
+            GLRect {
+                left: 0,
+                bottom: start,
+                width,
+                height: end - start,
+            },
+            raw_texture,
+        );
+
+        if end < height {
+            self.upload_cursors.insert(id, end);
+            Ok(true)
         } else {
-            Some(Texture2d::with_mipmaps(
+            self.upload_cursors.remove(&id);
+            Ok(false)
+        }
+    }
+
+    /// Uploads only the `rect` part of `texture`, e.g. a single glyph which was
+    /// just rendered. A missing or differently sized cached texture is created
+    /// cleared first, so the rest of it stays invisible until it's uploaded.
+    pub fn update_texture_cache_rect(
+        &mut self,
+        id: u32,
+        texture: &Texture,
+        rect: Rect<u32>,
+    ) -> Result<(), TextureCreationError> {
+        let max_size = self.get_max_texture_size();
+        let width = texture.get_width();
+        let height = texture.get_height();
+        if width > max_size || height > max_size {
+            return Err(TextureCreationError::DimensionsNotSupported);
+        }
+
+        let cached = self.texture_cache.get(&id);
+        if !matches!(cached, Some(t) if t.width() == width && t.height() == height) {
+            let new_texture = Texture2d::empty_with_format(
                 &self.context,
-                raw_texture,
+                UncompressedFloatFormat::U8U8U8,
                 MipmapsOption::NoMipmap,
-            )?)
-        };
+                width,
+                height,
+            )?;
+            new_texture.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
+            self.texture_cache.insert(id, Rc::new(new_texture));
+            self.upload_cursors.remove(&id);
+        }
+        if rect.width() == 0 || rect.height() == 0 {
+            return Ok(());
+        }
 
-        if let Some(new_texture) = new_texture {
-            self.texture_cache.insert(id, new_texture);
+        let data = texture.get_data();
+        let mut rect_data = Vec::with_capacity((rect.width() * rect.height() * 3) as usize);
+        for y in rect.min.y..rect.max.y {
+            let offset = 3 * (y * width + rect.min.x) as usize;
+            rect_data.extend_from_slice(&data[offset..offset + 3 * rect.width() as usize]);
         }
+        let raw_texture = RawImage2d {
+            data: Cow::Owned(rect_data),
+            width: rect.width(),
+            height: rect.height(),
+            format: ClientFormat::U8U8U8,
+        };
+        self.texture_cache[&id].write(
+            GLRect {
+                left: rect.min.x,
+                bottom: rect.min.y,
+                width: rect.width(),
+                height: rect.height(),
+            },
+            raw_texture,
+        );
 
         Ok(())
     }
 
+    /// Starts uploading a cached texture from its first row again, e.g. after
+    /// new glyphs were rendered into it.
+    pub fn restart_texture_upload(&mut self, id: u32) {
+        if self.texture_cache.contains_key(&id) {
+            self.upload_cursors.insert(id, 0);
+        }
+    }
+
     pub fn get_texture(&self, id: u32) -> Option<&Texture2d> {
-        self.texture_cache.get(&id)
+        self.texture_cache.get(&id).map(|texture| &**texture)
+    }
+
+    /// Shared handle to a cached texture, e.g. for previewing it with `UIImage`.
+    /// Textures are recreated when the font changes, so fetch it again then.
+    pub fn get_shared_texture(&self, id: u32) -> Option<Rc<Texture2d>> {
+        self.texture_cache.get(&id).cloned()
+    }
+
+    /// Uploads `Font::debug_atlas_image` of atlas `id`, which shows how glyphs
+    /// are packed. It isn't cached, so create it only when the atlas changes.
+    /// Returns `Ok(None)` when the atlas doesn't exist anymore.
+    pub fn create_packing_texture(
+        &self,
+        id: u32,
+    ) -> Result<Option<Rc<Texture2d>>, TextureCreationError> {
+        let texture = match self.font.debug_atlas_image(id) {
+            Some(texture) => texture,
+            None => return Ok(None),
+        };
+        let raw_texture = RawImage2d {
+            data: Cow::Borrowed(texture.get_data()),
+            width: texture.get_width(),
+            height: texture.get_height(),
+            format: ClientFormat::U8U8U8,
+        };
+        Ok(Some(Rc::new(Texture2d::with_mipmaps(
+            &self.context,
+            raw_texture,
+            MipmapsOption::NoMipmap,
+        )?)))
     }
 
     pub fn get_texture_render_batches(&mut self) -> Vec<TextureRenderBatch> {
@@ -249,33 +848,312 @@ impl UITextAreaContext {
 struct UITextAreaGlyphVertex {
     pos: [f32; 2],
     coord: [f32; 2],
+    bounds: [f32; 4],
 }
 
-implement_vertex!(UITextAreaGlyphVertex, pos, coord);
+implement_vertex!(UITextAreaGlyphVertex, pos, coord, bounds);
 
 impl UITextAreaGlyphVertex {
-    fn new(pos_x: f32, pos_y: f32, coord_x: f32, coord_y: f32) -> Self {
+    fn new(pos_x: f32, pos_y: f32, coord_x: f32, coord_y: f32, bounds: Rect<f32>) -> Self {
         Self {
             pos: [pos_x, pos_y],
             coord: [coord_x, coord_y],
+            bounds: [bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct UITextAreaGlowVertex {
+    pos: [f32; 2],
+}
+
+implement_vertex!(UITextAreaGlowVertex, pos);
+
+struct UITextAreaEdit {
+    index: usize,
+    removed: String,
+    inserted: String,
+    caret_before: usize,
+    caret_after: usize,
+    time: Instant,
+}
+
+struct UITextAreaHistory {
+    undo: VecDeque<UITextAreaEdit>,
+    redo: Vec<UITextAreaEdit>,
+    limit: usize,
+}
+
+impl UITextAreaHistory {
+    // Consecutive single character inserts typed within this window are undone together.
+    const COALESCE_WINDOW: Duration = Duration::from_secs(1);
+
+    fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit,
+        }
+    }
+
+    fn push(&mut self, edit: UITextAreaEdit) {
+        self.redo.clear();
+
+        if let Some(last) = self.undo.back_mut() {
+            let is_typing = edit.removed.is_empty()
+                && edit.inserted.chars().count() == 1
+                && !edit.inserted.chars().all(char::is_whitespace);
+            if is_typing
+                && last.removed.is_empty()
+                && last.caret_after == edit.caret_before
+                && last.time.elapsed() < Self::COALESCE_WINDOW
+            {
+                last.inserted.push_str(&edit.inserted);
+                last.caret_after = edit.caret_after;
+                last.time = edit.time;
+                return;
+            }
+        }
+
+        self.push_undo(edit);
+    }
+
+    // Adds an undoable edit, dropping the oldest ones above the limit.
+    fn push_undo(&mut self, edit: UITextAreaEdit) {
+        self.undo.push_back(edit);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+}
+
+struct UITextAreaLine {
+    range: Range<usize>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum UITextAreaCharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+impl UITextAreaCharClass {
+    fn from_char(c: char) -> Self {
+        if c.is_whitespace() {
+            UITextAreaCharClass::Whitespace
+        } else if c.is_alphanumeric() || c == '_' {
+            UITextAreaCharClass::Word
+        } else {
+            UITextAreaCharClass::Punctuation
+        }
+    }
+}
+
+const SOFT_HYPHEN: char = '\u{AD}';
+const AUTOSCROLL_MARGIN: f32 = 8.0;
+// Relative change of the display size which makes pixel perfect areas
+// rasterize the atlas again. Smaller changes keep the current atlas scaled.
+const PIXEL_PERFECT_TOLERANCE: f32 = 0.05;
+const ELLIPSIS: &str = "\u{2026}";
+
+// Word as displayed: soft hyphens are invisible.
+fn displayed_word(word: &str) -> Cow<'_, str> {
+    if !word.contains(SOFT_HYPHEN) {
+        return Cow::Borrowed(word);
+    }
+    Cow::Owned(word.chars().filter(|&c| c != SOFT_HYPHEN).collect())
+}
+
+// Part of a word drawn with one run style, placed `x` from the word start.
+// Glyphs are scaled by `scale` relative to the area's text size.
+struct UITextAreaWordPart {
+    layout: TextBlockLayout,
+    x: f32,
+    scale: f32,
+    style: usize,
+}
+
+struct UITextAreaWord {
+    parts: Vec<UITextAreaWordPart>,
+    width: f32,
+    end: f32,
+    // Pen position before every char of the word, followed by `end`.
+    offsets: Vec<f32>,
+}
+
+// Lays out `word`, starting at char `start` of the text, split into parts at
+// run boundaries. `char_styles` holds the run style index and scale of every
+// char. `suffix` is appended to the last part.
+fn layout_word(
+    font: &mut Font,
+    char_styles: &[(usize, f32)],
+    start: usize,
+    word: &str,
+    suffix: &str,
+) -> UITextAreaWord {
+    let style_of = |index: usize| char_styles.get(index).cloned().unwrap_or((0, 1.0));
+
+    let mut groups: Vec<(Range<usize>, (usize, f32))> = Vec::new();
+    for (char_index, (index, c)) in word.char_indices().enumerate() {
+        let style = style_of(start + char_index);
+        let end = index + c.len_utf8();
+        match groups.last_mut() {
+            Some((range, last)) if *last == style => range.end = end,
+            _ => groups.push((index..end, style)),
+        }
+    }
+    if groups.is_empty() {
+        groups.push((0..0, style_of(start)));
+    }
+
+    let count = groups.len();
+    let mut parts = Vec::with_capacity(count);
+    let mut offsets = Vec::with_capacity(word.len() + 1);
+    let mut min_x = 0.0;
+    let mut x = 0.0;
+    for (index, (range, (style, scale))) in groups.into_iter().enumerate() {
+        let mut text = displayed_word(&word[range.clone()]).into_owned();
+        if index + 1 == count {
+            text.push_str(suffix);
+        }
+        let layout = font.layout_text_block(&text);
+        if index == 0 {
+            min_x = layout.bounding_box.min.x * scale;
         }
+
+        // Soft hyphens aren't displayed, so they share the next char's stop.
+        let advances = layout.lines.first().map_or(&[][..], |line| &line.advances);
+        let mut advances = advances.iter();
+        let mut pen = x;
+        for c in word[range].chars() {
+            offsets.push(pen);
+            if c != SOFT_HYPHEN {
+                pen += advances.next().unwrap_or(&0.0) * scale;
+            }
+        }
+
+        let end = x + layout.bounding_box.max.x * scale;
+        parts.push(UITextAreaWordPart {
+            layout,
+            x,
+            scale,
+            style,
+        });
+        x = end;
+    }
+
+    offsets.push(x);
+
+    UITextAreaWord {
+        parts,
+        width: x - min_x,
+        end: x,
+        offsets,
+    }
+}
+
+// Byte index at which `word` is broken to fit into `available` width, and
+// whether the break shows a hyphen. `width` measures a prefix of the word,
+// optionally followed by a hyphen. Soft hyphens are preferred. Words wider
+// than a whole line are moved to a new line (index 0) and broken anywhere
+// there, keeping at least one character so wrapping always makes progress.
+fn word_break<F: FnMut(&str, bool) -> f32>(
+    mut width: F,
+    word: &str,
+    available: f32,
+    line_width: f32,
+    line_start: bool,
+) -> Option<(usize, bool)> {
+    let word_width = width(word, false);
+    if word_width <= available {
+        return None;
+    }
+
+    let soft_break = word
+        .char_indices()
+        .filter(|&(_, c)| c == SOFT_HYPHEN)
+        .map(|(index, c)| index + c.len_utf8())
+        .filter(|&end| end < word.len())
+        .take_while(|&end| width(&word[..end], true) <= available)
+        .last();
+    if let Some(end) = soft_break {
+        return Some((end, true));
+    }
+
+    if word_width <= line_width {
+        return None;
+    }
+    if !line_start {
+        return Some((0, false));
     }
+
+    let first_end = word.chars().next().map_or(word.len(), char::len_utf8);
+    let end = word
+        .char_indices()
+        .map(|(index, c)| index + c.len_utf8())
+        .filter(|&end| end < word.len())
+        .take_while(|&end| width(&word[..end], false) <= available)
+        .last()
+        .unwrap_or(first_end);
+    if end < word.len() {
+        Some((end, false))
+    } else {
+        None
+    }
+}
+
+// Char range of the committed text drawn with a run style.
+struct UITextAreaRun {
+    range: Range<usize>,
+    style: UITextAreaRunStyle,
 }
 
-struct UITextAreaRenderPass {
-    vertex_buffer: VertexBuffer<UITextAreaGlyphVertex>,
-    index_buffer: IndexBuffer<u16>,
+// Style transition started by `UITextArea::animate_to`.
+#[derive(Clone, Copy)]
+struct UITextAreaTransition {
+    from: UITextAreaStyle,
+    to: UITextAreaStyle,
+    start: Instant,
+    duration: Duration,
+    easing: UIEasing,
 }
 
 pub struct UITextArea {
     style: UITextAreaStyle,
-    passes: HashMap<u32, UITextAreaRenderPass>,
+    transition: Option<UITextAreaTransition>,
+    // Keyed by texture id and index into `run_styles`.
+    passes: HashMap<(u32, usize), GlyphRenderPass<UITextAreaGlyphVertex>>,
+    runs: Vec<UITextAreaRun>,
+    run_styles: Vec<UITextAreaRunStyle>,
     context: Rc<RefCell<UITextAreaContext>>,
+    marker: UIBlock,
     last_size: UISize,
     last_text: String,
+    caret: usize,
+    caret_stops: Vec<UIPoint>,
+    history: UITextAreaHistory,
+    lines: Vec<UITextAreaLine>,
+    composition: Option<String>,
+    selection: Option<Range<usize>>,
+    composition_underlines: Vec<Rect<f32>>,
+    content_size: UISize,
+    aligned: bool,
+    dynamic: bool,
+    hyphenate: bool,
+    max_lines: Option<usize>,
+    pixel_perfect: bool,
+    scale_factor: f32,
     offset: UIPoint,
     drag_offset: UIPoint,
     drag_start: Option<UIPoint>,
+    // Caret and stop count the view was last scrolled for.
+    scrolled_caret: Option<(usize, usize)>,
     zoom: f32,
     mouse_x: f32,
     mouse_y: f32,
@@ -287,16 +1165,55 @@ impl UITextArea {
         text: &str,
         style: UITextAreaStyle,
     ) -> Self {
+        let marker = UIBlock::new(
+            context.borrow().block_context.clone(),
+            UIBlockStyle {
+                alpha: 1.0,
+                radius: 0.0,
+                sharpness: 1.0,
+                left_offset: 0.0,
+                left_color: [1.0, 1.0, 1.0],
+                right_offset: 0.0,
+                right_color: [1.0, 1.0, 1.0],
+                inner_shadow: 1.0,
+                shade_color: [1.0, 1.0, 1.0],
+                border_width: 0.0,
+                border_color: [0.0, 0.0, 0.0],
+                shadow_color: [0.0, 0.0, 0.0, 0.0],
+                shadow_blur: 0.0,
+                shadow_offset: [0.0, 0.0],
+            },
+        );
+
         Self {
             context,
+            marker,
             last_size: UISize::zero(),
             last_text: text.into(),
+            caret: text.chars().count(),
+            caret_stops: Vec::new(),
+            history: UITextAreaHistory::new(100),
+            lines: Vec::new(),
+            composition: None,
+            selection: None,
+            composition_underlines: Vec::new(),
+            content_size: UISize::zero(),
+            aligned: true,
+            dynamic: false,
+            hyphenate: true,
+            max_lines: None,
+            pixel_perfect: false,
+            scale_factor: 1.0,
             offset: UIPoint::zero(),
             drag_offset: UIPoint::zero(),
             drag_start: None,
+            scrolled_caret: None,
             zoom: 1.0,
             passes: HashMap::new(),
+            runs: Vec::new(),
+            run_styles: Vec::new(),
             style,
+            transition: None,
             mouse_x: 0.0,
             mouse_y: 0.0,
         }
@@ -306,31 +1223,520 @@ impl UITextArea {
         self.style
     }
 
+    /// Switches to `style` immediately, stopping a running transition.
     pub fn set_style(&mut self, style: UITextAreaStyle) {
-        self.style = style;
+        self.transition = None;
+        self.apply_style(style);
     }
 
-    pub fn set_text(&mut self, text: &str) {
-        if self.last_text != text {
-            self.last_text = text.into();
-            self.invalidate();
+    /// Transitions from the current style to `style` over `duration`, with
+    /// progress shaped by `easing`. The style advances whenever the area
+    /// updates its input and `get_style` returns the intermediate one.
+    pub fn animate_to(&mut self, style: UITextAreaStyle, duration: Duration, easing: UIEasing) {
+        if duration == Duration::from_secs(0) {
+            self.set_style(style);
+            return;
         }
+        self.transition = Some(UITextAreaTransition {
+            from: self.style,
+            to: style,
+            start: Instant::now(),
+            duration,
+            easing,
+        });
     }
 
-    pub fn invalidate(&mut self) {
-        let mut context = self.context.borrow_mut();
+    pub fn is_animating(&self) -> bool {
+        self.transition.is_some()
+    }
 
-        enum FormattedText<'a> {
-            End,
-            NewLine,
-            Word(&'a str),
+    fn apply_style(&mut self, style: UITextAreaStyle) {
+        if style.content_align != self.style.content_align {
+            self.aligned = true;
         }
-
+        self.style = style;
+    }
+
+    fn update_transition(&mut self) {
+        let transition = match self.transition {
+            Some(transition) => transition,
+            None => return,
+        };
+        let progress = transition.start.elapsed().as_secs_f64() / transition.duration.as_secs_f64();
+        if progress >= 1.0 {
+            self.transition = None;
+            self.apply_style(transition.to);
+        } else {
+            let t = transition.easing.value(progress as f32);
+            self.apply_style(transition.from.lerp(&transition.to, t));
+        }
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.last_text
+    }
+
+    /// Dynamic text areas rewrite their glyph buffers in place on every edit
+    /// instead of allocating new ones.
+    pub fn set_dynamic(&mut self, dynamic: bool) {
+        self.dynamic = dynamic;
+    }
+
+    /// Lets wrapping break words at soft hyphens, showing a hyphen, and break
+    /// words which don't fit into a whole line anywhere. Enabled by default.
+    pub fn set_hyphenate(&mut self, hyphenate: bool) {
+        if self.hyphenate != hyphenate {
+            self.hyphenate = hyphenate;
+            self.invalidate();
+        }
+    }
+
+    /// Limits how many lines are laid out. When the text doesn't fit, the last
+    /// line is shortened to end with an ellipsis. At least one line is shown.
+    pub fn set_max_lines(&mut self, max_lines: Option<usize>) {
+        if self.max_lines != max_lines {
+            self.max_lines = max_lines;
+            self.invalidate();
+        }
+    }
+
+    /// Rasterizes the atlas at the size the text is displayed at, in device
+    /// pixels of a display with `scale_factor`, instead of scaling it from the
+    /// context's font size. The atlas is rendered again whenever the display
+    /// size drifts by more than a few percent, e.g. when zooming. Sizes above
+    /// `UITextAreaContext::get_max_font_size` are rasterized at that size and
+    /// scaled up. The atlas is shared by all areas of the context, so only one
+    /// of them should be pixel perfect.
+    pub fn set_pixel_perfect(&mut self, pixel_perfect: bool, scale_factor: f32) {
+        self.pixel_perfect = pixel_perfect;
+        self.scale_factor = scale_factor;
+    }
+
+    pub fn is_pixel_perfect(&self) -> bool {
+        self.pixel_perfect
+    }
+
+    fn update_pixel_perfect(&mut self) {
+        if !self.pixel_perfect {
+            return;
+        }
+
+        let (font_size, display_size) = {
+            let context = self.context.borrow();
+            let display_size = self.style.text_size * self.zoom * self.scale_factor;
+            (
+                context.font.get_font_size_f32(),
+                display_size
+                    .round()
+                    .min(context.get_max_font_size().floor())
+                    .max(1.0),
+            )
+        };
+        if (display_size - font_size).abs() > font_size * PIXEL_PERFECT_TOLERANCE {
+            self.context.borrow_mut().set_font_size(display_size);
+            self.invalidate();
+        }
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        if self.last_text != text || !self.runs.is_empty() {
+            self.last_text = text.into();
+            self.runs.clear();
+            self.selection = None;
+            self.caret = self.last_text.chars().count();
+            self.history = UITextAreaHistory::new(self.history.limit);
+            self.invalidate();
+        }
+    }
+
+    /// Replaces the text with runs drawn with their own style overrides. Runs
+    /// are laid out inline and wrap together. Edits extend the run they are
+    /// made in, `set_text` removes all runs.
+    pub fn set_rich_text(&mut self, runs: Vec<UITextAreaStyledRun>) {
+        self.last_text.clear();
+        self.runs.clear();
+        self.selection = None;
+        let mut start = 0;
+        for run in runs {
+            let end = start + run.text.chars().count();
+            self.last_text.push_str(&run.text);
+            self.runs.push(UITextAreaRun {
+                range: start..end,
+                style: run.style,
+            });
+            start = end;
+        }
+        self.caret = start;
+        self.history = UITextAreaHistory::new(self.history.limit);
+        self.invalidate();
+    }
+
+    pub fn get_caret(&self) -> usize {
+        self.caret
+    }
+
+    /// Places the caret before the character with the given index, clamped to the text length.
+    pub fn set_caret(&mut self, caret: usize) {
+        self.caret = caret.min(self.last_text.chars().count());
+    }
+
+    pub fn get_selection(&self) -> Option<Range<usize>> {
+        self.selection.clone()
+    }
+
+    /// Highlights the characters in `selection`, clamped to the text length.
+    /// Edits clear the selection.
+    pub fn set_selection(&mut self, selection: Option<Range<usize>>) {
+        let len = self.last_text.chars().count();
+        self.selection = selection.map(|range| {
+            let start = range.start.min(range.end).min(len);
+            start..range.end.max(range.start).min(len)
+        });
+    }
+
+    /// Highlight rectangles of the characters in `range`, one per laid out line
+    /// it spans, in the area's coordinates in units of the text size. Lines
+    /// whose line break or wrapping whitespace is selected are extended to
+    /// show it. An empty range gives no rectangles, the caret marks it instead.
+    pub fn selection_rects(&self, range: Range<usize>) -> Vec<Rect<f32>> {
+        let context = self.context.borrow();
+        let ascent = context.font.get_ascent();
+        let descent = context.font.get_descent();
+        let break_width = 0.3;
+
+        let display_index = |index: usize| {
+            if index >= self.caret {
+                index + self.composition_len()
+            } else {
+                index
+            }
+        };
+        let start = display_index(range.start);
+        let end = display_index(range.end).min(self.caret_stops.len().saturating_sub(1));
+
+        let mut rects = Vec::new();
+        for line in &self.lines {
+            let from = start.max(line.range.start);
+            let to = end.min(line.range.end);
+            if from >= to {
+                continue;
+            }
+
+            let top = self.caret_stops[line.range.start].top + descent;
+            let left = self.caret_stops[from].left;
+            let right = if to == line.range.end {
+                self.caret_stops[to - 1].left + break_width
+            } else {
+                self.caret_stops[to].left
+            };
+            rects.push(Rect::new(left, top, right, top + ascent - descent));
+        }
+        rects
+    }
+
+    /// Caret rectangle in pixels, relative to the top left corner of the
+    /// area's layout, as drawn with the area's own style. The view offset and
+    /// zoom are applied, so the caret is visible when the rectangle lies within
+    /// `0..width` and `-height..0`. `None` until the text is laid out.
+    pub fn caret_rect(&self) -> Option<Rect<f32>> {
+        let context = self.context.borrow();
+        let scale = self.style.text_size * self.zoom;
+        let caret =
+            self.caret_marker(scale, context.font.get_ascent(), context.font.get_descent())?;
+        let left = self.offset.left + self.drag_offset.left;
+        let top = self.offset.top + self.drag_offset.top;
+        Some(Rect::new(
+            caret.min.x + left,
+            caret.min.y + top,
+            caret.max.x + left,
+            caret.max.y + top,
+        ))
+    }
+
+    /// Pans the view by the least amount which brings the caret inside
+    /// `layout`, keeping a small margin around it.
+    pub fn scroll_to_caret(&mut self, layout: UILayout) {
+        let caret = match self.caret_rect() {
+            Some(caret) => caret,
+            None => return,
+        };
+        let shift = |min: f32, max: f32, low: f32, high: f32| {
+            if max > high {
+                high - max
+            } else if min < low {
+                low - min
+            } else {
+                0.0
+            }
+        };
+
+        let margin = AUTOSCROLL_MARGIN;
+        let left = shift(caret.min.x, caret.max.x, margin, layout.width - margin);
+        let top = shift(caret.min.y, caret.max.y, -layout.height + margin, -margin);
+        if left != 0.0 || top != 0.0 {
+            self.aligned = false;
+            self.offset = UIPoint {
+                left: self.offset.left + left,
+                top: self.offset.top + top,
+            };
+        }
+    }
+
+    // Caret in pixels relative to the text origin, for text drawn at `scale`.
+    fn caret_marker(&self, scale: f32, ascent: f32, descent: f32) -> Option<Rect<f32>> {
+        let caret = self.caret_stops.get(self.display_caret())?;
+        let caret_width = (0.05 * scale).max(1.0);
+        let left = caret.left * scale - caret_width / 2.0;
+        let top = (caret.top + descent) * scale;
+        Some(Rect::new(
+            left,
+            top,
+            left + caret_width,
+            top + (ascent - descent) * scale,
+        ))
+    }
+
+    pub fn insert_text(&mut self, text: &str) {
+        self.edit(
+            self.caret..self.caret,
+            text,
+            self.caret + text.chars().count(),
+        );
+    }
+
+    /// Deletes the user-perceived character (grapheme cluster) before the caret.
+    pub fn delete_backward(&mut self) {
+        if self.caret > 0 {
+            let chars: Vec<char> = self.last_text.chars().collect();
+            let start = previous_grapheme_boundary(&chars, self.caret);
+            self.edit(start..self.caret, "", start);
+        }
+    }
+
+    /// Deletes the user-perceived character (grapheme cluster) after the caret.
+    pub fn delete_forward(&mut self) {
+        let chars: Vec<char> = self.last_text.chars().collect();
+        if self.caret < chars.len() {
+            let end = next_grapheme_boundary(&chars, self.caret);
+            self.edit(self.caret..end, "", self.caret);
+        }
+    }
+
+    /// Sets how many edits are kept for undo. Older edits are dropped first.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.limit = limit;
+        self.history.trim();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(edit) = self.history.undo.pop_back() {
+            let inserted = edit.index..edit.index + edit.inserted.chars().count();
+            self.replace(inserted, &edit.removed);
+            self.caret = edit.caret_before;
+            self.history.redo.push(edit);
+            self.invalidate();
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(edit) = self.history.redo.pop() {
+            let removed = edit.index..edit.index + edit.removed.chars().count();
+            self.replace(removed, &edit.inserted);
+            self.caret = edit.caret_after;
+            self.history.push_undo(edit);
+            self.invalidate();
+        }
+    }
+
+    fn edit(&mut self, range: Range<usize>, text: &str, caret: usize) {
+        let removed = self.replace(range.clone(), text);
+        self.history.push(UITextAreaEdit {
+            index: range.start,
+            removed,
+            inserted: text.into(),
+            caret_before: self.caret,
+            caret_after: caret,
+            time: Instant::now(),
+        });
+        self.caret = caret;
+        self.invalidate();
+    }
+
+    fn replace(&mut self, range: Range<usize>, text: &str) -> String {
+        let inserted = text.chars().count();
+        // Positions after the edit shift, positions inside it collapse to its start.
+        let shift = |index: usize| {
+            if index >= range.end {
+                index - range.len() + inserted
+            } else {
+                index.min(range.start)
+            }
+        };
+        for run in &mut self.runs {
+            run.range = shift(run.range.start)..shift(run.range.end);
+        }
+        self.runs.retain(|run| !run.range.is_empty());
+        self.selection = None;
+
+        let range = self.byte_index(range.start)..self.byte_index(range.end);
+        let removed = self.last_text[range.clone()].to_string();
+        self.last_text.replace_range(range, text);
+        removed
+    }
+
+    /// Moves the caret over one user-perceived character (grapheme cluster) to the left.
+    pub fn move_left(&mut self) {
+        let chars: Vec<char> = self.last_text.chars().collect();
+        self.caret = previous_grapheme_boundary(&chars, self.caret);
+    }
+
+    /// Moves the caret over one user-perceived character (grapheme cluster) to the right.
+    pub fn move_right(&mut self) {
+        let chars: Vec<char> = self.last_text.chars().collect();
+        self.caret = next_grapheme_boundary(&chars, self.caret);
+    }
+
+    /// Moves the caret to the start of the previous word, skipping any whitespace before it.
+    pub fn move_word_left(&mut self) {
+        let chars: Vec<char> = self.last_text.chars().collect();
+        let mut caret = self.caret;
+        while caret > 0 && chars[caret - 1].is_whitespace() {
+            caret -= 1;
+        }
+        if caret > 0 {
+            let class = UITextAreaCharClass::from_char(chars[caret - 1]);
+            while caret > 0 && UITextAreaCharClass::from_char(chars[caret - 1]) == class {
+                caret -= 1;
+            }
+        }
+        self.caret = caret;
+    }
+
+    /// Moves the caret to the end of the next word, skipping any whitespace after it.
+    pub fn move_word_right(&mut self) {
+        let chars: Vec<char> = self.last_text.chars().collect();
+        let mut caret = self.caret;
+        while caret < chars.len() && chars[caret].is_whitespace() {
+            caret += 1;
+        }
+        if caret < chars.len() {
+            let class = UITextAreaCharClass::from_char(chars[caret]);
+            while caret < chars.len() && UITextAreaCharClass::from_char(chars[caret]) == class {
+                caret += 1;
+            }
+        }
+        self.caret = caret;
+    }
+
+    /// Moves the caret to the beginning of its laid out (wrapped) line.
+    pub fn move_line_start(&mut self) {
+        if let Some(line) = self.caret_line() {
+            let start = line.range.start;
+            self.caret = self.committed_index(start);
+        }
+    }
+
+    /// Moves the caret to the end of its laid out (wrapped) line.
+    pub fn move_line_end(&mut self) {
+        if let Some(line) = self.caret_line() {
+            let end = line.range.end - 1;
+            self.caret = self.committed_index(end);
+        }
+    }
+
+    pub fn get_composition(&self) -> Option<&str> {
+        self.composition.as_deref()
+    }
+
+    /// Displays an in-progress IME composition at the caret. Passing `None` cancels the
+    /// composition.
+    ///
+    /// Nothing in the demo calls this or `commit_composition` yet: winit 0.25
+    /// doesn't report IME preedit events, so composed text only arrives as
+    /// committed `ReceivedCharacter`s.
+    pub fn set_composition(&mut self, composition: Option<&str>) {
+        if self.composition.as_deref() != composition {
+            self.composition = composition.map(String::from);
+            self.invalidate();
+        }
+    }
+
+    /// Inserts the current composition at the caret and returns the committed text.
+    pub fn commit_composition(&mut self) -> Option<String> {
+        let composition = self.composition.take()?;
+        self.insert_text(&composition);
+        Some(composition)
+    }
+
+    fn byte_index(&self, index: usize) -> usize {
+        self.last_text
+            .char_indices()
+            .nth(index)
+            .map_or(self.last_text.len(), |(byte_index, _)| byte_index)
+    }
+
+    fn composition_len(&self) -> usize {
+        self.composition.as_ref().map_or(0, |c| c.chars().count())
+    }
+
+    // Caret stops and lines are computed for the displayed text, which includes the
+    // composition placed right before the caret.
+    fn display_caret(&self) -> usize {
+        self.caret + self.composition_len()
+    }
+
+    fn committed_index(&self, display_index: usize) -> usize {
+        if display_index >= self.display_caret() {
+            display_index - self.composition_len()
+        } else {
+            display_index.min(self.caret)
+        }
+    }
+
+    fn caret_line(&self) -> Option<&UITextAreaLine> {
+        let caret = self.display_caret();
+        self.lines.iter().find(|line| line.range.contains(&caret))
+    }
+
+    pub fn invalidate(&mut self) {
+        let mut context = self.context.borrow_mut();
+
+        enum FormattedText<'a> {
+            End,
+            NewLine,
+            Word(usize, &'a str),
+        }
+
+        struct LineWord {
+            layout: UITextAreaWord,
+            start: usize,
+            offsets: Vec<f32>,
+        }
+
         struct ProcessTextCtx {
             line_y: f32,
             line_total_space: f32,
             line_word_space: f32,
-            line_words: VecDeque<TextBlockLayout>,
+            line_words: VecDeque<LineWord>,
+            line_count: usize,
+            // Alignment of the last allowed line, once it's full.
+            line_full: Option<bool>,
+            truncated: bool,
+        }
+
+        enum LineEnd {
+            Wrap,
+            NewLine,
+            End,
+            Truncate,
         }
 
         struct PassData {
@@ -339,7 +1745,7 @@ impl UITextArea {
         }
 
         struct RenderWordContext {
-            passes: HashMap<u32, PassData>,
+            passes: HashMap<(u32, usize), PassData>,
         }
 
         let line_gap = context.font.get_line_gap();
@@ -348,69 +1754,191 @@ impl UITextArea {
         let line_height = line_gap + ascent - descent;
         let line_max_width = self.last_size.width / self.style.text_size;
         let line_min_space = 0.3;
+        let underline_pos = descent / 2.0;
+        let underline_thickness = 0.05;
+        let hyphenate = self.hyphenate;
+        let max_lines = self.max_lines;
+
+        let text: Cow<str> = match &self.composition {
+            Some(composition) => {
+                let mut text = self.last_text.clone();
+                text.insert_str(self.byte_index(self.caret), composition);
+                Cow::Owned(text)
+            }
+            None => Cow::Borrowed(&self.last_text),
+        };
+        let composition_range = self.caret..self.display_caret();
+        let mut composition_underlines = Vec::new();
+        let mut caret_stops = vec![None; text.chars().count() + 1];
+
+        let mut run_styles = vec![UITextAreaRunStyle::default()];
+        let mut char_styles = vec![(0, 1.0); caret_stops.len() - 1];
+        for run in &self.runs {
+            let style = match run_styles.iter().position(|style| *style == run.style) {
+                Some(style) => style,
+                None => {
+                    run_styles.push(run.style);
+                    run_styles.len() - 1
+                }
+            };
+            let scale = run
+                .style
+                .size
+                .map_or(1.0, |size| size / self.style.text_size);
+            let display_index = |index: usize| {
+                if index >= self.caret {
+                    index + self.composition_len()
+                } else {
+                    index
+                }
+            };
+            let end = display_index(run.range.end).min(char_styles.len());
+            for char_style in &mut char_styles[display_index(run.range.start).min(end)..end] {
+                *char_style = (style, scale);
+            }
+        }
+        let char_styles = &char_styles;
 
         let mut render_word_ctx = RenderWordContext {
             passes: HashMap::new(),
         };
 
-        let mut render_word = |word_layout: &TextBlockLayout, x: f32, y: f32| {
+        let mut render_word = |word_layout: &UITextAreaWord, x: f32, y: f32| {
             let ctx = &mut render_word_ctx;
-            for glyph_layout in &word_layout.glyph_layouts {
-                let pass_data = ctx
-                    .passes
-                    .entry(glyph_layout.texture_id)
-                    .or_insert(PassData {
-                        vertices: Vec::new(),
-                        indices: Vec::new(),
-                    });
-
-                let new_index = pass_data.vertices.len();
-                let scr = glyph_layout.screen_coord;
-                let tex = glyph_layout.texture_coord;
-
-                let tl =
-                    UITextAreaGlyphVertex::new(scr.min.x + x, scr.max.y + y, tex.min.x, tex.max.y);
-                let tr =
-                    UITextAreaGlyphVertex::new(scr.max.x + x, scr.max.y + y, tex.max.x, tex.max.y);
-                let bl =
-                    UITextAreaGlyphVertex::new(scr.min.x + x, scr.min.y + y, tex.min.x, tex.min.y);
-                let br =
-                    UITextAreaGlyphVertex::new(scr.max.x + x, scr.min.y + y, tex.max.x, tex.min.y);
-
-                pass_data.vertices.push(tl);
-                pass_data.vertices.push(tr);
-                pass_data.vertices.push(br);
-                pass_data.vertices.push(bl);
-
-                pass_data.indices.push(new_index as u16);
-                pass_data.indices.push((new_index + 1) as u16);
-                pass_data.indices.push((new_index + 2) as u16);
-                pass_data.indices.push((new_index + 2) as u16);
-                pass_data.indices.push((new_index + 3) as u16);
-                pass_data.indices.push(new_index as u16);
+            for part in &word_layout.parts {
+                for glyph_layout in &part.layout.glyph_layouts {
+                    let pass_data = ctx
+                        .passes
+                        .entry((glyph_layout.texture_id, part.style))
+                        .or_insert(PassData {
+                            vertices: Vec::new(),
+                            indices: Vec::new(),
+                        });
+
+                    let tex = glyph_layout.texture_coord;
+                    let (vertices, indices) =
+                        glyph_layout.quad(pass_data.vertices.len() as u16, |pos, uv| {
+                            UITextAreaGlyphVertex::new(
+                                pos.x * part.scale + part.x + x,
+                                pos.y * part.scale + y,
+                                uv.x,
+                                uv.y,
+                                tex,
+                            )
+                        });
+                    pass_data.vertices.extend_from_slice(&vertices);
+                    pass_data.indices.extend_from_slice(&indices);
+                }
             }
         };
 
-        let mut layout_line = |text_ctx: &mut ProcessTextCtx, align: bool| {
-            let word_count = text_ctx.line_words.len();
-            if word_count == 0 {
+        let mut layout_line = |text_ctx: &mut ProcessTextCtx,
+                               font: &mut Font,
+                               line_end: LineEnd| {
+            let align = match line_end {
+                LineEnd::Wrap => true,
+                LineEnd::NewLine | LineEnd::End => false,
+                LineEnd::Truncate => text_ctx.line_full.unwrap_or(false),
+            };
+            let breaks = matches!(line_end, LineEnd::Wrap | LineEnd::NewLine);
+
+            // The last allowed line is held back until it's known whether any
+            // text follows it, in which case it ends with an ellipsis.
+            if breaks && matches!(max_lines, Some(max) if text_ctx.line_count + 1 >= max) {
+                text_ctx.line_full = Some(align);
                 return;
             }
 
+            let mut hidden = None;
+            if let LineEnd::Truncate = line_end {
+                let ellipsis_width = font.layout_text_block(ELLIPSIS).bounding_box.width();
+                loop {
+                    let words = &mut text_ctx.line_words;
+                    let width = words.iter().map(|word| word.layout.width).sum::<f32>()
+                        + words.len().saturating_sub(1) as f32 * line_min_space;
+                    let chars = words.back().map_or(0, |word| word.offsets.len() - 1);
+                    if width + ellipsis_width <= line_max_width || (words.len() <= 1 && chars <= 1)
+                    {
+                        break;
+                    }
+                    if chars <= 1 {
+                        words.pop_back();
+                        continue;
+                    }
+
+                    let word = words.back_mut().expect("Cannot find last word");
+                    word.offsets.pop();
+                    let prefix: String = text.chars().skip(word.start).take(chars - 1).collect();
+                    word.layout = layout_word(font, char_styles, word.start, &prefix, "");
+                    *word.offsets.last_mut().expect("Cannot find word end") = word.layout.end;
+                }
+
+                if let Some(word) = text_ctx.line_words.back_mut() {
+                    let chars = word.offsets.len() - 1;
+                    let prefix: String = text.chars().skip(word.start).take(chars).collect();
+                    word.layout = layout_word(font, char_styles, word.start, &prefix, ELLIPSIS);
+                    hidden = Some(word.start + chars);
+                }
+                text_ctx.line_word_space = text_ctx
+                    .line_words
+                    .iter()
+                    .map(|word| word.layout.width)
+                    .sum();
+                text_ctx.truncated = true;
+            }
+
+            let word_count = text_ctx.line_words.len();
             let mut line_x = 0.0;
-            let space = if align {
+            let mut line_end_x = 0.0;
+            let space = if align && word_count > 1 {
                 (line_max_width - text_ctx.line_word_space) / (word_count - 1) as f32
             } else {
                 line_min_space
             };
 
             while let Some(word) = text_ctx.line_words.pop_front() {
-                render_word(&word, line_x, text_ctx.line_y);
-                line_x += word.bounding_box.width() + space;
+                render_word(&word.layout, line_x, text_ctx.line_y);
+
+                for (index, offset) in word.offsets.iter().enumerate() {
+                    caret_stops[word.start + index] = Some(UIPoint {
+                        left: line_x + offset,
+                        top: text_ctx.line_y,
+                    });
+                }
+
+                let end = word.start + word.offsets.len() - 1;
+                if word.start < composition_range.end && end > composition_range.start {
+                    let from = composition_range.start.max(word.start) - word.start;
+                    let to = composition_range.end.min(end) - word.start;
+                    let y = text_ctx.line_y + underline_pos;
+                    composition_underlines.push(Rect::new(
+                        line_x + word.offsets[from],
+                        y - underline_thickness,
+                        line_x + word.offsets[to],
+                        y,
+                    ));
+                }
+
+                line_end_x = line_x + word.layout.end;
+                line_x += word.layout.width + space;
+            }
+
+            // Hidden text keeps its caret stops, all placed after the ellipsis.
+            if let Some(hidden) = hidden {
+                for stop in &mut caret_stops[hidden + 1..] {
+                    *stop = Some(UIPoint {
+                        left: line_end_x,
+                        top: text_ctx.line_y,
+                    });
+                }
             }
 
             text_ctx.line_total_space = 0.0;
             text_ctx.line_word_space = 0.0;
+            if breaks {
+                text_ctx.line_y -= line_height;
+                text_ctx.line_count += 1;
+            }
         };
 
         let mut process_text_ctx = ProcessTextCtx {
@@ -418,64 +1946,126 @@ impl UITextArea {
             line_total_space: 0.0,
             line_word_space: 0.0,
             line_words: VecDeque::new(),
+            line_count: 0,
+            line_full: None,
+            truncated: false,
         };
 
         let mut process_text = |formatted_text: FormattedText| {
-            let mut ctx = &mut process_text_ctx;
+            let ctx = &mut process_text_ctx;
+            if ctx.truncated {
+                return;
+            }
             match formatted_text {
                 FormattedText::End => {
-                    layout_line(ctx, false);
+                    layout_line(ctx, &mut context.font, LineEnd::End);
                 }
                 FormattedText::NewLine => {
-                    layout_line(ctx, false);
-                    ctx.line_y -= line_height
+                    layout_line(ctx, &mut context.font, LineEnd::NewLine);
                 }
-                FormattedText::Word(word) => {
-                    let word_layout = context.font.layout_text_block(word);
-                    let word_width = word_layout.bounding_box.width();
-                    if word_width <= line_max_width - ctx.line_total_space {
+                FormattedText::Word(start, word) => {
+                    let (mut start, mut word) = (start, word);
+                    loop {
+                        if ctx.line_full.is_some() {
+                            if ctx.line_words.is_empty() {
+                                ctx.line_words.push_back(LineWord {
+                                    layout: layout_word(
+                                        &mut context.font,
+                                        char_styles,
+                                        start,
+                                        "",
+                                        "",
+                                    ),
+                                    start,
+                                    offsets: vec![0.0],
+                                });
+                            }
+                            layout_line(ctx, &mut context.font, LineEnd::Truncate);
+                            break;
+                        }
+
+                        let available = line_max_width - ctx.line_total_space;
+                        let line_start = ctx.line_words.is_empty();
+                        let split = if hyphenate {
+                            let font = &mut context.font;
+                            word_break(
+                                |text, hyphen| {
+                                    let suffix = if hyphen { "-" } else { "" };
+                                    layout_word(font, char_styles, start, text, suffix).width
+                                },
+                                word,
+                                available,
+                                line_max_width,
+                                line_start,
+                            )
+                        } else {
+                            None
+                        };
+                        let (piece, hyphen) = match split {
+                            Some((0, _)) => {
+                                layout_line(ctx, &mut context.font, LineEnd::Wrap);
+                                continue;
+                            }
+                            Some((end, hyphen)) => (&word[..end], hyphen),
+                            None => (word, false),
+                        };
+
+                        let suffix = if hyphen { "-" } else { "" };
+                        let word_layout =
+                            layout_word(&mut context.font, char_styles, start, piece, suffix);
+                        let word_width = word_layout.width;
+                        if word_width > available && !line_start {
+                            layout_line(ctx, &mut context.font, LineEnd::Wrap);
+                            continue;
+                        }
+
+                        let offsets = word_layout.offsets.clone();
                         ctx.line_total_space += word_width + line_min_space;
                         ctx.line_word_space += word_width;
-                    } else {
-                        layout_line(ctx, true);
-                        ctx.line_y -= line_height;
-                        ctx.line_total_space = word_width + line_min_space;
-                        ctx.line_word_space = word_width;
+                        ctx.line_words.push_back(LineWord {
+                            layout: word_layout,
+                            start,
+                            offsets,
+                        });
+
+                        if piece.len() == word.len() {
+                            break;
+                        }
+                        start += piece.chars().count();
+                        word = &word[piece.len()..];
+                        layout_line(ctx, &mut context.font, LineEnd::Wrap);
                     }
-                    ctx.line_words.push_back(word_layout);
                 }
             };
         };
 
         let mut format_text = || {
             let mut word_start = None;
-            for (index, character) in self.last_text.char_indices() {
+            for (char_index, (index, character)) in text.char_indices().enumerate() {
                 match character {
                     '\n' => {
-                        if let Some(start) = word_start {
-                            process_text(FormattedText::Word(&self.last_text[start..index]));
+                        if let Some((start, start_index)) = word_start {
+                            process_text(FormattedText::Word(start, &text[start_index..index]));
                             word_start = None;
                         }
                         process_text(FormattedText::NewLine);
                     }
                     x if x.is_whitespace() => {
-                        if let Some(start) = word_start {
-                            process_text(FormattedText::Word(&self.last_text[start..index]));
+                        if let Some((start, start_index)) = word_start {
+                            process_text(FormattedText::Word(start, &text[start_index..index]));
                             word_start = None;
                         }
                     }
                     _ => {
                         if word_start.is_none() {
-                            word_start = Some(index);
+                            word_start = Some((char_index, index));
                         }
                     }
                 }
             }
 
-            if let Some(start) = word_start {
-                process_text(FormattedText::Word(
-                    &self.last_text[start..self.last_text.len()],
-                ));
+            if let Some((start, start_index)) = word_start {
+                process_text(FormattedText::Word(start, &text[start_index..text.len()]));
             }
 
             process_text(FormattedText::End);
@@ -483,91 +2073,323 @@ impl UITextArea {
 
         format_text();
 
-        let mut gl_passes = HashMap::<u32, UITextAreaRenderPass>::new();
+        // Stops that don't belong to any word are whitespace. They follow the previous stop,
+        // or start a new line after a line break.
+        let mut stops: Vec<UIPoint> = Vec::with_capacity(caret_stops.len());
+        let mut lines: Vec<UITextAreaLine> = Vec::new();
+        let mut previous_char = None;
+        let mut chars = text.chars();
+
+        for (index, stop) in caret_stops.into_iter().enumerate() {
+            let stop = stop.unwrap_or_else(|| match (stops.last(), previous_char) {
+                (Some(last), Some('\n')) => UIPoint {
+                    left: 0.0,
+                    top: last.top - line_height,
+                },
+                (Some(last), _) => UIPoint {
+                    left: last.left + line_min_space,
+                    top: last.top,
+                },
+                (None, _) => UIPoint {
+                    left: 0.0,
+                    top: -ascent,
+                },
+            });
+
+            let same_line = previous_char != Some('\n')
+                && matches!(stops.last(), Some(last) if (last.top - stop.top).abs() < f32::EPSILON);
+
+            match lines.last_mut() {
+                Some(line) if same_line => line.range.end = index + 1,
+                _ => lines.push(UITextAreaLine {
+                    range: index..index + 1,
+                }),
+            }
+
+            stops.push(stop);
+            previous_char = chars.next();
+        }
+
+        let mut gl_passes = HashMap::<(u32, usize), GlyphRenderPass<UITextAreaGlyphVertex>>::new();
+        let mut previous_passes = take(&mut self.passes);
         let gl_context = &context.context;
 
         for (id, pass_data) in render_word_ctx.passes {
-            let vertex_buffer = VertexBuffer::immutable(gl_context, pass_data.vertices.as_slice())
-                .expect("Cannot create vertex buffer for text_area");
+            let vertices = pass_data.vertices.as_slice();
+            let indices = pass_data.indices.as_slice();
 
-            let index_buffer = IndexBuffer::immutable(
-                gl_context,
-                PrimitiveType::TrianglesList,
-                pass_data.indices.as_slice(),
-            )
-            .expect("Cannot create index buffer for text_area");
+            let pass = match previous_passes.remove(&id) {
+                Some(mut pass) if self.dynamic && pass.fits(vertices, indices) => {
+                    pass.write(vertices, indices);
+                    pass
+                }
+                _ => GlyphRenderPass::new(gl_context, vertices, indices, self.dynamic),
+            };
 
-            gl_passes.insert(
-                id,
-                UITextAreaRenderPass {
-                    vertex_buffer,
-                    index_buffer,
-                },
-            );
+            gl_passes.insert(id, pass);
         }
 
+        let bottom = stops.iter().map(|stop| stop.top).fold(-ascent, f32::min) + descent;
+        self.content_size = UISize {
+            width: stops.iter().map(|stop| stop.left).fold(0.0, f32::max),
+            height: -bottom,
+        };
+
         self.passes = gl_passes;
+        self.run_styles = run_styles;
+        self.caret_stops = stops;
+        self.lines = lines;
+        self.composition_underlines = composition_underlines;
+    }
+
+    // Run styles override `style` only when `run_styles` is set. Their sizes are
+    // part of the glyph geometry and always apply.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_passes<S: Surface>(
+        &self,
+        surface: &mut S,
+        context: &UITextAreaContext,
+        pos: [f32; 2],
+        screen: [f32; 2],
+        style: UITextAreaStyle,
+        run_styles: bool,
+        draw_parameters: &DrawParameters,
+    ) -> Result<(), DrawError> {
+        let shadow_size = context.font.get_shadow_size();
+        let font_size = context.font.get_font_size_f32();
+        let (linear, encode_srgb) = context.color_space.get().uniforms();
+        // Scaled by the screen-space footprint of an atlas texel in the shader.
+        let sharpness = self.style.sharpness / f32::from(shadow_size);
+        let atlas_scale = [
+            font_size / context.font.get_texture_width() as f32,
+            font_size / context.font.get_texture_height() as f32,
+        ];
+
+        for (&(texture_id, style_index), pass_data) in &self.passes {
+            let style = match self.run_styles.get(style_index) {
+                Some(run_style) if run_styles => UITextAreaStyle {
+                    text_color: run_style.color.unwrap_or(style.text_color),
+                    stroke: run_style.stroke.or(style.stroke),
+                    ..style
+                },
+                _ => style,
+            };
+            let (stroke_width, stroke_color) = style.stroke.unwrap_or((0.0, Color::black()));
+
+            if let Some(texture) = context.get_texture(texture_id) {
+                surface.draw(
+                    pass_data
+                        .vertex_buffer
+                        .slice(..pass_data.vertex_count)
+                        .expect("Cannot slice vertex buffer for text_area"),
+                    pass_data
+                        .index_buffer
+                        .slice(..pass_data.index_count)
+                        .expect("Cannot slice index buffer for text_area"),
+                    &context.program,
+                    &uniform! {
+                        uTexture: texture,
+                        uInnerDist: 1.0 - style.inner_dist,
+                        uOuterDist: 1.0 - style.outer_dist,
+                        uSharpness: sharpness,
+                        uFontSize: style.text_size * self.zoom,
+                        uPosition: pos,
+                        uScreen: screen,
+                        uColor: style.text_color,
+                        uShadowColor: style.shadow_color,
+                        uShadowPos: style.shadow_pos,
+                        uShadowSize: style.shadow_size,
+                        uShadowAlpha: style.shadow_alpha,
+                        uTextureVisibility: style.texture_visibility,
+                        uShadowOffset: style.shadow_offset,
+                        uAtlasScale: atlas_scale,
+                        uMouse: [self.mouse_x, self.mouse_y],
+                        uAnimation: self.style.animation,
+                        uStroke: style.stroke.is_some(),
+                        uStrokeWidth: stroke_width / 2.0,
+                        uStrokeColor: stroke_color,
+                        uLinear: linear,
+                        uEncodeSrgb: encode_srgb
+                    },
+                    draw_parameters,
+                )?;
+            }
+        }
+
+        Ok(())
     }
 
-    pub fn render_styled(
+    pub fn render_styled<S: Surface>(
         &self,
-        frame: &mut Frame,
+        frame: &mut S,
         layout: UILayout,
         style: UITextAreaStyle,
         screen: UISize,
-    ) {
+    ) -> Result<(), DrawError> {
+        let style = UITextAreaStyle {
+            text_color: style.fill_color(),
+            ..style
+        };
         let pos = [
             layout.left + self.offset.left + self.drag_offset.left,
             layout.top + layout.height + self.offset.top + self.drag_offset.top,
         ];
-        let screen = [screen.width, screen.height];
-        let context = self.context.borrow_mut();
-        let shadow_size = context.font.get_shadow_size();
-        let font_size = context.font.get_font_size();
-        let sharpness = self.style.sharpness
-            / f32::from(shadow_size)
-            / (style.text_size * self.zoom / f32::from(font_size));
-
-        for (texture_id, pass_data) in &self.passes {
-            if let Some(texture) = context.get_texture(*texture_id) {
-                frame
-                    .draw(
-                        &pass_data.vertex_buffer,
-                        &pass_data.index_buffer,
-                        &context.program,
-                        &uniform! {
-                            uTexture: texture,
-                            uInnerDist: 1.0 - style.inner_dist,
-                            uOuterDist: 1.0 - style.outer_dist,
-                            uSharpness: sharpness,
-                            uFontSize: style.text_size * self.zoom,
-                            uPosition: pos,
-                            uScreen: screen,
-                            uColor: style.text_color,
-                            uShadowColor: style.shadow_color,
-                            uShadowPos: style.shadow_pos,
-                            uShadowSize: style.shadow_size,
-                            uShadowAlpha: style.shadow_alpha,
-                            uTextureVisibility: style.texture_visibility,
-                            uMouse: [self.mouse_x, self.mouse_y],
-                            uAnimation: self.style.animation
-                        },
-                        &DrawParameters {
-                            blend: Blend::alpha_blending(),
-                            color_mask: (true, true, true, false),
-                            ..Default::default()
-                        },
-                    )
-                    .expect("Cannot draw UITextArea pass");
+        if let Some(background) = style.background {
+            self.marker
+                .render_styled(frame, layout, background, screen)?;
+        }
+
+        if let Some(selection) = &self.selection {
+            let scale = style.text_size * self.zoom;
+            let color = [style.text_color.r, style.text_color.g, style.text_color.b];
+            let selection_style = UIBlockStyle {
+                alpha: 0.25,
+                left_color: color,
+                right_color: color,
+                shade_color: color,
+                ..self.marker.get_style()
+            };
+            for rect in self.selection_rects(selection.clone()) {
+                let selection_layout = UILayout {
+                    left: pos[0] + rect.min.x * scale,
+                    top: pos[1] + rect.min.y * scale,
+                    width: rect.width() * scale,
+                    height: rect.height() * scale,
+                };
+                self.marker
+                    .render_styled(frame, selection_layout, selection_style, screen)?;
             }
         }
+
+        let screen_size = screen;
+        let screen = [screen.width, screen.height];
+        let mut context = self.context.borrow_mut();
+
+        if let Some((radius, intensity, glow_color)) = style.glow {
+            let glow_style = UITextAreaStyle {
+                text_color: glow_color,
+                shadow_alpha: 0.0,
+                texture_visibility: 0.0,
+                stroke: None,
+                ..style
+            };
+            let (width, height) = frame.get_dimensions();
+            context.update_glow_textures(width, height);
+
+            let context = &*context;
+            let (text_texture, blur_texture) = context.glow_textures.as_ref().unwrap();
+            let premultiplied_alpha = BlendingFunction::Addition {
+                source: LinearBlendingFactor::One,
+                destination: LinearBlendingFactor::OneMinusSourceAlpha,
+            };
+
+            let mut text_buffer = SimpleFrameBuffer::new(&context.context, text_texture)
+                .expect("Cannot create glow framebuffer for UITextArea");
+            text_buffer.clear_color(0.0, 0.0, 0.0, 0.0);
+            self.draw_passes(
+                &mut text_buffer,
+                context,
+                pos,
+                screen,
+                glow_style,
+                false,
+                &ui_draw_parameters(true),
+            )?;
+
+            let mut blur_buffer = SimpleFrameBuffer::new(&context.context, blur_texture)
+                .expect("Cannot create glow framebuffer for UITextArea");
+            blur_buffer.clear_color(0.0, 0.0, 0.0, 0.0);
+            blur_buffer.draw(
+                &context.glow_quad,
+                NoIndices(PrimitiveType::TriangleStrip),
+                &context.glow_program,
+                &uniform! {
+                    uTexture: text_texture.sampled().wrap_function(SamplerWrapFunction::Clamp),
+                    uDirection: [1.0 / width as f32, 0.0],
+                    uRadius: radius,
+                    uIntensity: 1.0f32
+                },
+                &Default::default(),
+            )?;
+
+            frame.draw(
+                &context.glow_quad,
+                NoIndices(PrimitiveType::TriangleStrip),
+                &context.glow_program,
+                &uniform! {
+                    uTexture: blur_texture.sampled().wrap_function(SamplerWrapFunction::Clamp),
+                    uDirection: [0.0, 1.0 / height as f32],
+                    uRadius: radius,
+                    uIntensity: intensity
+                },
+                &DrawParameters {
+                    blend: Blend {
+                        color: premultiplied_alpha,
+                        alpha: premultiplied_alpha,
+                        constant_value: (0.0, 0.0, 0.0, 0.0),
+                    },
+                    color_mask: (true, true, true, context.write_alpha.get()),
+                    ..Default::default()
+                },
+            )?;
+        }
+
+        self.draw_passes(
+            frame,
+            &context,
+            pos,
+            screen,
+            style,
+            true,
+            &ui_draw_parameters(context.write_alpha.get()),
+        )?;
+
+        let scale = style.text_size * self.zoom;
+        let color = [style.text_color.r, style.text_color.g, style.text_color.b];
+        let marker_style = UIBlockStyle {
+            left_color: color,
+            right_color: color,
+            shade_color: color,
+            ..self.marker.get_style()
+        };
+
+        for underline in &self.composition_underlines {
+            let underline_layout = UILayout {
+                left: pos[0] + underline.min.x * scale,
+                top: pos[1] + underline.min.y * scale,
+                width: underline.width() * scale,
+                height: (underline.height() * scale).max(1.0),
+            };
+            self.marker
+                .render_styled(frame, underline_layout, marker_style, screen_size)?;
+        }
+
+        let ascent = context.font.get_ascent();
+        let descent = context.font.get_descent();
+        if let Some(caret) = self.caret_marker(scale, ascent, descent) {
+            let caret_layout = UILayout {
+                left: pos[0] + caret.min.x,
+                top: pos[1] + caret.min.y,
+                width: caret.width(),
+                height: caret.height(),
+            };
+            self.marker
+                .render_styled(frame, caret_layout, marker_style, screen_size)?;
+        }
+
+        Ok(())
     }
 }
 
 impl UIWidget for UITextArea {
     type Event = ();
 
-    fn render(&self, frame: &mut Frame, layout: UILayout, screen: UISize) {
+    fn render<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
         self.render_styled(frame, layout, self.style, screen)
     }
 
@@ -577,6 +2399,9 @@ impl UIWidget for UITextArea {
         frame_input: UIFrameInput,
         _events: &mut Vec<Self::Event>,
     ) {
+        self.update_transition();
+        self.update_pixel_perfect();
+
         self.mouse_x = frame_input.mouse_pos.left;
         self.mouse_y = frame_input.mouse_pos.top;
 
@@ -590,6 +2415,25 @@ impl UIWidget for UITextArea {
             self.invalidate();
         }
 
+        if self.aligned {
+            let (horizontal, vertical) = self.style.content_align.factors();
+            let scale = self.style.text_size * self.zoom;
+            self.offset = UIPoint {
+                left: (layout.width - self.content_size.width * scale) * horizontal,
+                top: -(layout.height - self.content_size.height * scale) * vertical,
+            };
+        }
+
+        // Follow the caret after edits and caret moves, but leave the view
+        // where it was panned to otherwise. Dragging suspends it.
+        let caret_state = (self.display_caret(), self.caret_stops.len());
+        if self.drag_start.is_none() && self.scrolled_caret != Some(caret_state) {
+            if self.scrolled_caret.is_some() {
+                self.scroll_to_caret(layout);
+            }
+            self.scrolled_caret = Some(caret_state);
+        }
+
         let left = frame_input.mouse_pos.left - layout.left;
         let top = frame_input.mouse_pos.top - layout.top - layout.height;
 
@@ -607,23 +2451,137 @@ impl UIWidget for UITextArea {
                     top: top - drag_start.top,
                 };
             }
-        } else if layout.is_inside(frame_input.mouse_pos) {
-            if frame_input.left_mouse_button_pressed {
-                self.drag_start = Some(UIPoint { left, top });
-            }
+        } else if layout.is_inside(frame_input.mouse_pos) && frame_input.left_mouse_button_pressed {
+            self.drag_start = Some(UIPoint { left, top });
+            self.aligned = false;
+        }
+    }
 
-            if let Some(mouse_wheel_delta) = frame_input.mouse_wheel_delta {
-                let new_zoom = (self.zoom + mouse_wheel_delta / 100.0 * self.zoom)
-                    .max(1.0 / 8.0)
-                    .min(128.0);
-                let new_offset_left = left - (left - self.offset.left) * (new_zoom / self.zoom);
-                let new_offset_top = top - (top - self.offset.top) * (new_zoom / self.zoom);
-                self.zoom = new_zoom;
-                self.offset = UIPoint {
-                    left: new_offset_left,
-                    top: new_offset_top,
-                };
-            }
+    fn focusable(&self) -> bool {
+        true
+    }
+
+    fn captures_input(&self) -> bool {
+        self.drag_start.is_some()
+    }
+
+    fn on_scroll(&mut self, layout: UILayout, mouse_pos: UIPoint, delta: f32) -> bool {
+        if self.drag_start.is_some() {
+            return true;
         }
+
+        let left = mouse_pos.left - layout.left;
+        let top = mouse_pos.top - layout.top - layout.height;
+        let new_zoom = (self.zoom + delta / 100.0 * self.zoom)
+            .max(1.0 / 8.0)
+            .min(128.0);
+        let new_offset_left = left - (left - self.offset.left) * (new_zoom / self.zoom);
+        let new_offset_top = top - (top - self.offset.top) * (new_zoom / self.zoom);
+        self.zoom = new_zoom;
+        self.aligned = false;
+        self.offset = UIPoint {
+            left: new_offset_left,
+            top: new_offset_top,
+        };
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::widget::tests::{font, headless_renderer};
+    use crate::ui::widget::UISurface;
+
+    fn edit(index: usize, inserted: &str) -> UITextAreaEdit {
+        UITextAreaEdit {
+            index,
+            removed: String::new(),
+            inserted: inserted.into(),
+            caret_before: index,
+            caret_after: index + inserted.chars().count(),
+            time: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn history_limit_applies_to_redo() {
+        let mut history = UITextAreaHistory::new(2);
+        // Whitespace isn't coalesced, so every edit is kept on its own.
+        for index in 0..3 {
+            history.push(edit(index, " "));
+        }
+        assert_eq!(history.undo.len(), 2);
+
+        // Undo both edits and lower the limit, as `set_history_limit` does,
+        // while they wait to be redone.
+        history.redo.push(history.undo.pop_back().unwrap());
+        history.redo.push(history.undo.pop_back().unwrap());
+        history.limit = 1;
+        history.trim();
+        while let Some(edit) = history.redo.pop() {
+            history.push_undo(edit);
+        }
+        assert_eq!(history.undo.len(), 1);
+        assert_eq!(history.undo[0].index, 2);
+    }
+
+    // Needs an X11 display with OpenGL, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn composition_is_displayed_until_committed() {
+        let (_event_loop, renderer) = match headless_renderer() {
+            Some(headless) => headless,
+            None => {
+                eprintln!("Skipping composition test: no GL context available");
+                return;
+            }
+        };
+        let block_context = Rc::new(UIBlockContext::new(&renderer));
+        let context = Rc::new(RefCell::new(UITextAreaContext::new(
+            &renderer,
+            block_context,
+            font(),
+        )));
+        let mut text_area = UITextArea::new(context, "ab", UITextAreaStyle::default());
+        text_area.last_size = UISize {
+            width: 200.0,
+            height: 100.0,
+        };
+        text_area.set_caret(2);
+
+        text_area.set_composition(Some("xy"));
+        assert_eq!(text_area.get_composition(), Some("xy"));
+        assert_eq!(text_area.get_text(), "ab");
+        assert_eq!(text_area.get_caret(), 2);
+        assert!(!text_area.composition_underlines.is_empty());
+
+        let layout = UILayout {
+            left: 0.0,
+            top: 0.0,
+            width: 200.0,
+            height: 100.0,
+        };
+        let screen = UISize {
+            width: 200.0,
+            height: 100.0,
+        };
+        let texture = Texture2d::empty(&renderer, 200, 100).expect("Cannot create texture");
+        let mut frame_buffer =
+            SimpleFrameBuffer::new(&renderer, &texture).expect("Cannot create frame buffer");
+        text_area
+            .render(
+                &mut UISurface::FrameBuffer(&mut frame_buffer),
+                layout,
+                screen,
+            )
+            .expect("Cannot render text area with composition");
+
+        assert_eq!(text_area.commit_composition(), Some("xy".to_string()));
+        assert_eq!(text_area.get_composition(), None);
+        assert_eq!(text_area.get_text(), "abxy");
+        assert_eq!(text_area.get_caret(), 4);
+        assert!(text_area.composition_underlines.is_empty());
+        assert_eq!(text_area.commit_composition(), None);
     }
 }
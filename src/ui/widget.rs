@@ -1,4 +1,15 @@
-use glium::Frame;
+use glium::backend::Context;
+use glium::draw_parameters::{BlendingFunction, LinearBlendingFactor};
+use glium::framebuffer::{MultiOutputFrameBuffer, SimpleFrameBuffer};
+use glium::index::{IndicesSource, PrimitiveType};
+use glium::uniforms::{MagnifySamplerFilter, Uniforms};
+use glium::vertex::{MultiVerticesSource, Vertex};
+use glium::{
+    Blend, BlitTarget, DrawError, DrawParameters, Frame, IndexBuffer, Program, Rect, Surface,
+    VertexBuffer,
+};
+use std::any::Any;
+use std::rc::Rc;
 
 // Helper structures ----------------------------------------------------------
 
@@ -22,12 +33,21 @@ pub struct UILayout {
     pub height: f32,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UIKey {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct UIFrameInput {
     pub mouse_pos: UIPoint,
     pub left_mouse_button_pressed: bool,
     pub right_mouse_button_pressed: bool,
-    pub mouse_wheel_delta: Option<f32>,
+    pub shift_pressed: bool,
+    pub focused: bool,
 }
 
 impl UIPoint {
@@ -90,14 +110,287 @@ impl UIFrameInput {
             mouse_pos: UIPoint::zero(),
             left_mouse_button_pressed: false,
             right_mouse_button_pressed: false,
-            mouse_wheel_delta: None,
+            shift_pressed: false,
+            focused: false,
         }
     }
 }
 
+// Render targets -------------------------------------------------------------
+
+/// Surface the manager renders into: the window frame or an offscreen
+/// framebuffer, e.g. one backed by a `Texture2d`. Widgets render into any
+/// `Surface`; the enum only exists because widgets are stored as trait objects.
+pub enum UISurface<'a, 'b> {
+    Frame(&'a mut Frame),
+    FrameBuffer(&'a mut SimpleFrameBuffer<'b>),
+}
+
+impl<'a, 'b> From<&'a mut Frame> for UISurface<'a, 'b> {
+    fn from(frame: &'a mut Frame) -> Self {
+        UISurface::Frame(frame)
+    }
+}
+
+impl<'a, 'b> From<&'a mut SimpleFrameBuffer<'b>> for UISurface<'a, 'b> {
+    fn from(frame_buffer: &'a mut SimpleFrameBuffer<'b>) -> Self {
+        UISurface::FrameBuffer(frame_buffer)
+    }
+}
+
+impl<'a, 'b> Surface for UISurface<'a, 'b> {
+    fn clear(
+        &mut self,
+        rect: Option<&Rect>,
+        color: Option<(f32, f32, f32, f32)>,
+        color_srgb: bool,
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) {
+        match self {
+            UISurface::Frame(s) => s.clear(rect, color, color_srgb, depth, stencil),
+            UISurface::FrameBuffer(s) => s.clear(rect, color, color_srgb, depth, stencil),
+        }
+    }
+
+    fn get_dimensions(&self) -> (u32, u32) {
+        match self {
+            UISurface::Frame(s) => s.get_dimensions(),
+            UISurface::FrameBuffer(s) => s.get_dimensions(),
+        }
+    }
+
+    fn get_depth_buffer_bits(&self) -> Option<u16> {
+        match self {
+            UISurface::Frame(s) => s.get_depth_buffer_bits(),
+            UISurface::FrameBuffer(s) => s.get_depth_buffer_bits(),
+        }
+    }
+
+    fn get_stencil_buffer_bits(&self) -> Option<u16> {
+        match self {
+            UISurface::Frame(s) => s.get_stencil_buffer_bits(),
+            UISurface::FrameBuffer(s) => s.get_stencil_buffer_bits(),
+        }
+    }
+
+    fn draw<'c, 'd, V, I, U>(
+        &mut self,
+        vertices: V,
+        indices: I,
+        program: &Program,
+        uniforms: &U,
+        draw_parameters: &DrawParameters<'_>,
+    ) -> Result<(), DrawError>
+    where
+        V: MultiVerticesSource<'d>,
+        I: Into<IndicesSource<'c>>,
+        U: Uniforms,
+    {
+        match self {
+            UISurface::Frame(s) => s.draw(vertices, indices, program, uniforms, draw_parameters),
+            UISurface::FrameBuffer(s) => {
+                s.draw(vertices, indices, program, uniforms, draw_parameters)
+            }
+        }
+    }
+
+    fn blit_from_frame(
+        &self,
+        source_rect: &Rect,
+        target_rect: &BlitTarget,
+        filter: MagnifySamplerFilter,
+    ) {
+        match self {
+            UISurface::Frame(s) => s.blit_from_frame(source_rect, target_rect, filter),
+            UISurface::FrameBuffer(s) => s.blit_from_frame(source_rect, target_rect, filter),
+        }
+    }
+
+    fn blit_from_simple_framebuffer(
+        &self,
+        source: &SimpleFrameBuffer<'_>,
+        source_rect: &Rect,
+        target_rect: &BlitTarget,
+        filter: MagnifySamplerFilter,
+    ) {
+        match self {
+            UISurface::Frame(s) => {
+                s.blit_from_simple_framebuffer(source, source_rect, target_rect, filter)
+            }
+            UISurface::FrameBuffer(s) => {
+                s.blit_from_simple_framebuffer(source, source_rect, target_rect, filter)
+            }
+        }
+    }
+
+    fn blit_from_multioutput_framebuffer(
+        &self,
+        source: &MultiOutputFrameBuffer<'_>,
+        source_rect: &Rect,
+        target_rect: &BlitTarget,
+        filter: MagnifySamplerFilter,
+    ) {
+        match self {
+            UISurface::Frame(s) => {
+                s.blit_from_multioutput_framebuffer(source, source_rect, target_rect, filter)
+            }
+            UISurface::FrameBuffer(s) => {
+                s.blit_from_multioutput_framebuffer(source, source_rect, target_rect, filter)
+            }
+        }
+    }
+
+    fn blit_color<S>(
+        &self,
+        source_rect: &Rect,
+        target: &S,
+        target_rect: &BlitTarget,
+        filter: MagnifySamplerFilter,
+    ) where
+        S: Surface,
+    {
+        match self {
+            UISurface::Frame(s) => s.blit_color(source_rect, target, target_rect, filter),
+            UISurface::FrameBuffer(s) => s.blit_color(source_rect, target, target_rect, filter),
+        }
+    }
+}
+
+// Glyph buffers --------------------------------------------------------------
+
+/// Vertex and index buffers of glyphs drawn from a single atlas texture.
+/// Dynamic passes are created with spare room, so that text edits can write
+/// into the same buffers while they fit.
+pub struct GlyphRenderPass<V: Vertex> {
+    pub vertex_buffer: VertexBuffer<V>,
+    pub index_buffer: IndexBuffer<u16>,
+    pub vertex_count: usize,
+    pub index_count: usize,
+}
+
+impl<V: Vertex> GlyphRenderPass<V> {
+    pub fn new(context: &Rc<Context>, vertices: &[V], indices: &[u16], dynamic: bool) -> Self {
+        if !dynamic {
+            return Self {
+                vertex_buffer: VertexBuffer::immutable(context, vertices)
+                    .expect("Cannot create vertex buffer for glyphs"),
+                index_buffer: IndexBuffer::immutable(
+                    context,
+                    PrimitiveType::TrianglesList,
+                    indices,
+                )
+                .expect("Cannot create index buffer for glyphs"),
+                vertex_count: vertices.len(),
+                index_count: indices.len(),
+            };
+        }
+
+        // Leave room to grow, so that following updates can reuse the buffers.
+        let mut pass = Self {
+            vertex_buffer: VertexBuffer::empty_dynamic(context, vertices.len().next_power_of_two())
+                .expect("Cannot create vertex buffer for glyphs"),
+            index_buffer: IndexBuffer::empty_dynamic(
+                context,
+                PrimitiveType::TrianglesList,
+                indices.len().next_power_of_two(),
+            )
+            .expect("Cannot create index buffer for glyphs"),
+            vertex_count: 0,
+            index_count: 0,
+        };
+        pass.write(vertices, indices);
+        pass
+    }
+
+    pub fn fits(&self, vertices: &[V], indices: &[u16]) -> bool {
+        vertices.len() <= self.vertex_buffer.len() && indices.len() <= self.index_buffer.len()
+    }
+
+    pub fn write(&mut self, vertices: &[V], indices: &[u16]) {
+        self.vertex_buffer
+            .slice(..vertices.len())
+            .expect("Cannot write vertex buffer for glyphs")
+            .write(vertices);
+        self.index_buffer
+            .slice(..indices.len())
+            .expect("Cannot write index buffer for glyphs")
+            .write(indices);
+        self.vertex_count = vertices.len();
+        self.index_count = indices.len();
+    }
+}
+
 // Widget definition and IDs --------------------------------------------------
 
-#[derive(Copy, Clone, Debug)]
+/// Opacity multiplier used by widgets to render themselves while disabled.
+pub const DISABLED_OPACITY: f32 = 0.4;
+
+/// Sharpness uniform for distance field glyphs of an atlas rendered with
+/// `atlas_font_size` and `shadow_size`, displayed at `display_size`. It keeps
+/// the antialiased edge about `user_sharpness` screen pixels wide at any size.
+pub fn antialias_sharpness(
+    user_sharpness: f32,
+    shadow_size: f32,
+    display_size: f32,
+    atlas_font_size: f32,
+) -> f32 {
+    user_sharpness / shadow_size / (display_size / atlas_font_size)
+}
+
+/// Draw parameters shared by widget shaders. By default widgets leave the
+/// destination alpha untouched. With `write_alpha` set, alpha is accumulated
+/// as `src + dst * (1 - src)` so the target ends up premultiplied and can be
+/// composited over other content.
+pub fn ui_draw_parameters<'a>(write_alpha: bool) -> DrawParameters<'a> {
+    if !write_alpha {
+        return DrawParameters {
+            blend: Blend::alpha_blending(),
+            color_mask: (true, true, true, false),
+            ..Default::default()
+        };
+    }
+
+    DrawParameters {
+        blend: Blend {
+            color: BlendingFunction::Addition {
+                source: LinearBlendingFactor::SourceAlpha,
+                destination: LinearBlendingFactor::OneMinusSourceAlpha,
+            },
+            alpha: BlendingFunction::Addition {
+                source: LinearBlendingFactor::One,
+                destination: LinearBlendingFactor::OneMinusSourceAlpha,
+            },
+            constant_value: (0.0, 0.0, 0.0, 0.0),
+        },
+        ..Default::default()
+    }
+}
+
+/// Color space widget shaders blend in. Style colors are always given in sRGB.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum UIColorSpace {
+    /// Colors are mixed as given, which darkens gradients and antialiased
+    /// edges. The default.
+    #[default]
+    Srgb,
+    /// Colors are converted to linear before mixing. `srgb_target` tells
+    /// whether the surface encodes the output to sRGB itself, otherwise the
+    /// shaders do it.
+    Linear { srgb_target: bool },
+}
+
+impl UIColorSpace {
+    /// Values of the `uLinear` and `uEncodeSrgb` uniforms of widget shaders.
+    pub fn uniforms(self) -> (bool, bool) {
+        match self {
+            UIColorSpace::Srgb => (false, false),
+            UIColorSpace::Linear { srgb_target } => (true, !srgb_target),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct UIWidgetId {
     id: usize,
 }
@@ -117,7 +410,20 @@ pub trait UIWidget {
 
     fn layout(&self, _layout: UILayout, _children: &mut [UILayout]) {}
 
-    fn render(&self, _frame: &mut Frame, _layout: UILayout, _screen: UISize) {}
+    /// Children of a widget returning `false` are skipped by the manager: they
+    /// are neither rendered nor receive input, but keep their state.
+    fn children_visible(&self) -> bool {
+        true
+    }
+
+    fn render<S: Surface>(
+        &self,
+        _frame: &mut S,
+        _layout: UILayout,
+        _screen: UISize,
+    ) -> Result<(), DrawError> {
+        Ok(())
+    }
 
     fn update_input(
         &mut self,
@@ -126,6 +432,61 @@ pub trait UIWidget {
         _events: &mut Vec<Self::Event>,
     ) {
     }
+
+    /// Called by the manager when the widget is enabled or disabled. Disabled
+    /// widgets receive no input and should render dimmed.
+    fn set_enabled(&mut self, _enabled: bool) {}
+
+    /// Whether pressing the mouse over this widget gives it keyboard focus.
+    fn focusable(&self) -> bool {
+        false
+    }
+
+    /// Checked after `update_input`. Returning `true`, e.g. while a drag is in
+    /// progress, captures the mouse: other widgets see it released and away
+    /// until the left button is released.
+    fn captures_input(&self) -> bool {
+        false
+    }
+
+    /// Receives key presses and releases while this widget has focus.
+    fn on_key(
+        &mut self,
+        _layout: UILayout,
+        _frame_input: UIFrameInput,
+        _key: UIKey,
+        _pressed: bool,
+        _events: &mut Vec<Self::Event>,
+    ) {
+    }
+
+    /// Receives the mouse wheel delta when this is the topmost widget under the
+    /// cursor. Returning `false` passes the scroll to the widget below.
+    fn on_scroll(&mut self, _layout: UILayout, _mouse_pos: UIPoint, _delta: f32) -> bool {
+        false
+    }
+
+    /// Starts a drag when the mouse is pressed at `mouse_pos` and moved past
+    /// the drag threshold. Returning `None` leaves it to the widget below.
+    fn drag_payload(&mut self, _layout: UILayout, _mouse_pos: UIPoint) -> Option<Box<dyn Any>> {
+        None
+    }
+
+    /// Called on the topmost widget under the cursor when a drag is released.
+    /// Returning `false` passes the payload to the widget below.
+    fn on_drop(&mut self, _layout: UILayout, _mouse_pos: UIPoint, _payload: &dyn Any) -> bool {
+        false
+    }
+
+    /// Draws the ghost following the cursor while this widget is dragged.
+    fn render_drag_ghost<S: Surface>(
+        &self,
+        frame: &mut S,
+        layout: UILayout,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        self.render(frame, layout, screen)
+    }
 }
 
 impl<T: UIWidget> Clone for UITypedWidgetId<T> {
@@ -155,17 +516,36 @@ trait UIWidgetData {
     fn get_layout(&self) -> UILayout;
     fn set_size(&mut self, size: UISize);
     fn get_size(&self) -> UISize;
+    fn set_visible(&mut self, visible: bool);
+    fn is_visible(&self) -> bool;
+    fn set_enabled(&mut self, enabled: bool);
+    fn is_enabled(&self) -> bool;
 
     fn measure(&self, children: &[UISize]) -> UISize;
     fn layout(&self, children: &mut [UILayout]);
-    fn render(&self, frame: &mut Frame, screen: UISize);
+    fn children_visible(&self) -> bool;
+    fn render(&self, frame: &mut UISurface, screen: UISize) -> Result<(), DrawError>;
     fn update_input(&mut self, frame_input: UIFrameInput);
+    fn scroll(&mut self, mouse_pos: UIPoint, delta: f32) -> bool;
+    fn focusable(&self) -> bool;
+    fn captures_input(&self) -> bool;
+    fn key(&mut self, frame_input: UIFrameInput, key: UIKey, pressed: bool);
+    fn drag_payload(&mut self, mouse_pos: UIPoint) -> Option<Box<dyn Any>>;
+    fn drop_payload(&mut self, mouse_pos: UIPoint, payload: &dyn Any) -> bool;
+    fn render_drag_ghost(
+        &self,
+        frame: &mut UISurface,
+        offset: UIPoint,
+        screen: UISize,
+    ) -> Result<(), DrawError>;
 }
 
 struct UITypedWidgetData<T: UIWidget> {
     layout: UILayout,
     size: UISize,
     children: Vec<UIWidgetId>,
+    visible: bool,
+    enabled: bool,
     widget: T,
     events: Vec<T::Event>,
 }
@@ -189,26 +569,99 @@ impl<T: UIWidget> UIWidgetData for UITypedWidgetData<T> {
     fn get_size(&self) -> UISize {
         self.size
     }
+    fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+    fn is_visible(&self) -> bool {
+        self.visible
+    }
+    fn set_enabled(&mut self, enabled: bool) {
+        if self.enabled != enabled {
+            self.enabled = enabled;
+            self.widget.set_enabled(enabled);
+        }
+    }
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
     fn measure(&self, children: &[UISize]) -> UISize {
         self.widget.measure(children)
     }
     fn layout(&self, children: &mut [UILayout]) {
         self.widget.layout(self.layout, children);
     }
-    fn render(&self, frame: &mut Frame, screen: UISize) {
-        self.widget.render(frame, self.layout, screen);
+    fn children_visible(&self) -> bool {
+        self.widget.children_visible()
+    }
+    fn render(&self, frame: &mut UISurface, screen: UISize) -> Result<(), DrawError> {
+        self.widget.render(frame, self.layout, screen)
     }
     fn update_input(&mut self, frame_input: UIFrameInput) {
         self.widget
             .update_input(self.layout, frame_input, &mut self.events);
     }
+    fn scroll(&mut self, mouse_pos: UIPoint, delta: f32) -> bool {
+        self.widget.on_scroll(self.layout, mouse_pos, delta)
+    }
+    fn focusable(&self) -> bool {
+        self.widget.focusable()
+    }
+    fn captures_input(&self) -> bool {
+        self.widget.captures_input()
+    }
+    fn key(&mut self, frame_input: UIFrameInput, key: UIKey, pressed: bool) {
+        self.widget
+            .on_key(self.layout, frame_input, key, pressed, &mut self.events);
+    }
+    fn drag_payload(&mut self, mouse_pos: UIPoint) -> Option<Box<dyn Any>> {
+        self.widget.drag_payload(self.layout, mouse_pos)
+    }
+    fn drop_payload(&mut self, mouse_pos: UIPoint, payload: &dyn Any) -> bool {
+        self.widget.on_drop(self.layout, mouse_pos, payload)
+    }
+    fn render_drag_ghost(
+        &self,
+        frame: &mut UISurface,
+        offset: UIPoint,
+        screen: UISize,
+    ) -> Result<(), DrawError> {
+        let layout = UILayout {
+            left: self.layout.left + offset.left,
+            top: self.layout.top + offset.top,
+            ..self.layout
+        };
+        self.widget.render_drag_ghost(frame, layout, screen)
+    }
+}
+
+struct UIDrag {
+    source: UIWidgetId,
+    start: UIPoint,
+    payload: Box<dyn Any>,
 }
 
+// Mouse position seen by widgets while another widget captures the input.
+const CAPTURED_MOUSE_POS: UIPoint = UIPoint {
+    left: -1.0e6,
+    top: -1.0e6,
+};
+
+// Distance in pixels the mouse has to travel while pressed to start a drag.
+const DRAG_THRESHOLD: f32 = 4.0;
+
 pub struct UIWidgetManager {
     screen: UISize,
     widgets: Vec<Box<dyn UIWidgetData>>,
     root: Option<UIWidgetId>,
+    pins: Vec<(UIWidgetId, Box<dyn FnMut() -> UILayout>)>,
+    order: Vec<UIWidgetId>,
     frame_input: UIFrameInput,
+    scroll_delta: Option<f32>,
+    press_start: Option<UIPoint>,
+    drag: Option<UIDrag>,
+    capture: Option<UIWidgetId>,
+    focus: Option<UIWidgetId>,
+    keys: Vec<(UIKey, bool)>,
 }
 
 impl UIWidgetManager {
@@ -217,7 +670,15 @@ impl UIWidgetManager {
             screen,
             widgets: Vec::new(),
             root: None,
+            pins: Vec::new(),
+            order: Vec::new(),
             frame_input: UIFrameInput::new(),
+            scroll_delta: None,
+            press_start: None,
+            drag: None,
+            capture: None,
+            focus: None,
+            keys: Vec::new(),
         }
     }
 
@@ -241,8 +702,92 @@ impl UIWidgetManager {
         self.frame_input.right_mouse_button_pressed = pressed;
     }
 
+    pub fn set_shift_pressed(&mut self, pressed: bool) {
+        self.frame_input.shift_pressed = pressed;
+    }
+
+    /// Queues a key press or release for the focused widget.
+    pub fn key_input(&mut self, key: UIKey, pressed: bool) {
+        self.keys.push((key, pressed));
+    }
+
+    pub fn focused(&self) -> Option<UIWidgetId> {
+        self.focus
+    }
+
+    pub fn is_focused<T: Into<UIWidgetId>>(&self, widget: T) -> bool {
+        self.focus == Some(widget.into())
+    }
+
+    /// Routes mouse input exclusively to `widget` until the left mouse button
+    /// is released. Other widgets see the button released and the cursor
+    /// outside of them, regardless of the render order. Does nothing while
+    /// another widget holds the capture.
+    pub fn capture_input<T: Into<UIWidgetId>>(&mut self, widget: T) {
+        self.capture.get_or_insert(widget.into());
+    }
+
+    pub fn release_input(&mut self) {
+        self.capture = None;
+    }
+
+    pub fn captured(&self) -> Option<UIWidgetId> {
+        self.capture
+    }
+
     pub fn set_mouse_wheel_delta(&mut self, delta: Option<f32>) {
-        self.frame_input.mouse_wheel_delta = delta;
+        self.scroll_delta = delta;
+    }
+
+    /// Hidden widgets and their children are not laid out, rendered nor updated
+    /// and take no space in their parent's layout.
+    pub fn set_visible<T: Into<UIWidgetId>>(&mut self, widget: T, visible: bool) {
+        let widget = widget.into();
+        self.widgets[widget.id].set_visible(visible);
+        if !visible && self.focus == Some(widget) {
+            self.focus = None;
+        }
+    }
+
+    pub fn is_visible<T: Into<UIWidgetId>>(&self, widget: T) -> bool {
+        self.widgets[widget.into().id].is_visible()
+    }
+
+    /// Disabled widgets are rendered dimmed and receive no input.
+    pub fn set_enabled<T: Into<UIWidgetId>>(&mut self, widget: T, enabled: bool) {
+        let widget = widget.into();
+        self.widgets[widget.id].set_enabled(enabled);
+        if !enabled && self.focus == Some(widget) {
+            self.focus = None;
+        }
+    }
+
+    pub fn is_enabled<T: Into<UIWidgetId>>(&self, widget: T) -> bool {
+        self.widgets[widget.into().id].is_enabled()
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Topmost widget containing `point`, according to the last rendered frame.
+    pub fn widget_at(&self, point: UIPoint) -> Option<UIWidgetId> {
+        self.order
+            .iter()
+            .rev()
+            .find(|widget| self.widgets[widget.id].get_layout().is_inside(point))
+            .copied()
+    }
+
+    /// Layout `widget` got in the last rendered frame, or `None` if it wasn't
+    /// laid out, e.g. because it or one of its parents is hidden.
+    pub fn get_layout<T: Into<UIWidgetId>>(&self, widget: T) -> Option<UILayout> {
+        let widget = widget.into();
+        if self.order.contains(&widget) {
+            Some(self.widgets[widget.id].get_layout())
+        } else {
+            None
+        }
     }
 
     pub fn create<T: UIWidget + 'static>(&mut self, widget: T) -> UITypedWidgetId<T> {
@@ -251,6 +796,8 @@ impl UIWidgetManager {
             layout: UILayout::zero(),
             size: UISize::zero(),
             children: Vec::new(),
+            visible: true,
+            enabled: true,
             events: Vec::new(),
             widget,
         });
@@ -288,39 +835,152 @@ impl UIWidgetManager {
         widget_data.add_child(child.into());
     }
 
-    pub fn render(&mut self, frame: &mut Frame) {
-        let mut index = 0;
-        let mut widgets = Vec::with_capacity(self.widgets.len());
+    /// Renders `widget` and its children at the layout returned by `layout`,
+    /// which is called every frame. Pinned widgets are independent of the
+    /// tree and are drawn above it, in the order they were pinned.
+    pub fn pin<T: Into<UIWidgetId>, F: FnMut() -> UILayout + 'static>(
+        &mut self,
+        widget: T,
+        layout: F,
+    ) {
+        let widget = widget.into();
+        self.unpin(widget);
+        self.pins.push((widget, Box::new(layout)));
+    }
+
+    pub fn unpin<T: Into<UIWidgetId>>(&mut self, widget: T) {
+        let widget = widget.into();
+        self.pins.retain(|(pinned, _)| *pinned != widget);
+    }
+
+    /// Lays out, updates and renders all widgets into the window frame.
+    pub fn render(&mut self, frame: &mut Frame) -> Result<(), DrawError> {
+        self.render_to(&mut UISurface::Frame(frame))
+    }
+
+    /// Lays out, updates and renders all widgets into `frame`, which may be an
+    /// offscreen framebuffer sized like the screen. Every widget is processed
+    /// even if drawing one of them fails; the first draw error is returned.
+    pub fn render_to(&mut self, frame: &mut UISurface) -> Result<(), DrawError> {
+        let mut result = Ok(());
+
+        // Scroll goes to the topmost widget under the cursor which handles it,
+        // or only to the capturing widget.
+        if let Some(delta) = self.scroll_delta.take() {
+            let mouse_pos = self.frame_input.mouse_pos;
+            for widget in self.order.iter().rev() {
+                if matches!(self.capture, Some(capture) if capture != *widget) {
+                    continue;
+                }
+                let widget_data = &mut self.widgets[widget.id];
+                if widget_data.is_enabled()
+                    && widget_data.get_layout().is_inside(mouse_pos)
+                    && widget_data.scroll(mouse_pos, delta)
+                {
+                    break;
+                }
+            }
+        }
+
+        // Pressing the mouse moves focus to the topmost focusable widget under it.
+        if self.frame_input.left_mouse_button_pressed
+            && self.press_start.is_none()
+            && self.capture.is_none()
+        {
+            let mouse_pos = self.frame_input.mouse_pos;
+            self.focus = self.order.iter().rev().copied().find(|widget| {
+                let widget_data = &self.widgets[widget.id];
+                widget_data.focusable()
+                    && widget_data.is_enabled()
+                    && widget_data.get_layout().is_inside(mouse_pos)
+            });
+        }
 
+        if let Some(focus) = self.focus {
+            let frame_input = UIFrameInput {
+                focused: true,
+                ..self.frame_input
+            };
+            for (key, pressed) in self.keys.drain(..) {
+                self.widgets[focus.id].key(frame_input, key, pressed);
+            }
+        }
+        self.keys.clear();
+
+        self.update_drag();
+
+        let mut order = Vec::with_capacity(self.widgets.len());
         if let Some(root) = self.root {
-            let widget_data = &mut self.widgets[root.id];
-            widget_data.set_layout(UILayout::from_size(self.screen));
-            widgets.push(root);
+            let layout = UILayout::from_size(self.screen);
+            result = result.and(self.render_tree(root, layout, frame, &mut order));
+        }
+
+        let mut pins = std::mem::take(&mut self.pins);
+        for (widget, layout) in &mut pins {
+            if self.widgets[widget.id].is_visible() {
+                let layout = layout();
+                result = result.and(self.render_tree(*widget, layout, frame, &mut order));
+            }
         }
+        self.pins = pins;
+        self.order = order;
+
+        // The capture outlives the frame the button is released in, so that
+        // the capturing widget sees the release.
+        if !self.frame_input.left_mouse_button_pressed {
+            self.capture = None;
+        }
+
+        if let Some(drag) = &self.drag {
+            let mouse_pos = self.frame_input.mouse_pos;
+            let offset = UIPoint {
+                left: mouse_pos.left - drag.start.left,
+                top: mouse_pos.top - drag.start.top,
+            };
+            let widget_data = &self.widgets[drag.source.id];
+            result = result.and(widget_data.render_drag_ghost(frame, offset, self.screen));
+        }
+
+        result
+    }
+
+    // Lays out, updates and renders `root` and its visible descendants, with
+    // `root` placed at `layout`. Processed widgets are appended to `order`.
+    fn render_tree(
+        &mut self,
+        root: UIWidgetId,
+        layout: UILayout,
+        frame: &mut UISurface,
+        order: &mut Vec<UIWidgetId>,
+    ) -> Result<(), DrawError> {
+        let mut result = Ok(());
+        let mut index = 0;
+        let mut widgets = vec![root];
+        self.widgets[root.id].set_layout(layout);
 
         while index < widgets.len() {
             let widget = widgets[index];
             let widget_data = &self.widgets[widget.id];
-            widgets.extend(widget_data.get_children());
+            if widget_data.children_visible() {
+                widgets.extend(self.visible_children(widget));
+            }
             index += 1;
         }
+        order.extend_from_slice(&widgets);
 
         for widget in widgets.iter().rev() {
-            let widget_data = &self.widgets[widget.id];
-            let children: Vec<UISize> = widget_data
-                .get_children()
-                .iter()
+            let children: Vec<UISize> = self
+                .visible_children(*widget)
                 .map(|child| self.widgets[child.id].get_size())
                 .collect();
 
-            let size = widget_data.measure(&children);
+            let size = self.widgets[widget.id].measure(&children);
             self.widgets[widget.id].set_size(size);
         }
 
         for widget in widgets {
-            let widget_data = &self.widgets[widget.id];
-            let mut children_layouts: Vec<UILayout> = widget_data
-                .get_children()
+            let children_ids: Vec<UIWidgetId> = self.visible_children(widget).collect();
+            let mut children_layouts: Vec<UILayout> = children_ids
                 .iter()
                 .map(|child| {
                     let child = &self.widgets[child.id];
@@ -329,17 +989,230 @@ impl UIWidgetManager {
                 })
                 .collect();
 
-            widget_data.layout(&mut children_layouts);
+            self.widgets[widget.id].layout(&mut children_layouts);
 
-            let children_ids = widget_data.get_children().to_vec();
             for (index, child) in children_ids.iter().enumerate() {
                 let child = &mut self.widgets[child.id];
                 child.set_layout(children_layouts[index]);
             }
 
+            let frame_input = match self.capture {
+                Some(capture) if capture != widget => UIFrameInput {
+                    mouse_pos: CAPTURED_MOUSE_POS,
+                    left_mouse_button_pressed: false,
+                    right_mouse_button_pressed: false,
+                    focused: self.focus == Some(widget),
+                    ..self.frame_input
+                },
+                _ => UIFrameInput {
+                    focused: self.focus == Some(widget),
+                    ..self.frame_input
+                },
+            };
+            let widget_data = &mut self.widgets[widget.id];
+            if widget_data.is_enabled() {
+                widget_data.update_input(frame_input);
+                if widget_data.captures_input() && self.frame_input.left_mouse_button_pressed {
+                    self.capture.get_or_insert(widget);
+                }
+            }
+            result = result.and(widget_data.render(frame, self.screen));
+        }
+
+        result
+    }
+
+    fn visible_children(&self, widget: UIWidgetId) -> impl Iterator<Item = UIWidgetId> + '_ {
+        self.widgets[widget.id]
+            .get_children()
+            .iter()
+            .copied()
+            .filter(move |child| self.widgets[child.id].is_visible())
+    }
+
+    // Starts, tracks and drops drags, using layouts of the previous frame.
+    fn update_drag(&mut self) {
+        let mouse_pos = self.frame_input.mouse_pos;
+
+        if !self.frame_input.left_mouse_button_pressed {
+            self.press_start = None;
+            if let Some(drag) = self.drag.take() {
+                for widget in self.order.iter().rev() {
+                    let widget_data = &mut self.widgets[widget.id];
+                    if widget_data.is_enabled()
+                        && widget_data.get_layout().is_inside(mouse_pos)
+                        && widget_data.drop_payload(mouse_pos, drag.payload.as_ref())
+                    {
+                        break;
+                    }
+                }
+            }
+            return;
+        }
+
+        let start = *self.press_start.get_or_insert(mouse_pos);
+        let distance = (mouse_pos.left - start.left).hypot(mouse_pos.top - start.top);
+        if self.drag.is_some() || self.capture.is_some() || distance < DRAG_THRESHOLD {
+            return;
+        }
+
+        for widget in self.order.iter().rev() {
             let widget_data = &mut self.widgets[widget.id];
-            widget_data.update_input(self.frame_input);
-            widget_data.render(frame, self.screen);
+            if !widget_data.is_enabled() || !widget_data.get_layout().is_inside(start) {
+                continue;
+            }
+            if let Some(payload) = widget_data.drag_payload(start) {
+                self.drag = Some(UIDrag {
+                    source: *widget,
+                    start,
+                    payload,
+                });
+                break;
+            }
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::ui::block::{UIBlock, UIBlockContext, UIBlockStyle};
+    use crate::ui::button::{UIButton, UIButtonContext};
+    use crate::ui::label::{UILabel, UILabelContext, UILabelStyle};
+    use crate::ui::layout::UIVBoxLayout;
+    use crate::ui::slider::{UISlider, UISliderContext, UISliderScale};
+    use crate::ui::text_area::{UITextArea, UITextAreaContext, UITextAreaStyle};
+    use glium::glutin::dpi::PhysicalSize;
+    use glium::glutin::event_loop::EventLoop;
+    use glium::glutin::platform::unix::EventLoopExtUnix;
+    use glium::glutin::ContextBuilder;
+    use glium::texture::Texture2d;
+    use glium::HeadlessRenderer;
+    use mcsdf::font::Font;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const WIDTH: u32 = 320;
+    const HEIGHT: u32 = 240;
+
+    // Headless GL context, or `None` when there is no display to create one
+    // on, e.g. on CI machines. The event loop has to outlive the renderer.
+    pub(crate) fn headless_renderer() -> Option<(EventLoop<()>, HeadlessRenderer)> {
+        // Loading Xlib without a display to connect to aborts debug builds.
+        std::env::var_os("DISPLAY")?;
+        let event_loop = EventLoop::new_x11_any_thread().ok()?;
+        let context = ContextBuilder::new()
+            .build_headless(&event_loop, PhysicalSize::new(WIDTH, HEIGHT))
+            .ok()?;
+        let renderer = HeadlessRenderer::new(context).ok()?;
+        Some((event_loop, renderer))
+    }
+
+    pub(crate) fn font() -> Font {
+        let data = include_bytes!("../../assets/monserat.ttf");
+        Font::new(256, 256, 24, 4, data.to_vec()).expect("Cannot load font")
+    }
+
+    // Needs an X11 display with OpenGL, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn widget_tree_renders_headless() {
+        let (_event_loop, renderer) = match headless_renderer() {
+            Some(headless) => headless,
+            None => {
+                eprintln!("Skipping headless render test: no GL context available");
+                return;
+            }
+        };
+
+        let block_context = Rc::new(UIBlockContext::new(&renderer));
+        let label_context = Rc::new(RefCell::new(UILabelContext::new(&renderer, font())));
+        let button_context = Rc::new(UIButtonContext::new(
+            block_context.clone(),
+            label_context.clone(),
+        ));
+        let slider_context = Rc::new(UISliderContext::new(
+            block_context.clone(),
+            label_context.clone(),
+        ));
+        let text_area_context = Rc::new(RefCell::new(UITextAreaContext::new(
+            &renderer,
+            block_context.clone(),
+            font(),
+        )));
+
+        let mut manager = UIWidgetManager::new(UISize {
+            width: WIDTH as f32,
+            height: HEIGHT as f32,
+        });
+        let block = manager.create(UIBlock::new(
+            block_context.clone(),
+            UIBlockStyle {
+                alpha: 1.0,
+                radius: 4.0,
+                sharpness: 1.0,
+                left_offset: 0.0,
+                left_color: [0.1, 0.1, 0.1],
+                right_offset: 0.0,
+                right_color: [0.2, 0.2, 0.2],
+                inner_shadow: 4.0,
+                shade_color: [0.0, 0.0, 0.0],
+                border_width: 1.0,
+                border_color: [0.5, 0.5, 0.5],
+                shadow_color: [0.0, 0.0, 0.0, 0.5],
+                shadow_blur: 4.0,
+                shadow_offset: [0.0, -2.0],
+            },
+        ));
+        let vbox = manager.create(UIVBoxLayout {
+            hpadding: 10.0,
+            vpadding: 5.0,
+            min_height: 20.0,
+            max_height: 60.0,
+        });
+        let label = manager.create(UILabel::new(
+            label_context.clone(),
+            "Label",
+            UILabelStyle::default(),
+        ));
+        let slider = manager.create(UISlider::new(
+            &slider_context,
+            0.0,
+            1.0,
+            0.1,
+            0.5,
+            1,
+            UISliderScale::Linear,
+        ));
+        let button = manager.create(UIButton::new(&button_context, "Button"));
+        let text_area = manager.create(UITextArea::new(
+            text_area_context.clone(),
+            "Text area",
+            UITextAreaStyle::default(),
+        ));
+
+        manager.root(block);
+        manager.add_child(block, vbox);
+        manager.add_child(vbox, label);
+        manager.add_child(vbox, slider);
+        manager.add_child(vbox, button);
+        manager.add_child(vbox, text_area);
+
+        let texture = Texture2d::empty(&renderer, WIDTH, HEIGHT).expect("Cannot create texture");
+        let mut frame_buffer =
+            SimpleFrameBuffer::new(&renderer, &texture).expect("Cannot create frame buffer");
+        frame_buffer.clear_color(1.0, 0.0, 1.0, 1.0);
+        // Glyphs are rendered on demand, so a second frame draws them too.
+        for _ in 0..2 {
+            manager
+                .render_to(&mut UISurface::FrameBuffer(&mut frame_buffer))
+                .expect("Cannot render widgets");
+        }
+        renderer.finish();
+
+        // The block covers the whole surface.
+        let pixels: Vec<Vec<(u8, u8, u8, u8)>> = texture.read();
+        let center = pixels[HEIGHT as usize / 2][WIDTH as usize / 2];
+        assert_ne!(center, (255, 0, 255, 255));
+    }
+}
@@ -17,20 +17,51 @@ pub enum Action {
     Continue,
 }
 
-pub fn start_loop<F>(event_loop: glutin::event_loop::EventLoop<()>, mut callback: F) -> !
+/// How often `start_loop` runs its callback.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FramePacing {
+    /// As soon as possible, swapping buffers is expected to wait for vsync.
+    Vsync,
+    /// At most once per given duration.
+    Fixed(Duration),
+    /// As soon as possible.
+    Uncapped,
+}
+
+/// Runs `callback` with events buffered since its last call. Events which
+/// can't outlive the event loop callback are dropped, except for
+/// `ScaleFactorChanged`, whose latest scale factor is passed separately.
+pub fn start_loop<F>(
+    event_loop: glutin::event_loop::EventLoop<()>,
+    frame_pacing: FramePacing,
+    mut callback: F,
+) -> !
 where
-    F: 'static + FnMut(&Vec<glutin::event::Event<'_, ()>>) -> Action,
+    F: 'static + FnMut(&Vec<glutin::event::Event<'_, ()>>, Option<f64>) -> Action,
 {
     let mut events_buffer = Vec::new();
+    let mut scale_factor = None;
     let mut next_frame_time = Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
+        if let glutin::event::Event::WindowEvent {
+            event:
+                glutin::event::WindowEvent::ScaleFactorChanged {
+                    scale_factor: s, ..
+                },
+            ..
+        } = event
+        {
+            scale_factor = Some(s);
+        }
+
         let run_callback = match event.to_static() {
             Some(glutin::event::Event::NewEvents(cause)) => {
                 matches!(
                     cause,
                     glutin::event::StartCause::ResumeTimeReached { .. }
                         | glutin::event::StartCause::Init
+                        | glutin::event::StartCause::Poll
                 )
             }
             Some(event) => {
@@ -44,8 +75,10 @@ where
         };
 
         let action = if run_callback {
-            let action = callback(&events_buffer);
-            next_frame_time = Instant::now() + Duration::from_nanos(16666667);
+            let action = callback(&events_buffer, scale_factor.take());
+            if let FramePacing::Fixed(frame_time) = frame_pacing {
+                next_frame_time = Instant::now() + frame_time;
+            }
             events_buffer.clear();
             action
         } else {
@@ -54,7 +87,10 @@ where
 
         match action {
             Action::Continue => {
-                *control_flow = ControlFlow::WaitUntil(next_frame_time);
+                *control_flow = match frame_pacing {
+                    FramePacing::Fixed(_) => ControlFlow::WaitUntil(next_frame_time),
+                    FramePacing::Vsync | FramePacing::Uncapped => ControlFlow::Poll,
+                };
             }
             Action::Stop => *control_flow = ControlFlow::Exit,
         }